@@ -29,7 +29,8 @@ module Oso
           end
 
           OPS = {
-            'Eq' => '=', 'In' => 'IN', 'Nin' => 'NOT IN', 'Neq' => '!='
+            'Eq' => '=', 'In' => 'IN', 'Nin' => 'NOT IN', 'Neq' => '!=',
+            'Lt' => '<', 'Leq' => '<=', 'Gt' => '>', 'Geq' => '>='
           }.freeze
 
           private
@@ -42,13 +43,33 @@ module Oso
           end
 
           def add_side(side, args)
-            if side.is_a? ::Oso::Polar::Data::Filter::Projection
+            case side
+            when ::Oso::Polar::Data::Filter::Projection
               "#{side.source.table_name}.#{side.field || side.source.primary_key}"
+            when ::Oso::Polar::Data::Filter
+              args.push build_query(side).select(side.model.primary_key)
+              '(?)'
+            when ::Oso::Polar::Data::Filter::Fixpoint
+              "(#{fixpoint(side)})"
             else
               args.push side
               '?'
             end
           end
+
+          # The records that reach a record passing the fixpoint's filter,
+          # following the relation with a recursive common table expression.
+          def fixpoint(side) # rubocop:disable Metrics/AbcSize
+            rel = side.relation
+            rec = side.filter.types[rel.left].fields[rel.name]
+            table = rel.left.table_name
+            base = build_query(side.filter).select("#{table}.#{rec.other_field}").to_sql
+            "WITH RECURSIVE closure(id) AS (#{base} UNION " \
+              "SELECT #{table}.#{rec.other_field} FROM #{table} " \
+              "INNER JOIN closure ON #{table}.#{rec.my_field} = closure.id) " \
+              "SELECT #{table}.#{rel.left.primary_key} FROM #{table} " \
+              "WHERE #{table}.#{rec.my_field} IN (SELECT id FROM closure)"
+          end
         end
       end
     end
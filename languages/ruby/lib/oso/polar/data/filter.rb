@@ -29,6 +29,10 @@ module Oso
 
         Projection = Struct.new(:source, :field)
 
+        # The records that reach a record passing `filter` through `relation`
+        # one or more times.
+        Fixpoint = Struct.new(:relation, :filter)
+
         Relation = Struct.new(:left, :name, :right) do
           def self.parse(polar, left, name, right)
             Relation.new(polar.name_to_class(left), name, polar.name_to_class(right))
@@ -48,6 +52,10 @@ module Oso
               Projection.new(polar.name_to_class(val[0]), val[1])
             when 'Immediate'
               polar.host.to_ruby('value' => [[val.keys.first, val.values.first]])
+            when 'Subquery'
+              Filter.parse(polar, val)
+            when 'Fixpoint'
+              Fixpoint.new(Relation.parse(polar, *val[0]), Filter.parse(polar, val[1]))
             else
               raise key
             end
@@ -37,6 +37,12 @@ module Oso
             [FFI::Polar, :string, :string, :string, :string],
             CResultString
           )
+          attach_function(
+            :build_data_filter_explained,
+            :polar_build_data_filter_explained,
+            [FFI::Polar, :string, :string, :string, :string],
+            CResultString
+          )
         end
         private_constant :Rust
 
@@ -66,6 +72,15 @@ module Oso
           JSON.parse plan.to_s
         end
 
+        def build_data_filter_explained(types, partials, variable, class_tag)
+          types = JSON.dump(types)
+          partials = JSON.dump(partials)
+          explained = Rust.build_data_filter_explained(self, types, partials, variable, class_tag)
+          process_messages
+          explained = check_result explained
+          JSON.parse explained.to_s
+        end
+
         # @param sources [Array<Source>]
         # @raise [FFI::Error] if the FFI call returns an error.
         def load(sources)
@@ -23,6 +23,7 @@ module Oso
           attach_function :free, :query_free, [FFI::Query], :int32
           attach_function :result_free, :result_free, [:pointer], :int32
           attach_function :bind, :polar_bind, [FFI::Query, :string, :string], CResultVoid
+          attach_function :set_fixpoints, :polar_query_set_fixpoints, [FFI::Query, :uint32], CResultVoid
         end
         private_constant :Rust
 
@@ -73,6 +74,16 @@ module Oso
           check_result res
         end
 
+        # Represent recursive relations in partial results as closures, which
+        # only `build_data_filter` understands.
+        #
+        # @param enabled [Boolean]
+        # @raise [FFI::Error] if the FFI call returns an error.
+        def set_fixpoints(enabled)
+          res = Rust.set_fixpoints(self, enabled ? 1 : 0)
+          check_result res
+        end
+
         def next_message
           check_result Rust.next_message(self)
         end
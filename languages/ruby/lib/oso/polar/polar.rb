@@ -161,7 +161,9 @@ module Oso
       #   @param query [Predicate]
       #   @return [Enumerator] of resulting bindings
       #   @raise [Error] if the FFI call raises one.
-      def query(query, host: self.host.dup, bindings: {})
+      # @param fixpoints [Boolean] whether to represent recursive relations in
+      #   partial results as closures, for data filtering.
+      def query(query, host: self.host.dup, bindings: {}, fixpoints: false)
         case query
         when String
           ffi_query = ffi_polar.new_query_from_str(query)
@@ -170,6 +172,7 @@ module Oso
         else
           raise InvalidQueryTypeError
         end
+        ffi_query.set_fixpoints(true) if fixpoints
         Query.new(ffi_query, host: host, bindings: bindings)
       end
 
@@ -179,10 +182,10 @@ module Oso
       # @param args [Array<Object>]
       # @return [Enumerator] of resulting bindings
       # @raise [Error] if the FFI call raises one.
-      def query_rule(name, *args, accept_expression: false, bindings: {})
+      def query_rule(name, *args, accept_expression: false, bindings: {}, fixpoints: false)
         host = self.host.dup
         host.accept_expression = accept_expression
-        query(Predicate.new(name, args: args), host: host, bindings: bindings)
+        query(Predicate.new(name, args: args), host: host, bindings: bindings, fixpoints: fixpoints)
       end
 
       # Query for a rule, returning true if it has any results.
@@ -248,7 +251,7 @@ module Oso
       private
 
       # new/old data filtering core API shared logic
-      def partial_query(actor, action, resource_cls) # rubocop:disable Metrics/MethodLength
+      def partial_query(actor, action, resource_cls, fixpoints: false) # rubocop:disable Metrics/MethodLength
         var_name = 'resource'
         resource = Variable.new var_name
 
@@ -258,7 +261,8 @@ module Oso
           action,
           resource,
           bindings: { var_name => type_constraint(resource, resource_cls) },
-          accept_expression: true
+          accept_expression: true,
+          fixpoints: fixpoints
         )
 
         partials.each_with_object([]) do |result, out|
@@ -269,7 +273,7 @@ module Oso
       end
 
       def new_authorized_query(actor, action, resource_class)
-        partials = partial_query(actor, action, resource_class)
+        partials = partial_query(actor, action, resource_class, fixpoints: true)
         types = host.serialize_types
         class_name = class_to_name resource_class
         plan = ffi.build_data_filter(types, partials, 'resource', class_name)
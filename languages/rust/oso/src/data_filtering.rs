@@ -0,0 +1,156 @@
+//! Data filtering: turn an `allow` rule into a query over your data source.
+//!
+//! Register the fields and relations of your classes with
+//! [`ClassBuilder::add_field`](crate::ClassBuilder::add_field) and
+//! [`ClassBuilder::add_relation`](crate::ClassBuilder::add_relation), then implement
+//! [`Adapter`] for your data source. [`Oso::authorized_query`](crate::Oso::authorized_query)
+//! and [`Oso::authorized_resources`](crate::Oso::authorized_resources) partially evaluate
+//! `allow(actor, action, resource)` and hand the resulting [`Filter`] to the adapter.
+//! [`Oso::authorized_actors`](crate::Oso::authorized_actors) does the same for the actor,
+//! with a known resource.
+
+use std::any::TypeId;
+use std::ops::Deref;
+
+use polar_core::terms::{Term, Value};
+
+use crate::host::Host;
+use crate::PolarValue;
+
+pub use polar_core::data_filtering::Types;
+pub use polar_core::filter::{self, Comparison, Condition, Datum, Projection};
+
+/// A relation from one registered class to another, used to describe
+/// fields that refer to other records (for example, a foreign key).
+///
+/// `my_field` is the field on this class and `other_field` is the field on
+/// `other_type` that must be equal for two records to be related, or, for a
+/// many-to-many relation, the fields that the join type refers to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Relation {
+    pub kind: String,
+    pub other_type: String,
+    pub my_field: String,
+    pub other_field: String,
+    pub through: Option<Through>,
+}
+
+/// The join type of a many-to-many relation, e.g., `UserGroup` for a
+/// `user_groups(user_id, group_id)` table.
+///
+/// `my_field` is the field on the join type that's equal to the relation's
+/// `my_field`, and `other_field` the one that's equal to its `other_field`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Through {
+    pub join_type: String,
+    pub my_field: String,
+    pub other_field: String,
+}
+
+impl Relation {
+    /// A relation to exactly one record of `other_type`.
+    pub fn one(other_type: &str, my_field: &str, other_field: &str) -> Self {
+        Self::new("one", other_type, my_field, other_field)
+    }
+
+    /// A relation to any number of records of `other_type`.
+    pub fn many(other_type: &str, my_field: &str, other_field: &str) -> Self {
+        Self::new("many", other_type, my_field, other_field)
+    }
+
+    /// A relation to any number of records of `other_type` through records of
+    /// `join_type`, which store `my_field` in `join_my_field` and `other_field`
+    /// in `join_other_field`.
+    pub fn many_through(
+        other_type: &str,
+        join_type: &str,
+        my_field: &str,
+        join_my_field: &str,
+        join_other_field: &str,
+        other_field: &str,
+    ) -> Self {
+        Self {
+            through: Some(Through {
+                join_type: join_type.to_owned(),
+                my_field: join_my_field.to_owned(),
+                other_field: join_other_field.to_owned(),
+            }),
+            ..Self::many(other_type, my_field, other_field)
+        }
+    }
+
+    fn new(kind: &str, other_type: &str, my_field: &str, other_field: &str) -> Self {
+        Self {
+            kind: kind.to_owned(),
+            other_type: other_type.to_owned(),
+            my_field: my_field.to_owned(),
+            other_field: other_field.to_owned(),
+            through: None,
+        }
+    }
+}
+
+/// The type of a field registered on a `Class` for data filtering.
+#[derive(Clone, Debug)]
+pub(crate) enum FieldType {
+    /// A plain field holding a value of the Rust type with this `TypeId`.
+    /// The type name is only used for error messages.
+    Base(TypeId, &'static str),
+    Relation(Relation),
+}
+
+/// A filter over records of a registered class, as produced by
+/// [`Oso::authorized_query`](crate::Oso::authorized_query).
+///
+/// Dereferences to the [`filter::Filter`] built by the Polar core. Immediate
+/// values in its conditions are Polar values; use [`Filter::to_polar_value`]
+/// to turn them back into Rust values, including application instances.
+#[derive(Clone)]
+pub struct Filter {
+    filter: filter::Filter,
+    host: Host,
+}
+
+impl Filter {
+    pub(crate) fn new(filter: filter::Filter, host: Host) -> Self {
+        Self { filter, host }
+    }
+
+    /// Convert an immediate value from one of the filter's conditions into a `PolarValue`.
+    pub fn to_polar_value(&self, value: &Value) -> crate::Result<PolarValue> {
+        PolarValue::from_term(&Term::new_temporary(value.clone()), &self.host)
+    }
+
+    /// Consume the wrapper and return the underlying core filter.
+    pub fn into_inner(self) -> filter::Filter {
+        self.filter
+    }
+}
+
+impl Deref for Filter {
+    type Target = filter::Filter;
+
+    fn deref(&self) -> &Self::Target {
+        &self.filter
+    }
+}
+
+impl std::fmt::Debug for Filter {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(fmt, "{:?}", self.filter)
+    }
+}
+
+/// Turns data filters into queries against a data source, and runs them.
+///
+/// `T` is the Rust type of the records returned by the data source.
+pub trait Adapter<T> {
+    /// The query type understood by the data source, e.g. a SQL string or an ORM query.
+    type Query;
+
+    /// Build a query from a data filter.
+    fn build_query(&self, filter: &Filter) -> crate::Result<Self::Query>;
+
+    /// Run a query built by `build_query` and return the matching records.
+    fn execute_query(&self, query: Self::Query) -> crate::Result<Vec<T>>;
+}
@@ -58,6 +58,16 @@ pub enum OsoError {
     #[error("Tried to find an instance that doesn't exist -- internal error")]
     MissingInstanceError,
 
+    /// The actor is not allowed to perform the action, and is not allowed to read
+    /// the resource either. Usually handled by returning a 404 error to the client.
+    #[error("Oso NotFoundError -- The current user does not have permission to read the given resource. You should handle this error by returning a 404 error to the client.")]
+    NotFoundError,
+
+    /// The actor is not allowed to perform the action.
+    /// Usually handled by returning a 403 error to the client.
+    #[error("Oso ForbiddenError -- The requested action was not allowed for the given resource. You should handle this error by returning a 403 error to the client.")]
+    ForbiddenError,
+
     /// TODO: replace all these with proper variants
     #[error("{message}")]
     Custom { message: String },
@@ -3,12 +3,15 @@
 use std::any::TypeId;
 use std::collections::HashMap;
 use std::fmt;
+use std::future::Future;
 use std::sync::Arc;
 
+use crate::data_filtering::{FieldType, Relation};
 use crate::errors::{InvalidCallError, OsoError};
 
 use super::class_method::{
-    AttributeGetter, ClassMethod, Constructor, InstanceMethod, RegisterHook,
+    AsyncAttributeGetter, AsyncInstanceMethod, AttributeGetter, ClassMethod, Constructor,
+    HostFuture, InstanceMethod, RegisterHook,
 };
 use super::from_polar::FromPolarList;
 use super::method::{Function, Method};
@@ -17,9 +20,12 @@ use super::Host;
 use super::PolarValue;
 
 type Attributes = HashMap<&'static str, AttributeGetter>;
+type AsyncAttributes = HashMap<&'static str, AsyncAttributeGetter>;
 type RegisterHooks = Vec<RegisterHook>;
 type ClassMethods = HashMap<&'static str, ClassMethod>;
 type InstanceMethods = HashMap<&'static str, InstanceMethod>;
+type AsyncInstanceMethods = HashMap<&'static str, AsyncInstanceMethod>;
+type Fields = HashMap<&'static str, FieldType>;
 
 fn equality_not_supported(
 ) -> Box<dyn Fn(&Host, &Instance, &Instance) -> crate::Result<bool> + Send + Sync> {
@@ -56,8 +62,14 @@ pub struct Class {
     attributes: Attributes,
     /// Instance methods on `T` that expect a list of `PolarValue`s, and an instance of `&T`
     instance_methods: InstanceMethods,
+    /// Attribute lookups on an instance of `T` that return a future
+    async_attributes: AsyncAttributes,
+    /// Instance methods on `T` that return a future
+    async_instance_methods: AsyncInstanceMethods,
     /// Class methods on `T`
     class_methods: ClassMethods,
+    /// Field types of `T`, used for data filtering
+    pub(crate) fields: Fields,
 
     /// A function that accepts arguments of this class and compares them for equality.
     /// Limitation: Only works on comparisons of the same type.
@@ -110,6 +122,24 @@ impl Class {
         }
     }
 
+    /// Error for an async method or attribute used by a synchronous query.
+    fn check_not_async(&self, name: &str, is_method: bool) -> crate::Result<()> {
+        let is_async = if is_method {
+            self.async_instance_methods.contains_key(name)
+        } else {
+            self.async_attributes.contains_key(name)
+        };
+        if is_async {
+            lazy_error!(
+                "{}.{} is async and can only be used from an async query, e.g. `Oso::is_allowed_async`",
+                self.name,
+                name
+            )
+        } else {
+            Ok(())
+        }
+    }
+
     fn equals(&self, host: &Host, lhs: &Instance, rhs: &Instance) -> crate::Result<bool> {
         // equality checking is currently only supported for exactly matching types
         // TODO: support multiple dispatch for equality
@@ -142,7 +172,10 @@ where
                 constructor: None,
                 attributes: HashMap::new(),
                 instance_methods: InstanceMethods::new(),
+                async_attributes: AsyncAttributes::new(),
+                async_instance_methods: AsyncInstanceMethods::new(),
                 class_methods: ClassMethods::new(),
+                fields: Fields::new(),
                 equality_check: Arc::from(equality_not_supported()),
                 into_iter: Arc::from(iterator_not_supported()),
                 type_id: TypeId::of::<T>(),
@@ -249,6 +282,43 @@ where
         self
     }
 
+    /// Register the type of a field for data filtering.
+    /// `class.add_field::<i64>("id")`
+    pub fn add_field<V: 'static>(mut self, name: &'static str) -> Self {
+        self.class.fields.insert(
+            name,
+            FieldType::Base(TypeId::of::<V>(), std::any::type_name::<V>()),
+        );
+        self
+    }
+
+    /// Register a field that relates this class to another class, for data filtering.
+    /// `class.add_relation("owner", Relation::one("User", "owner_id", "id"))`
+    pub fn add_relation(mut self, name: &'static str, relation: Relation) -> Self {
+        self.class
+            .fields
+            .insert(name, FieldType::Relation(relation));
+        self
+    }
+
+    /// Add an attribute getter that returns a future, for statements like `foo.bar`
+    /// that need to await application code.
+    ///
+    /// Only usable from async queries such as `Oso::is_allowed_async`.
+    /// `class.add_async_attribute_getter("bar", |instance| fetch_bar(instance.id))`
+    pub fn add_async_attribute_getter<F, Fut>(mut self, name: &'static str, f: F) -> Self
+    where
+        F: Fn(&T) -> Fut + Send + Sync + 'static,
+        Fut: Future + 'static,
+        Fut::Output: ToPolarResult,
+        T: 'static,
+    {
+        self.class
+            .async_attributes
+            .insert(name, AsyncAttributeGetter::new(f));
+        self
+    }
+
     /// Set the name of the polar class.
     pub fn name(mut self, name: &str) -> Self {
         self.class.name = name.to_string();
@@ -282,6 +352,23 @@ where
         self
     }
 
+    /// Add a method that returns a future, for polar method calls like `foo.roles()`
+    /// that need to await application code.
+    ///
+    /// Only usable from async queries such as `Oso::is_allowed_async`.
+    pub fn add_async_method<F, Args, Fut>(mut self, name: &'static str, f: F) -> Self
+    where
+        Args: FromPolarList,
+        F: Method<T, Args, Result = Fut>,
+        Fut: Future + 'static,
+        Fut::Output: ToPolarResult,
+    {
+        self.class
+            .async_instance_methods
+            .insert(name, AsyncInstanceMethod::new(f));
+        self
+    }
+
     /// A method that returns multiple values. Every element in the iterator returned by the method will
     /// be a separate polar return value.
     pub fn add_iterator_method<F, Args, I>(mut self, name: &'static str, f: F) -> Self
@@ -360,6 +447,11 @@ impl Instance {
         self.inner.as_ref().type_id()
     }
 
+    /// The Rust type name of the instance, for debugging purposes only.
+    pub(crate) fn debug_type_name(&self) -> &'static str {
+        self.debug_type_name
+    }
+
     /// Looks up the `Class` for this instance on the provided `host`
     pub fn class<'a>(&self, host: &'a Host) -> crate::Result<&'a Class> {
         host.get_class_by_type_id(self.inner.as_ref().type_id())
@@ -384,6 +476,7 @@ impl Instance {
         let attr = self
             .class(host)
             .and_then(|c| {
+                c.check_not_async(name, false)?;
                 c.attributes.get(name).ok_or_else(|| {
                     InvalidCallError::AttributeNotFound {
                         attribute_name: name.to_owned(),
@@ -407,6 +500,7 @@ impl Instance {
     ) -> crate::Result<PolarValue> {
         tracing::trace!({method = %name, ?args}, "call");
         let method = self.class(host).and_then(|c| {
+            c.check_not_async(name, true)?;
             c.get_method(name).ok_or_else(|| {
                 InvalidCallError::MethodNotFound {
                     method_name: name.to_owned(),
@@ -418,6 +512,33 @@ impl Instance {
         method.invoke(self, args, host)
     }
 
+    /// Start the named async method (when `args` is `Some`) or async attribute
+    /// getter (when `args` is `None`) via the registered `Class`.
+    ///
+    /// Returns: `None` if there is no such async method or attribute.
+    pub fn call_async(
+        &self,
+        name: &str,
+        args: Option<Vec<PolarValue>>,
+        host: &mut Host,
+    ) -> crate::Result<Option<HostFuture>> {
+        tracing::trace!({method = %name, ?args}, "call_async");
+        let class = match self.class(host) {
+            Ok(class) => class,
+            Err(_) => return Ok(None),
+        };
+        match args {
+            Some(args) => match class.async_instance_methods.get(name).cloned() {
+                Some(method) => method.invoke(self, args, host).map(Some),
+                None => Ok(None),
+            },
+            None => match class.async_attributes.get(name).cloned() {
+                Some(attr) => attr.invoke(self, host).map(Some),
+                None => Ok(None),
+            },
+        }
+    }
+
     pub fn as_iter(&self, host: &Host) -> crate::Result<crate::host::PolarIterator> {
         self.class(host).and_then(|c| (c.into_iter)(host, self))
     }
@@ -1,6 +1,9 @@
 //! Wrapper structs for the generic `Function` and `Method` traits
+use std::future::Future;
 use std::sync::Arc;
 
+use futures::future::{FutureExt, LocalBoxFuture};
+
 use crate::host::from_polar::FromPolarList;
 use crate::host::to_polar::{PolarIterator, ToPolar, ToPolarResult};
 
@@ -15,6 +18,10 @@ fn join<A, B>(left: crate::Result<A>, right: crate::Result<B>) -> crate::Result<
 type TypeErasedFunction<R> = Arc<dyn Fn(Vec<PolarValue>) -> crate::Result<R> + Send + Sync>;
 type TypeErasedMethod<R> =
     Arc<dyn Fn(&Instance, Vec<PolarValue>, &mut Host) -> crate::Result<R> + Send + Sync>;
+type TypeErasedGetter<R> = Arc<dyn Fn(&Instance, &mut Host) -> crate::Result<R> + Send + Sync>;
+
+/// The pending result of an async method or attribute getter.
+pub type HostFuture = LocalBoxFuture<'static, crate::Result<PolarValue>>;
 
 #[derive(Clone)]
 pub struct RegisterHook(Arc<dyn Fn(&mut crate::Oso) -> crate::Result<()> + Send + Sync + 'static>);
@@ -77,6 +84,34 @@ impl AttributeGetter {
     }
 }
 
+#[derive(Clone)]
+pub struct AsyncAttributeGetter(TypeErasedGetter<HostFuture>);
+
+impl AsyncAttributeGetter {
+    pub fn new<T, F, Fut>(f: F) -> Self
+    where
+        T: 'static,
+        F: Fn(&T) -> Fut + Send + Sync + 'static,
+        Fut: Future + 'static,
+        Fut::Output: ToPolarResult,
+    {
+        Self(Arc::new(move |receiver, host: &mut Host| {
+            receiver
+                .downcast(Some(host))
+                .map_err(|e| e.invariant().into())
+                .map(|receiver| {
+                    f(receiver)
+                        .map(ToPolarResult::to_polar_result)
+                        .boxed_local()
+                })
+        }))
+    }
+
+    pub fn invoke(&self, receiver: &Instance, host: &mut Host) -> crate::Result<HostFuture> {
+        self.0(receiver, host)
+    }
+}
+
 #[derive(Clone)]
 pub struct InstanceMethod(TypeErasedMethod<PolarValue>);
 
@@ -153,6 +188,45 @@ impl InstanceMethod {
     }
 }
 
+#[derive(Clone)]
+pub struct AsyncInstanceMethod(TypeErasedMethod<HostFuture>);
+
+impl AsyncInstanceMethod {
+    pub fn new<T, F, Args>(f: F) -> Self
+    where
+        Args: FromPolarList,
+        F: Method<T, Args>,
+        F::Result: Future + 'static,
+        <F::Result as Future>::Output: ToPolarResult,
+        T: 'static,
+    {
+        Self(Arc::new(
+            move |receiver: &Instance, args: Vec<PolarValue>, host: &mut Host| {
+                let receiver = receiver
+                    .downcast(Some(host))
+                    .map_err(|e| e.invariant().into());
+
+                let args = Args::from_polar_list(&args);
+
+                join(receiver, args).map(|(receiver, args)| {
+                    f.invoke(receiver, args)
+                        .map(ToPolarResult::to_polar_result)
+                        .boxed_local()
+                })
+            },
+        ))
+    }
+
+    pub fn invoke(
+        &self,
+        receiver: &Instance,
+        args: Vec<PolarValue>,
+        host: &mut Host,
+    ) -> crate::Result<HostFuture> {
+        self.0(receiver, args, host)
+    }
+}
+
 #[derive(Clone)]
 pub struct ClassMethod(TypeErasedFunction<PolarValue>);
 
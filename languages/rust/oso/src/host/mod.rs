@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
 
+use crate::data_filtering::FieldType;
 use crate::errors::OsoError;
 use crate::Polar;
 
@@ -12,10 +13,12 @@ mod to_polar;
 mod value;
 
 pub use class::{Class, ClassBuilder, Instance};
+pub use class_method::HostFuture;
 pub use from_polar::{FromPolar, FromPolarList};
+use polar_core::data_filtering::{Type, Types};
 use polar_core::terms::{Operator, Symbol};
 pub use to_polar::{PolarIterator, ToPolar, ToPolarList};
-pub use value::PolarValue;
+pub use value::{Expression, PolarValue};
 
 lazy_static::lazy_static! {
     /// Map of classes that have been globally registered
@@ -117,7 +120,7 @@ impl Host {
     pub fn register_mros(&self) -> crate::Result<()> {
         for name in self.classes.keys() {
             if name != "oso::host::Class" {
-                self.polar.register_mro(Symbol(name.clone()), vec![])?;
+                self.polar.register_mro(Symbol::new(name), vec![])?;
             }
         }
         Ok(())
@@ -185,6 +188,77 @@ impl Host {
         Ok(res)
     }
 
+    /// Starting from the class `base_tag`, follow the registered fields in `path`
+    /// and check whether the resulting class is `class_tag`.
+    pub fn isa_with_path(
+        &self,
+        base_tag: &str,
+        path: &[String],
+        class_tag: &str,
+    ) -> crate::Result<bool> {
+        let mut tag = base_tag.to_owned();
+        for field in path {
+            tag = match self.get_class(&tag)?.fields.get(field.as_str()) {
+                Some(field_type) => self.field_class_tag(field_type)?,
+                None => {
+                    return Err(crate::errors::InvalidCallError::AttributeNotFound {
+                        attribute_name: field.clone(),
+                        type_name: tag,
+                    }
+                    .into())
+                }
+            };
+        }
+        Ok(tag == class_tag)
+    }
+
+    fn field_class_tag(&self, field_type: &FieldType) -> crate::Result<String> {
+        match field_type {
+            FieldType::Base(type_id, type_name) => self
+                .class_names
+                .get(type_id)
+                .cloned()
+                .ok_or_else(|| OsoError::MissingClassError {
+                    name: type_name.to_string(),
+                }),
+            FieldType::Relation(relation) => Ok(relation.other_type.clone()),
+        }
+    }
+
+    /// Serialize the registered field types of every class, for data filtering.
+    pub fn serialize_types(&self) -> crate::Result<Types> {
+        let mut types = Types::new();
+        for (name, class) in &self.classes {
+            let mut fields = HashMap::new();
+            for (field, field_type) in &class.fields {
+                let field_type = match field_type {
+                    FieldType::Base(..) => Type::Base {
+                        class_tag: self.field_class_tag(field_type)?,
+                    },
+                    FieldType::Relation(relation) => match &relation.through {
+                        None => Type::Relation {
+                            kind: relation.kind.clone(),
+                            other_class_tag: relation.other_type.clone(),
+                            my_field: relation.my_field.clone(),
+                            other_field: relation.other_field.clone(),
+                        },
+                        Some(through) => Type::JoinRelation {
+                            other_class_tag: relation.other_type.clone(),
+                            join_class_tag: through.join_type.clone(),
+                            my_field: relation.my_field.clone(),
+                            join_my_field: through.my_field.clone(),
+                            join_other_field: through.other_field.clone(),
+                            other_field: relation.other_field.clone(),
+                        },
+                    },
+                };
+                fields.insert(field.to_string(), field_type);
+            }
+            types.insert(name.clone(), fields);
+        }
+        Ok(types)
+    }
+
     pub fn is_subspecializer(&self, _id: u64, _left_tag: &str, _right_tag: &str) -> bool {
         // Rust has no notion of inheritance, so there are no subspecializers.
         false
@@ -1,3 +1,4 @@
+use polar_core::formatting::ToPolarString;
 use polar_core::terms::*;
 use std::collections::hash_map::HashMap;
 
@@ -11,7 +12,12 @@ use crate::host::{Host, Instance};
 /// Any other types can be wrapped using `PolarValue::new_from_instance`.
 /// If the instance has a registered `Class`, then this can be used
 /// from the policy too.
+///
+/// `Expression` and `Pattern` only come back from queries over partials; see
+/// [`Oso::query_rule_partial`](crate::Oso::query_rule_partial). More variants may
+/// be added, so matches on a `PolarValue` need a wildcard arm.
 #[derive(Clone, Debug)]
+#[non_exhaustive]
 pub enum PolarValue {
     Integer(i64),
     Float(f64),
@@ -21,6 +27,15 @@ pub enum PolarValue {
     List(Vec<PolarValue>),
     Variable(String),
     Instance(Instance),
+    Expression(Expression),
+    Pattern(Pattern),
+}
+
+/// The constraints on a partial, e.g., `_this.owner = "alice"`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Expression {
+    pub operator: Operator,
+    pub args: Vec<PolarValue>,
 }
 
 impl PartialEq for PolarValue {
@@ -32,6 +47,8 @@ impl PartialEq for PolarValue {
             (PolarValue::List(l1), PolarValue::List(l2)) => l1 == l2,
             (PolarValue::Map(m1), PolarValue::Map(m2)) => m1 == m2,
             (PolarValue::String(s1), PolarValue::String(s2)) => s1 == s2,
+            (PolarValue::Expression(e1), PolarValue::Expression(e2)) => e1 == e2,
+            (PolarValue::Pattern(p1), PolarValue::Pattern(p2)) => p1 == p2,
             _ => false,
         }
     }
@@ -55,7 +72,7 @@ impl PolarValue {
             Value::Dictionary(dict) => {
                 let mut map = HashMap::new();
                 for (k, v) in &dict.fields {
-                    let key = k.0.clone();
+                    let key = k.to_string();
                     let value = PolarValue::from_term(v, host)?;
                     map.insert(key, value);
                 }
@@ -71,7 +88,20 @@ impl PolarValue {
                 }
                 PolarValue::List(list)
             }
-            Value::Variable(Symbol(sym)) => PolarValue::Variable(sym.clone()),
+            Value::Variable(sym) => PolarValue::Variable(sym.to_string()),
+            Value::Expression(Operation { operator, args }) if host.accept_expression => {
+                let mut values = vec![];
+                for arg in args {
+                    values.push(PolarValue::from_term(arg, host)?);
+                }
+                PolarValue::Expression(Expression {
+                    operator: *operator,
+                    args: values,
+                })
+            }
+            Value::Pattern(pattern) if host.accept_expression => {
+                PolarValue::Pattern(pattern.clone())
+            }
             Value::Expression(_) => {
                 return Err(crate::OsoError::Custom {
                     message: r#"
@@ -92,6 +122,19 @@ This may mean you performed an operation in your policy over an unbound variable
     }
 
     pub(crate) fn to_term(&self, host: &mut Host) -> Term {
+        self.to_term_with(&mut |instance| {
+            let repr = instance.name(host).to_owned();
+            let id = host.cache_instance(instance.clone(), None);
+            ExternalInstance {
+                constructor: None,
+                repr: Some(repr),
+                instance_id: id,
+            }
+        })
+    }
+
+    /// Convert to a term, turning instances into external instances with `instance`.
+    fn to_term_with(&self, instance: &mut impl FnMut(&Instance) -> ExternalInstance) -> Term {
         let value = match self {
             PolarValue::Integer(i) => Value::Number(Numeric::Integer(*i)),
             PolarValue::Float(f) => Value::Number(Numeric::Float(*f)),
@@ -100,29 +143,45 @@ This may mean you performed an operation in your policy over an unbound variable
             PolarValue::Map(map) => {
                 let mut dict = Dictionary::new();
                 for (k, v) in map {
-                    let key = Symbol(k.clone());
-                    let value = v.to_term(host);
+                    let key = Symbol::new(k);
+                    let value = v.to_term_with(instance);
                     dict.fields.insert(key, value);
                 }
                 Value::Dictionary(dict)
             }
-            PolarValue::Instance(instance) => {
-                let id = host.cache_instance(instance.clone(), None);
-                Value::ExternalInstance(ExternalInstance {
-                    constructor: None,
-                    repr: Some(std::any::type_name::<Self>().to_owned()),
-                    instance_id: id,
-                })
-            }
+            PolarValue::Instance(i) => Value::ExternalInstance(instance(i)),
             PolarValue::List(l) => {
                 let mut list = vec![];
                 for v in l {
-                    list.push(v.to_term(host))
+                    list.push(v.to_term_with(instance))
                 }
                 Value::List(list)
             }
-            PolarValue::Variable(s) => Value::Variable(Symbol(s.clone())),
+            PolarValue::Variable(s) => Value::Variable(Symbol::new(s)),
+            PolarValue::Expression(Expression { operator, args }) => Value::Expression(Operation {
+                operator: *operator,
+                args: args.iter().map(|arg| arg.to_term_with(instance)).collect(),
+            }),
+            PolarValue::Pattern(pattern) => Value::Pattern(pattern.clone()),
         };
         Term::new_from_ffi(value)
     }
 }
+
+impl ToPolarString for PolarValue {
+    /// Instances are shown by their Rust type names, since they may not be registered.
+    fn to_polar(&self) -> String {
+        self.to_term_with(&mut |instance| ExternalInstance {
+            constructor: None,
+            repr: Some(instance.debug_type_name().to_owned()),
+            instance_id: 0,
+        })
+        .to_polar()
+    }
+}
+
+impl ToPolarString for Expression {
+    fn to_polar(&self) -> String {
+        PolarValue::Expression(self.clone()).to_polar()
+    }
+}
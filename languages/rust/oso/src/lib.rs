@@ -75,15 +75,24 @@
 pub mod macros;
 
 pub(crate) mod builtins;
+pub mod data_filtering;
 pub mod errors;
 mod extras;
 mod host;
 mod oso;
 mod query;
 
-pub use crate::oso::{Action, Oso};
+pub use crate::oso::{Action, Explanation, Oso};
 pub use errors::{OsoError, Result};
-pub use host::{Class, ClassBuilder, FromPolar, FromPolarList, PolarValue, ToPolar, ToPolarList};
+pub use host::{
+    Class, ClassBuilder, Expression, FromPolar, FromPolarList, PolarValue, ToPolar, ToPolarList,
+};
+pub use polar_core::config::{LogSink, PolarConfig};
+pub use polar_core::formatting::ToPolarString;
+pub use polar_core::query::CancellationToken;
+pub use polar_core::rules::Rule;
+pub use polar_core::terms::{Operator, Pattern};
+pub use polar_core::traces::{ExplainKind, ExplainNode, RuleFailure};
 pub use query::{Query, ResultSet};
 
 use polar_core::polar::Polar;
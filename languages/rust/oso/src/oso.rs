@@ -1,13 +1,21 @@
 //! Communicate with the Polar virtual machine: load rules, make queries, etc/
+use polar_core::config::PolarConfig;
+use polar_core::events::ResultEvent;
+use polar_core::rules::Rule;
 use polar_core::sources::Source;
-use polar_core::terms::{Call, Symbol, Term, Value};
+use polar_core::terms::{
+    Call, Dictionary, InstanceLiteral, Operation, Operator, Pattern, Symbol, Term, Value,
+};
+use polar_core::traces::{ExplainNode, RuleFailure};
 
+use std::any::TypeId;
 use std::collections::HashSet;
 use std::fs::File;
 use std::hash::Hash;
 use std::io::Read;
 use std::sync::Arc;
 
+use crate::data_filtering::{Adapter, Filter};
 use crate::host::Host;
 use crate::query::Query;
 use crate::{FromPolar, OsoError, PolarValue, ToPolar, ToPolarList};
@@ -18,6 +26,8 @@ use crate::{FromPolar, OsoError, PolarValue, ToPolar, ToPolarList};
 pub struct Oso {
     inner: Arc<polar_core::polar::Polar>,
     host: Host,
+    /// The action used by `authorize` to decide between `NotFoundError` and `ForbiddenError`.
+    read_action: PolarValue,
 }
 
 impl Default for Oso {
@@ -46,13 +56,41 @@ impl<T: FromPolar> FromPolar for Action<T> {
     }
 }
 
+/// Why an `allow` query succeeded or failed, as returned by [`Oso::explain`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Explanation {
+    pub allowed: bool,
+    /// If allowed, the proof of the first successful `allow` rule: the rule,
+    /// the body goals that succeeded and the bindings they were run with.
+    pub proof: Option<ExplainNode>,
+    /// If denied, for each candidate `allow` rule, the failing goal that came
+    /// closest to success.
+    pub failures: Vec<RuleFailure>,
+}
+
 impl Oso {
     /// Create a new instance of Oso. Each instance is separate and can have different rules and classes loaded into it.
+    ///
+    /// Configured by the `POLAR_*` environment variables; see [`Oso::with_config`].
     pub fn new() -> Self {
-        let inner = Arc::new(polar_core::polar::Polar::new());
+        Self::with_config(PolarConfig::from_env())
+    }
+
+    /// Create a new instance of Oso configured by `config`, ignoring the `POLAR_*`
+    /// environment variables.
+    /// # Examples
+    /// ```ignore
+    /// let oso = Oso::with_config(PolarConfig::new().query_timeout_ms(0));
+    /// ```
+    pub fn with_config(config: PolarConfig) -> Self {
+        let inner = Arc::new(polar_core::polar::Polar::with_config(config));
         let host = Host::new(inner.clone());
 
-        let mut oso = Self { inner, host };
+        let mut oso = Self {
+            inner,
+            host,
+            read_action: PolarValue::String("read".to_owned()),
+        };
 
         for class in crate::builtins::classes() {
             oso.register_class(class)
@@ -75,7 +113,253 @@ impl Oso {
         Action: ToPolar,
         Resource: ToPolar,
     {
-        let mut query = self.query_rule("allow", (actor, action, resource)).unwrap();
+        self.query_rule_once("allow", (actor, action, resource))
+    }
+
+    /// Like `is_allowed`, but drives the query asynchronously so that the policy
+    /// can call async methods and attributes registered with
+    /// `ClassBuilder::add_async_method` and `ClassBuilder::add_async_attribute_getter`.
+    pub async fn is_allowed_async<Actor, Action, Resource>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+    ) -> crate::Result<bool>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+    {
+        let mut query = self.query_rule("allow", (actor, action, resource))?;
+        match query.next_result_async().await {
+            Some(Ok(_)) => Ok(true),
+            Some(Err(e)) => Err(e),
+            None => Ok(false),
+        }
+    }
+
+    /// Explain the decision `is_allowed` would make for `actor`, `action` and `resource`.
+    /// # Examples
+    /// ```ignore
+    /// let explanation = oso.explain(alice, "edit", doc)?;
+    /// for failure in explanation.failures {
+    ///     println!("{} failed at {:?}", failure.rule, failure.goal.map(|g| g.source));
+    /// }
+    /// ```
+    pub fn explain<Actor, Action, Resource>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+    ) -> crate::Result<Explanation>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+    {
+        let mut query = self.query_rule_with_options(
+            self.host.clone(),
+            "allow",
+            (actor, action, resource),
+            true,
+            self.inner.config(),
+        )?;
+        match query.next_result() {
+            Some(Ok(_)) => Ok(Explanation {
+                allowed: true,
+                proof: query.take_proof(),
+                failures: vec![],
+            }),
+            Some(Err(e)) => Err(e),
+            None => Ok(Explanation {
+                allowed: false,
+                proof: None,
+                failures: query.rule_failures(),
+            }),
+        }
+    }
+
+    /// Set the action used by `authorize` to decide whether an authorization
+    /// failure is a `NotFoundError` or a `ForbiddenError`. Defaults to `"read"`.
+    pub fn set_read_action<Action: ToPolar>(&mut self, action: Action) {
+        self.read_action = action.to_polar();
+    }
+
+    /// Ensure that `actor` is allowed to perform `action` on `resource`.
+    ///
+    /// Returns `Ok(())` if an `allow` rule permits the action. Otherwise, returns
+    /// `OsoError::NotFoundError` if the actor is not allowed to read the resource
+    /// either, and `OsoError::ForbiddenError` if it is.
+    pub fn authorize<Actor, Action, Resource>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+    ) -> crate::Result<()>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+    {
+        let (actor, action, resource) = (actor.to_polar(), action.to_polar(), resource.to_polar());
+        if self.query_rule_once("allow", (actor.clone(), action.clone(), resource.clone()))? {
+            return Ok(());
+        }
+
+        if action == self.read_action
+            || !self.query_rule_once("allow", (actor, self.read_action.clone(), resource))?
+        {
+            Err(OsoError::NotFoundError)
+        } else {
+            Err(OsoError::ForbiddenError)
+        }
+    }
+
+    /// Ensure that `actor` is allowed to send `request` to the server,
+    /// using the `allow_request` rule of the policy.
+    ///
+    /// Returns `OsoError::ForbiddenError` if the request is not allowed.
+    pub fn authorize_request<Actor, Request>(
+        &self,
+        actor: Actor,
+        request: Request,
+    ) -> crate::Result<()>
+    where
+        Actor: ToPolar,
+        Request: ToPolar,
+    {
+        if self.query_rule_once("allow_request", (actor, request))? {
+            Ok(())
+        } else {
+            Err(OsoError::ForbiddenError)
+        }
+    }
+
+    /// Ensure that `actor` is allowed to perform `action` on the `field` of `resource`,
+    /// using the `allow_field` rule of the policy.
+    ///
+    /// Returns `OsoError::ForbiddenError` if the action is not allowed.
+    pub fn authorize_field<Actor, Action, Resource, Field>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+        field: Field,
+    ) -> crate::Result<()>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+        Field: ToPolar,
+    {
+        if self.query_rule_once("allow_field", (actor, action, resource, field))? {
+            Ok(())
+        } else {
+            Err(OsoError::ForbiddenError)
+        }
+    }
+
+    /// Get the actions `actor` is allowed to take on `resource`.
+    ///
+    /// If the policy allows any action, e.g. `allow(_actor, _action, _resource)`, then
+    /// this returns `{"*"}` when `allow_wildcard` is `true`, and an error otherwise.
+    /// # Examples
+    /// ```ignore
+    /// let actions: HashSet<String> = oso.authorized_actions(actor, resource, false)?;
+    /// ```
+    pub fn authorized_actions<Actor, Resource, T>(
+        &self,
+        actor: Actor,
+        resource: Resource,
+        allow_wildcard: bool,
+    ) -> crate::Result<HashSet<T>>
+    where
+        Actor: ToPolar,
+        Resource: ToPolar,
+        T: FromPolar + Eq + Hash,
+    {
+        self.query_wildcard_set(
+            "allow",
+            (
+                actor.to_polar(),
+                PolarValue::Variable("action".to_owned()),
+                resource.to_polar(),
+            ),
+            "action",
+            allow_wildcard,
+        )
+    }
+
+    /// Get the fields of `resource` on which `actor` is allowed to perform `action`,
+    /// using the `allow_field` rule of the policy.
+    ///
+    /// If the policy allows any field, e.g. `allow_field(_actor, _action, _resource, _field)`,
+    /// then this returns `{"*"}` when `allow_wildcard` is `true`, and an error otherwise.
+    pub fn authorized_fields<Actor, Action, Resource, T>(
+        &self,
+        actor: Actor,
+        action: Action,
+        resource: Resource,
+        allow_wildcard: bool,
+    ) -> crate::Result<HashSet<T>>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: ToPolar,
+        T: FromPolar + Eq + Hash,
+    {
+        self.query_wildcard_set(
+            "allow_field",
+            (
+                actor.to_polar(),
+                action.to_polar(),
+                resource.to_polar(),
+                PolarValue::Variable("field".to_owned()),
+            ),
+            "field",
+            allow_wildcard,
+        )
+    }
+
+    /// Collect the values bound to `var` by the results of the rule query, where
+    /// an unbound `var` stands for any value.
+    fn query_wildcard_set<T>(
+        &self,
+        name: &str,
+        args: impl ToPolarList,
+        var: &str,
+        allow_wildcard: bool,
+    ) -> crate::Result<HashSet<T>>
+    where
+        T: FromPolar + Eq + Hash,
+    {
+        let mut set = HashSet::new();
+        for result in self.query_rule(name, args)? {
+            let value = match result?.get(var) {
+                Some(value) => value,
+                None => continue,
+            };
+            if let PolarValue::Variable(_) = value {
+                if !allow_wildcard {
+                    return lazy_error!(
+                        "The result of authorized_{var}s() contained an \"unconstrained\" {var} \
+                         that could represent any {var}, but allow_wildcard was set to false. \
+                         To fix, set allow_wildcard to true and compare with the \"*\" string.",
+                        var = var
+                    );
+                }
+                let mut set = HashSet::new();
+                set.insert(T::from_polar(PolarValue::String("*".to_owned()))?);
+                return Ok(set);
+            }
+            set.insert(T::from_polar(value)?);
+        }
+        Ok(set)
+    }
+
+    /// Return `true` if the rule query has at least one result.
+    fn query_rule_once(&self, name: &str, args: impl ToPolarList) -> crate::Result<bool> {
+        let mut query = self.query_rule(name, args)?;
         match query.next() {
             Some(Ok(_)) => Ok(true),
             Some(Err(e)) => Err(e),
@@ -129,6 +413,242 @@ impl Oso {
         Ok(set)
     }
 
+    /// Create a query for the resources of type `Resource` that `actor` is allowed to
+    /// perform `action` on, using `adapter` to turn the data filter into a query.
+    ///
+    /// `Resource` must be a registered class whose fields and relations have been
+    /// registered with `ClassBuilder::add_field` and `ClassBuilder::add_relation`.
+    pub fn authorized_query<Actor, Action, Resource, A>(
+        &self,
+        adapter: &A,
+        actor: Actor,
+        action: Action,
+    ) -> crate::Result<A::Query>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: 'static,
+        A: Adapter<Resource>,
+    {
+        let filter = self.build_data_filter(TypeId::of::<Resource>(), actor, action)?;
+        adapter.build_query(&filter)
+    }
+
+    /// Get the resources of type `Resource` that `actor` is allowed to perform `action` on,
+    /// using `adapter` to build and run the query.
+    /// # Examples
+    /// ```ignore
+    /// let posts: Vec<Post> = oso.authorized_resources(&adapter, user, "read")?;
+    /// ```
+    pub fn authorized_resources<Actor, Action, Resource, A>(
+        &self,
+        adapter: &A,
+        actor: Actor,
+        action: Action,
+    ) -> crate::Result<Vec<Resource>>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+        Resource: 'static,
+        A: Adapter<Resource>,
+    {
+        let query = self.authorized_query(adapter, actor, action)?;
+        adapter.execute_query(query)
+    }
+
+    /// Create a query for the actors of type `Actor` that are allowed to perform
+    /// `action` on `resource`, using `adapter` to turn the data filter into a query.
+    ///
+    /// `Actor` must be a registered class whose fields and relations have been
+    /// registered with `ClassBuilder::add_field` and `ClassBuilder::add_relation`.
+    pub fn authorized_actors_query<Action, Resource, Actor, A>(
+        &self,
+        adapter: &A,
+        action: Action,
+        resource: Resource,
+    ) -> crate::Result<A::Query>
+    where
+        Action: ToPolar,
+        Resource: ToPolar,
+        Actor: 'static,
+        A: Adapter<Actor>,
+    {
+        let actor = Symbol::new("actor");
+        let mut host = self.host.clone();
+        host.accept_expression = true;
+        let args = vec![
+            Term::new_from_ffi(Value::Variable(actor.clone())),
+            action.to_polar().to_term(&mut host),
+            resource.to_polar().to_term(&mut host),
+        ];
+        let filter = self.partial_filter(host, args, actor, TypeId::of::<Actor>())?;
+        adapter.build_query(&filter)
+    }
+
+    /// Get the actors of type `Actor` that are allowed to perform `action` on `resource`,
+    /// using `adapter` to build and run the query.
+    /// # Examples
+    /// ```ignore
+    /// let editors: Vec<User> = oso.authorized_actors(&adapter, "edit", post)?;
+    /// ```
+    pub fn authorized_actors<Action, Resource, Actor, A>(
+        &self,
+        adapter: &A,
+        action: Action,
+        resource: Resource,
+    ) -> crate::Result<Vec<Actor>>
+    where
+        Action: ToPolar,
+        Resource: ToPolar,
+        Actor: 'static,
+        A: Adapter<Actor>,
+    {
+        let query = self.authorized_actors_query(adapter, action, resource)?;
+        adapter.execute_query(query)
+    }
+
+    /// Build a data filter over the class named `class_tag` from the constraints on a
+    /// partial, e.g., the `PolarValue::Expression`s it's bound to in the results of
+    /// [`Oso::query_rule_partial`]. The partial must be constrained to be a `class_tag`.
+    pub fn data_filter(&self, partials: Vec<PolarValue>, class_tag: &str) -> crate::Result<Filter> {
+        let var = Symbol::new("_partial");
+        let mut host = self.host.clone();
+        let results = partials
+            .iter()
+            .map(|partial| {
+                let binding = (var.clone(), partial.to_term(&mut host));
+                ResultEvent::new(std::iter::once(binding).collect())
+            })
+            .collect();
+        let types = host.serialize_types()?;
+        let filter = self
+            .inner
+            .build_data_filter(types, results, &var.as_str(), class_tag)?;
+        Ok(Filter::new(filter, host))
+    }
+
+    /// Specialize the rule `name` for `args`, some of which may be partials given as
+    /// `PolarValue::Variable`s. Each result of querying `name(args)` becomes a residual
+    /// rule whose body is the constraints on the partials, so the rules can be
+    /// serialized with `ToPolarString` and loaded elsewhere.
+    pub fn residual_rules(&self, name: &str, args: impl ToPolarList) -> crate::Result<Vec<Rule>> {
+        let mut host = self.host.clone();
+        host.accept_expression = true;
+        let args: Vec<Term> = args
+            .to_polar_list()
+            .iter()
+            .map(|value| value.to_term(&mut host))
+            .collect();
+        let query_term = Term::new_from_ffi(Value::Call(Call {
+            name: Symbol::new(name),
+            args: args.clone(),
+            kwargs: None,
+        }));
+        let query = self.inner.new_query_from_term(query_term, false);
+        check_messages!(self.inner);
+
+        let mut results = vec![];
+        for result in Query::new(query, host) {
+            let bindings = result?.bindings;
+            results.push(ResultEvent::new(bindings));
+        }
+        Ok(self.inner.residual_rules(name, &args, results)?)
+    }
+
+    /// Specialize `allow(actor, action, resource)` for `actor`: the residual rules
+    /// allow the same actions on the same resources as the policy, but with the
+    /// actor's attributes already looked up, so they don't call back into the host
+    /// for the actor.
+    /// # Examples
+    /// ```ignore
+    /// let policy: Vec<String> = oso.specialize(user)?.iter().map(Rule::to_polar).collect();
+    /// ```
+    pub fn specialize<Actor: ToPolar>(&self, actor: Actor) -> crate::Result<Vec<Rule>> {
+        self.residual_rules(
+            "allow",
+            (
+                actor,
+                PolarValue::Variable("action".to_owned()),
+                PolarValue::Variable("resource".to_owned()),
+            ),
+        )
+    }
+
+    /// Partially evaluate `allow(actor, action, resource)` with `resource` constrained to
+    /// the class registered for `resource_type`, and build a data filter from the results.
+    fn build_data_filter<Actor, Action>(
+        &self,
+        resource_type: TypeId,
+        actor: Actor,
+        action: Action,
+    ) -> crate::Result<Filter>
+    where
+        Actor: ToPolar,
+        Action: ToPolar,
+    {
+        let resource = Symbol::new("resource");
+        let mut host = self.host.clone();
+        host.accept_expression = true;
+        let args = vec![
+            actor.to_polar().to_term(&mut host),
+            action.to_polar().to_term(&mut host),
+            Term::new_from_ffi(Value::Variable(resource.clone())),
+        ];
+        self.partial_filter(host, args, resource, resource_type)
+    }
+
+    /// Partially evaluate `allow(args)`, where `var` is one of the `args`, with `var`
+    /// constrained to the class registered for `class_type`, and build a data filter
+    /// for `var` from the results.
+    fn partial_filter(
+        &self,
+        host: Host,
+        args: Vec<Term>,
+        var: Symbol,
+        class_type: TypeId,
+    ) -> crate::Result<Filter> {
+        let class_tag = self.host.get_class_by_type_id(class_type)?.name.clone();
+        let query_term = Term::new_from_ffi(Value::Call(Call {
+            name: Symbol::new("allow"),
+            args,
+            kwargs: None,
+        }));
+        let mut query = self.inner.new_query_from_term(query_term, false);
+        check_messages!(self.inner);
+        query.set_fixpoints(true);
+
+        // Constrain the variable to be an instance of the requested class.
+        let isa = Term::new_from_ffi(Value::Expression(Operation {
+            operator: Operator::Isa,
+            args: vec![
+                Term::new_from_ffi(Value::Variable(var.clone())),
+                Term::new_from_ffi(Value::Pattern(Pattern::Instance(InstanceLiteral {
+                    tag: Symbol::new(&class_tag),
+                    fields: Dictionary::new(),
+                }))),
+            ],
+        }));
+        let constraint = Term::new_from_ffi(Value::Expression(Operation {
+            operator: Operator::And,
+            args: vec![isa],
+        }));
+        query.bind(var.clone(), constraint)?;
+
+        let mut query = Query::new(query, host);
+        let mut results = vec![];
+        for result in query.by_ref() {
+            let bindings = result?.bindings;
+            results.push(ResultEvent::new(bindings));
+        }
+
+        let host = query.host().clone();
+        let types = host.serialize_types()?;
+        let filter = self
+            .inner
+            .build_data_filter(types, results, &var.as_str(), &class_tag)?;
+        Ok(Filter::new(filter, host))
+    }
+
     /// Clear out all files and rules that have been loaded.
     pub fn clear_rules(&mut self) -> crate::Result<()> {
         self.inner.clear_rules();
@@ -136,8 +656,8 @@ impl Oso {
         Ok(())
     }
 
-    fn check_inline_queries(&self) -> crate::Result<()> {
-        while let Some(q) = self.inner.next_inline_query(false) {
+    fn check_inline_queries(&self, polar: &polar_core::polar::Polar) -> crate::Result<()> {
+        while let Some(q) = polar.next_inline_query(false) {
             let location = q.source_info();
             let query = Query::new(q, self.host.clone());
             match query.collect::<crate::Result<Vec<_>>>() {
@@ -146,7 +666,7 @@ impl Oso {
                 Err(e) => return lazy_error!("error in inline query: {}", e),
             }
         }
-        check_messages!(self.inner);
+        check_messages!(polar);
         Ok(())
     }
 
@@ -154,7 +674,38 @@ impl Oso {
     fn load_sources(&mut self, sources: Vec<Source>) -> crate::Result<()> {
         self.host.register_mros()?;
         self.inner.load(sources)?;
-        self.check_inline_queries()
+        self.check_inline_queries(&self.inner)
+    }
+
+    // Load Polar code into a new knowledge base and check its inline queries
+    // before swapping it in.
+    fn reload_sources(&self, sources: Vec<Source>) -> crate::Result<()> {
+        self.host.register_mros()?;
+        let staged = self.inner.load_staged(sources)?;
+        self.check_inline_queries(&staged)?;
+        self.inner.replace_kb(staged);
+        Ok(())
+    }
+
+    fn read_sources<P: AsRef<std::path::Path>>(filenames: Vec<P>) -> crate::Result<Vec<Source>> {
+        let mut sources = Vec::with_capacity(filenames.len());
+
+        for file in filenames {
+            let file = file.as_ref();
+            let filename = file.to_string_lossy().into_owned();
+            if !file.extension().map_or(false, |ext| ext == "polar") {
+                return Err(crate::OsoError::IncorrectFileType { filename });
+            }
+            let mut f = File::open(&file)?;
+            let mut src = String::new();
+            f.read_to_string(&mut src)?;
+            sources.push(Source {
+                src,
+                filename: Some(filename),
+            });
+        }
+
+        Ok(sources)
     }
 
     /// Load a file containing Polar rules. All Polar files must end in `.polar`.
@@ -175,24 +726,26 @@ impl Oso {
             return Ok(());
         }
 
-        let mut sources = Vec::with_capacity(filenames.len());
+        let sources = Self::read_sources(filenames)?;
+        self.load_sources(sources)
+    }
 
-        for file in filenames {
-            let file = file.as_ref();
-            let filename = file.to_string_lossy().into_owned();
-            if !file.extension().map_or(false, |ext| ext == "polar") {
-                return Err(crate::OsoError::IncorrectFileType { filename });
-            }
-            let mut f = File::open(&file)?;
-            let mut src = String::new();
-            f.read_to_string(&mut src)?;
-            sources.push(Source {
-                src,
-                filename: Some(filename),
-            });
+    /// Replace the loaded policy with the Polar rules in `filenames`.
+    ///
+    /// The new policy is loaded and validated on the side. If that fails, the
+    /// current policy stays in place and the error is returned. Otherwise the
+    /// new policy is swapped in at once: queries that are already running finish
+    /// against the policy they started with.
+    ///
+    /// An empty list of files is an error rather than a way to clear the policy;
+    /// use [`Oso::clear_rules`] for that.
+    pub fn reload_files<P: AsRef<std::path::Path>>(&self, filenames: Vec<P>) -> crate::Result<()> {
+        if filenames.is_empty() {
+            return lazy_error!("no files to reload the policy from");
         }
 
-        self.load_sources(sources)
+        let sources = Self::read_sources(filenames)?;
+        self.reload_sources(sources)
     }
 
     /// Load a string of polar source directly.
@@ -208,6 +761,15 @@ impl Oso {
         }])
     }
 
+    /// Replace the loaded policy with a string of polar source.
+    /// See [`Oso::reload_files`].
+    pub fn reload_str(&self, src: &str) -> crate::Result<()> {
+        self.reload_sources(vec![Source {
+            src: src.to_owned(),
+            filename: None,
+        }])
+    }
+
     /// Query the knowledge base. This can be an allow query or any other polar expression.
     /// # Examples
     /// ```ignore
@@ -228,19 +790,61 @@ impl Oso {
     /// ```
     #[must_use = "Query that is not consumed does nothing."]
     pub fn query_rule(&self, name: &str, args: impl ToPolarList) -> crate::Result<Query> {
-        let mut query_host = self.host.clone();
+        self.query_rule_with_options(self.host.clone(), name, args, false, self.inner.config())
+    }
+
+    /// Like `query_rule`, but the query's timeout, stack limit and logging
+    /// come from `config` instead of the config Oso was created with.
+    #[must_use = "Query that is not consumed does nothing."]
+    pub fn query_rule_with_config(
+        &self,
+        name: &str,
+        args: impl ToPolarList,
+        config: &PolarConfig,
+    ) -> crate::Result<Query> {
+        self.query_rule_with_options(self.host.clone(), name, args, false, config)
+    }
+
+    /// Query a rule whose arguments may include partials: unbound variables, given
+    /// as `PolarValue::Variable`s. Instead of failing, results bind each partial
+    /// to the constraints the policy puts on it, as a `PolarValue::Expression`.
+    /// # Examples
+    /// ```ignore
+    /// let partial = PolarValue::Variable("resource".to_owned());
+    /// let mut query = oso.query_rule_partial("allow", (user, "read", partial))?;
+    /// if let Some(PolarValue::Expression(constraints)) = query.next().unwrap()?.get("resource") {
+    ///     println!("{}", constraints.to_polar());
+    /// }
+    /// ```
+    #[must_use = "Query that is not consumed does nothing."]
+    pub fn query_rule_partial(&self, name: &str, args: impl ToPolarList) -> crate::Result<Query> {
+        let mut host = self.host.clone();
+        host.accept_expression = true;
+        self.query_rule_with_options(host, name, args, false, self.inner.config())
+    }
+
+    fn query_rule_with_options(
+        &self,
+        mut query_host: Host,
+        name: &str,
+        args: impl ToPolarList,
+        trace: bool,
+        config: &PolarConfig,
+    ) -> crate::Result<Query> {
         let args = args
             .to_polar_list()
             .iter()
             .map(|value| value.to_term(&mut query_host))
             .collect();
         let query_value = Value::Call(Call {
-            name: Symbol(name.to_string()),
+            name: Symbol::new(name),
             args,
             kwargs: None,
         });
         let query_term = Term::new_from_ffi(query_value);
-        let query = self.inner.new_query_from_term(query_term, false);
+        let query = self
+            .inner
+            .new_query_from_term_with_config(query_term, trace, config);
         check_messages!(self.inner);
         let query = Query::new(query, query_host);
         Ok(query)
@@ -265,10 +869,8 @@ impl Oso {
         value: V,
         name: &str,
     ) -> crate::Result<()> {
-        self.inner.register_constant(
-            Symbol(name.to_string()),
-            value.to_polar().to_term(&mut self.host),
-        )?;
+        self.inner
+            .register_constant(Symbol::new(name), value.to_polar().to_term(&mut self.host))?;
         Ok(())
     }
 }
@@ -1,12 +1,18 @@
 use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures::{ready, Stream};
 
 use crate::errors::OsoError;
-use crate::host::{Host, Instance, PolarIterator};
+use crate::host::{Host, HostFuture, Instance, PolarIterator};
 use crate::{FromPolar, PolarValue};
 
 use polar_core::events::*;
+use polar_core::query::CancellationToken;
 use polar_core::terms::*;
+use polar_core::traces::{ExplainNode, RuleFailure};
 
 impl Iterator for Query {
     type Item = crate::Result<ResultSet>;
@@ -15,10 +21,54 @@ impl Iterator for Query {
     }
 }
 
+/// Drive the query asynchronously, awaiting async methods and attributes
+/// registered with `ClassBuilder::add_async_method` and
+/// `ClassBuilder::add_async_attribute_getter`.
+impl Stream for Query {
+    type Item = crate::Result<ResultSet>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let query = self.get_mut();
+        loop {
+            if let Some((call_id, future)) = query.pending.as_mut() {
+                let (call_id, result) = (*call_id, ready!(future.as_mut().poll(cx)));
+                query.pending = None;
+                let result = query.finish_external_call(call_id, result);
+                if let Step::Yield(result) = query.handle_result(result) {
+                    return Poll::Ready(Some(result));
+                }
+            }
+            match query.step(true) {
+                Step::Continue => {}
+                Step::Yield(result) => return Poll::Ready(Some(result)),
+                Step::Done => return Poll::Ready(None),
+                Step::Await(call_id, future) => query.pending = Some((call_id, future)),
+            }
+        }
+    }
+}
+
+/// The outcome of handling a single event from the Polar VM.
+#[allow(clippy::large_enum_variant)]
+enum Step {
+    /// The event was handled; keep going.
+    Continue,
+    /// The query produced a result or an error.
+    Yield(crate::Result<ResultSet>),
+    /// The query is waiting on an async host call.
+    Await(u64, HostFuture),
+    /// The query is finished.
+    Done,
+}
+
 pub struct Query {
     inner: polar_core::query::Query,
     /// Stores a map from call_id to the iterator the call iterates through
     iterators: HashMap<u64, PolarIterator>,
+    /// The async host call the query is waiting on, if any
+    pending: Option<(u64, HostFuture)>,
+    /// Explanation of the last result, for traced queries
+    proof: Option<ExplainNode>,
     host: Host,
 }
 
@@ -26,6 +76,8 @@ impl Query {
     pub fn new(inner: polar_core::query::Query, host: Host) -> Self {
         Self {
             iterators: HashMap::new(),
+            pending: None,
+            proof: None,
             inner,
             host,
         }
@@ -35,78 +87,141 @@ impl Query {
         self.inner.source_info()
     }
 
+    /// A token that can stop this query from another thread. Once cancelled,
+    /// the query returns an error the next time it is run.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.inner.cancellation_token()
+    }
+
+    pub(crate) fn host(&self) -> &Host {
+        &self.host
+    }
+
     pub fn next_result(&mut self) -> Option<crate::Result<ResultSet>> {
         loop {
-            let event = self.inner.next()?;
-            check_messages!(self.inner);
-            if let Err(e) = event {
-                return Some(Err(e.into()));
+            match self.step(false) {
+                Step::Continue => {}
+                Step::Yield(result) => return Some(result),
+                Step::Done => return None,
+                Step::Await(..) => unreachable!("synchronous queries never await host calls"),
             }
-            let event = event.unwrap();
-            tracing::debug!(event=?event);
-            let result = match event {
-                QueryEvent::None => Ok(()),
-                QueryEvent::Done { .. } => return None,
-                QueryEvent::Result { bindings, .. } => {
-                    return Some(ResultSet::from_bindings(bindings, self.host.clone()));
-                }
-                QueryEvent::MakeExternal {
-                    instance_id,
-                    constructor,
-                } => self.handle_make_external(instance_id, constructor),
-                QueryEvent::NextExternal { call_id, iterable } => {
-                    self.handle_next_external(call_id, iterable)
-                }
-                QueryEvent::ExternalCall {
-                    call_id,
-                    instance,
-                    attribute,
-                    args,
-                    kwargs,
-                } => self.handle_external_call(call_id, instance, attribute, args, kwargs),
-                QueryEvent::ExternalOp {
-                    call_id,
-                    operator,
-                    args,
-                } => self.handle_external_op(call_id, operator, args),
-                QueryEvent::ExternalIsa {
-                    call_id,
-                    instance,
-                    class_tag,
-                } => self.handle_external_isa(call_id, instance, class_tag),
-                QueryEvent::ExternalIsSubSpecializer {
-                    call_id,
-                    instance_id,
-                    left_class_tag,
-                    right_class_tag,
-                } => self.handle_external_is_subspecializer(
-                    call_id,
-                    instance_id,
-                    left_class_tag,
-                    right_class_tag,
-                ),
-                QueryEvent::Debug { message } => self.handle_debug(message),
-                QueryEvent::ExternalIsSubclass {
-                    call_id,
-                    left_class_tag,
-                    right_class_tag,
-                } => self.handle_external_is_subclass(call_id, left_class_tag, right_class_tag),
-                event => unimplemented!("Unhandled event {:?}", event),
-            };
-
-            match result {
-                // Only call errors get passed back.
-                Err(call_error @ OsoError::InvalidCallError { .. }) => {
-                    tracing::error!("application invalid call error {}", call_error);
-                    if let Err(e) = self.application_error(call_error) {
-                        return Some(Err(e));
-                    }
+        }
+    }
+
+    /// Explanation of the last result of a traced query.
+    pub(crate) fn take_proof(&mut self) -> Option<ExplainNode> {
+        self.proof.take()
+    }
+
+    /// The nearest failure of each rule tried by a traced query.
+    pub(crate) fn rule_failures(&self) -> Vec<RuleFailure> {
+        self.inner.rule_failures()
+    }
+
+    /// Get the next result asynchronously. Unlike `next_result`, this can call
+    /// async methods and attributes. Equivalent to `StreamExt::next`.
+    pub async fn next_result_async(&mut self) -> Option<crate::Result<ResultSet>> {
+        futures::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+
+    /// Handle the next event from the Polar VM.
+    ///
+    /// If `allow_async` is set, external calls to async methods and attributes
+    /// are started and returned as `Step::Await` instead of failing.
+    fn step(&mut self, allow_async: bool) -> Step {
+        let event = match self.inner.next() {
+            Some(event) => event,
+            None => return Step::Done,
+        };
+        check_messages!(self.inner);
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => return Step::Yield(Err(e.into())),
+        };
+        tracing::debug!(event=?event);
+        let result = match event {
+            QueryEvent::None => Ok(()),
+            QueryEvent::Done { .. } => return Step::Done,
+            QueryEvent::Result { bindings, trace } => {
+                self.proof = trace.and_then(|trace| self.inner.explain_result(&trace.trace));
+                return Step::Yield(ResultSet::from_bindings(bindings, self.host.clone()));
+            }
+            QueryEvent::MakeExternal {
+                instance_id,
+                constructor,
+            } => self.handle_make_external(instance_id, constructor),
+            QueryEvent::NextExternal { call_id, iterable } => {
+                self.handle_next_external(call_id, iterable)
+            }
+            QueryEvent::ExternalCall {
+                call_id,
+                instance,
+                attribute,
+                args,
+                kwargs,
+            } => match self.handle_external_call(
+                call_id,
+                instance,
+                attribute,
+                args,
+                kwargs,
+                allow_async,
+            ) {
+                Ok(Some(future)) => return Step::Await(call_id, future),
+                result => result.map(|_| ()),
+            },
+            QueryEvent::ExternalOp {
+                call_id,
+                operator,
+                args,
+            } => self.handle_external_op(call_id, operator, args),
+            QueryEvent::ExternalIsa {
+                call_id,
+                instance,
+                class_tag,
+            } => self.handle_external_isa(call_id, instance, class_tag),
+            QueryEvent::ExternalIsaWithPath {
+                call_id,
+                base_tag,
+                path,
+                class_tag,
+            } => self.handle_external_isa_with_path(call_id, base_tag, path, class_tag),
+            QueryEvent::ExternalIsSubSpecializer {
+                call_id,
+                instance_id,
+                left_class_tag,
+                right_class_tag,
+            } => self.handle_external_is_subspecializer(
+                call_id,
+                instance_id,
+                left_class_tag,
+                right_class_tag,
+            ),
+            QueryEvent::Debug { message } => self.handle_debug(message),
+            QueryEvent::ExternalIsSubclass {
+                call_id,
+                left_class_tag,
+                right_class_tag,
+            } => self.handle_external_is_subclass(call_id, left_class_tag, right_class_tag),
+            event => unimplemented!("Unhandled event {:?}", event),
+        };
+        self.handle_result(result)
+    }
+
+    fn handle_result(&mut self, result: crate::Result<()>) -> Step {
+        match result {
+            // Only call errors get passed back.
+            Err(call_error @ OsoError::InvalidCallError { .. }) => {
+                tracing::error!("application invalid call error {}", call_error);
+                match self.application_error(call_error) {
+                    Ok(()) => Step::Continue,
+                    Err(e) => Step::Yield(Err(e)),
                 }
-                // All others get returned.
-                Err(err) => return Some(Err(err)),
-                // Continue on ok
-                Ok(_) => {}
             }
+            // All others get returned.
+            Err(err) => Step::Yield(Err(err)),
+            // Continue on ok
+            Ok(_) => Step::Continue,
         }
     }
 
@@ -144,7 +259,7 @@ impl Query {
                         .iter()
                         .map(|term| PolarValue::from_term(term, &self.host))
                         .collect::<crate::Result<Vec<PolarValue>>>()?;
-                    self.host.make_instance(&name.0, args, instance_id)
+                    self.host.make_instance(&name.as_str(), args, instance_id)
                 }
             }
             _ => lazy_error!("invalid type for constructing an instance -- internal error"),
@@ -173,6 +288,8 @@ impl Query {
         }
     }
 
+    /// Make an external call. When `allow_async` is set and the call is to an
+    /// async method or attribute, returns the future instead of answering the call.
     fn handle_external_call(
         &mut self,
         call_id: u64,
@@ -180,21 +297,44 @@ impl Query {
         name: Symbol,
         args: Option<Vec<Term>>,
         kwargs: Option<BTreeMap<Symbol, Term>>,
-    ) -> crate::Result<()> {
+        allow_async: bool,
+    ) -> crate::Result<Option<HostFuture>> {
         if kwargs.is_some() {
             return lazy_error!("Invalid call error: kwargs not supported in Rust.");
         }
         tracing::trace!(call_id, name = %name, args = ?args, "call");
         let instance = Instance::from_polar(PolarValue::from_term(&instance, &self.host)?)?;
+        let args = args
+            .map(|args| {
+                args.iter()
+                    .map(|v| PolarValue::from_term(v, &self.host))
+                    .collect::<crate::Result<Vec<PolarValue>>>()
+            })
+            .transpose()?;
+        if allow_async {
+            match instance.call_async(&name.as_str(), args.clone(), &mut self.host) {
+                Ok(Some(future)) => return Ok(Some(future)),
+                Ok(None) => {}
+                Err(e) => {
+                    self.call_result_none(call_id)?;
+                    return Err(e);
+                }
+            }
+        }
         let result = if let Some(args) = args {
-            let args = args
-                .iter()
-                .map(|v| PolarValue::from_term(v, &self.host))
-                .collect::<crate::Result<Vec<PolarValue>>>()?;
-            instance.call(&name.0, args, &mut self.host)
+            instance.call(&name.as_str(), args, &mut self.host)
         } else {
-            instance.get_attr(&name.0, &mut self.host)
+            instance.get_attr(&name.as_str(), &mut self.host)
         };
+        self.finish_external_call(call_id, result).map(|_| None)
+    }
+
+    /// Answer an external call with its `result`.
+    fn finish_external_call(
+        &mut self,
+        call_id: u64,
+        result: crate::Result<PolarValue>,
+    ) -> crate::Result<()> {
         match result {
             Ok(t) => self.call_result(call_id, t),
             Err(e) => {
@@ -229,13 +369,39 @@ impl Query {
         class_tag: Symbol,
     ) -> crate::Result<()> {
         tracing::debug!(instance = ?instance, class = %class_tag, "isa");
-        let res = self
-            .host
-            .isa(PolarValue::from_term(&instance, &self.host)?, &class_tag.0)?;
+        let res = self.host.isa(
+            PolarValue::from_term(&instance, &self.host)?,
+            &class_tag.as_str(),
+        )?;
         self.question_result(call_id, res)?;
         Ok(())
     }
 
+    fn handle_external_isa_with_path(
+        &mut self,
+        call_id: u64,
+        base_tag: Symbol,
+        path: Vec<Term>,
+        class_tag: Symbol,
+    ) -> crate::Result<()> {
+        tracing::debug!(base = %base_tag, path = ?path, class = %class_tag, "isa_with_path");
+        let res = path
+            .iter()
+            .map(|field| String::from_polar(PolarValue::from_term(field, &self.host)?))
+            .collect::<crate::Result<Vec<_>>>()
+            .and_then(|path| {
+                self.host
+                    .isa_with_path(&base_tag.as_str(), &path, &class_tag.as_str())
+            });
+        match res {
+            Ok(res) => self.question_result(call_id, res),
+            Err(e) => {
+                self.question_result(call_id, false)?;
+                Err(e)
+            }
+        }
+    }
+
     fn handle_external_is_subspecializer(
         &mut self,
         call_id: u64,
@@ -243,9 +409,11 @@ impl Query {
         left_class_tag: Symbol,
         right_class_tag: Symbol,
     ) -> crate::Result<()> {
-        let res = self
-            .host
-            .is_subspecializer(instance_id, &left_class_tag.0, &right_class_tag.0);
+        let res = self.host.is_subspecializer(
+            instance_id,
+            &left_class_tag.as_str(),
+            &right_class_tag.as_str(),
+        );
         self.question_result(call_id, res)?;
         Ok(())
     }
@@ -271,7 +439,9 @@ impl Query {
 
 #[derive(Clone)]
 pub struct ResultSet {
-    bindings: polar_core::kb::Bindings,
+    pub(crate) bindings: polar_core::kb::Bindings,
+    /// The names of the variables in `bindings`, in its order.
+    names: Vec<String>,
     host: crate::host::Host,
 }
 
@@ -294,16 +464,26 @@ This may mean you performed an operation in your policy over an unbound variable
             }
         }
 
-        Ok(Self { bindings, host })
+        let names = bindings.keys().map(ToString::to_string).collect();
+        Ok(Self {
+            bindings,
+            names,
+            host,
+        })
     }
 
     /// Return the keys in bindings.
     pub fn keys(&self) -> Box<dyn std::iter::Iterator<Item = &str> + '_> {
-        Box::new(self.bindings.keys().map(|sym| sym.0.as_ref()))
+        Box::new(self.names.iter().map(String::as_str))
     }
 
     pub fn iter_bindings(&self) -> Box<dyn std::iter::Iterator<Item = (&str, &Value)> + '_> {
-        Box::new(self.bindings.iter().map(|(k, v)| (k.0.as_ref(), v.value())))
+        Box::new(
+            self.names
+                .iter()
+                .zip(self.bindings.values())
+                .map(|(k, v)| (k.as_str(), v.value())),
+        )
     }
 
     pub fn is_empty(&self) -> bool {
@@ -312,7 +492,7 @@ This may mean you performed an operation in your policy over an unbound variable
 
     pub fn get(&self, name: &str) -> Option<crate::PolarValue> {
         self.bindings
-            .get(&Symbol(name.to_string()))
+            .get(&Symbol::new(name))
             .map(|t| PolarValue::from_term(t, &self.host).unwrap())
     }
 
@@ -0,0 +1,116 @@
+use futures::executor::block_on;
+use futures::StreamExt;
+use oso::{Oso, PolarClass};
+
+#[derive(PolarClass, Debug, Clone, PartialEq)]
+struct User {
+    #[polar(attribute)]
+    name: String,
+}
+
+#[derive(PolarClass, Debug, Clone, PartialEq)]
+struct Document {
+    #[polar(attribute)]
+    id: i64,
+}
+
+async fn fetch_owner(id: i64) -> String {
+    futures::future::ready(if id == 1 { "alice" } else { "bob" }.to_owned()).await
+}
+
+fn test_oso() -> Oso {
+    let mut oso = Oso::new();
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.register_class(
+        Document::get_polar_class_builder()
+            .add_async_attribute_getter("owner", |doc: &Document| fetch_owner(doc.id))
+            .add_async_method("is_owned_by", |doc: &Document, name: String| {
+                let id = doc.id;
+                async move { fetch_owner(id).await == name }
+            })
+            .build(),
+    )
+    .unwrap();
+    oso
+}
+
+#[test]
+fn test_async_attribute() -> oso::Result<()> {
+    let mut oso = test_oso();
+    oso.load_str(r#"allow(user: User, "read", doc: Document) if doc.owner = user.name;"#)?;
+
+    let alice = User {
+        name: "alice".to_owned(),
+    };
+    assert!(block_on(oso.is_allowed_async(
+        alice.clone(),
+        "read",
+        Document { id: 1 }
+    ))?);
+    assert!(!block_on(oso.is_allowed_async(
+        alice,
+        "read",
+        Document { id: 2 }
+    ))?);
+    Ok(())
+}
+
+#[test]
+fn test_async_method() -> oso::Result<()> {
+    let mut oso = test_oso();
+    oso.load_str(r#"allow(user: User, "read", doc: Document) if doc.is_owned_by(user.name);"#)?;
+
+    let bob = User {
+        name: "bob".to_owned(),
+    };
+    assert!(!block_on(oso.is_allowed_async(
+        bob.clone(),
+        "read",
+        Document { id: 1 }
+    ))?);
+    assert!(block_on(oso.is_allowed_async(
+        bob,
+        "read",
+        Document { id: 2 }
+    ))?);
+    Ok(())
+}
+
+#[test]
+fn test_async_query_stream() -> oso::Result<()> {
+    let mut oso = test_oso();
+    oso.load_str(r#"owner(doc: Document, name) if name = doc.owner;"#)?;
+
+    let query = oso.query_rule(
+        "owner",
+        (
+            Document { id: 2 },
+            oso::PolarValue::Variable("name".to_owned()),
+        ),
+    )?;
+    let results: Vec<_> = block_on(StreamExt::collect(query));
+    assert_eq!(results.len(), 1);
+    let name: String = results.into_iter().next().unwrap()?.get_typed("name")?;
+    assert_eq!(name, "bob");
+    Ok(())
+}
+
+#[test]
+fn test_async_method_from_sync_query() -> oso::Result<()> {
+    let mut oso = test_oso();
+    oso.load_str(r#"allow(user: User, "read", doc: Document) if doc.owner = user.name;"#)?;
+
+    let alice = User {
+        name: "alice".to_owned(),
+    };
+    let err = oso
+        .is_allowed(alice, "read", Document { id: 1 })
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("Document.owner is async and can only be used from an async query"),
+        "{}",
+        err
+    );
+    Ok(())
+}
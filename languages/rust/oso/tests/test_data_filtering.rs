@@ -0,0 +1,584 @@
+use oso::data_filtering::{
+    filter, Adapter, Comparison, Condition, Datum, Filter, Projection, Relation,
+};
+use oso::{Operator, Oso, PolarClass, PolarValue, ToPolarString};
+
+#[derive(PolarClass, Debug, Clone, PartialEq)]
+struct User {
+    #[polar(attribute)]
+    name: String,
+}
+
+#[derive(PolarClass, Debug, Clone, PartialEq)]
+struct Blog {
+    #[polar(attribute)]
+    id: i64,
+    #[polar(attribute)]
+    is_featured: bool,
+}
+
+#[derive(PolarClass, Debug, Clone, PartialEq)]
+struct Tag {
+    #[polar(attribute)]
+    id: i64,
+    #[polar(attribute)]
+    name: String,
+}
+
+#[derive(PolarClass, Debug, Clone, PartialEq)]
+struct Post {
+    #[polar(attribute)]
+    id: i64,
+    #[polar(attribute)]
+    blog_id: i64,
+    #[polar(attribute)]
+    is_published: bool,
+    #[polar(attribute)]
+    owner: String,
+}
+
+/// A toy adapter that evaluates filters over in-memory vectors.
+struct VecAdapter {
+    posts: Vec<Post>,
+    blogs: Vec<Blog>,
+}
+
+impl VecAdapter {
+    fn new() -> Self {
+        let blogs = vec![
+            Blog {
+                id: 1,
+                is_featured: true,
+            },
+            Blog {
+                id: 2,
+                is_featured: false,
+            },
+        ];
+        let posts = vec![
+            Post {
+                id: 1,
+                blog_id: 1,
+                is_published: false,
+                owner: "alice".to_owned(),
+            },
+            Post {
+                id: 2,
+                blog_id: 2,
+                is_published: true,
+                owner: "bob".to_owned(),
+            },
+            Post {
+                id: 3,
+                blog_id: 2,
+                is_published: false,
+                owner: "alice".to_owned(),
+            },
+        ];
+        Self { posts, blogs }
+    }
+
+    fn field(&self, post: &Post, projection: &Projection) -> PolarValue {
+        let Projection(typ, field) = projection;
+        let blog = self
+            .blogs
+            .iter()
+            .find(|blog| blog.id == post.blog_id)
+            .unwrap();
+        match (typ.as_str(), field.as_deref()) {
+            ("Post", None) | ("Post", Some("id")) => PolarValue::Integer(post.id),
+            ("Post", Some("is_published")) => PolarValue::Boolean(post.is_published),
+            ("Post", Some("owner")) => PolarValue::String(post.owner.clone()),
+            ("Blog", Some("id")) => PolarValue::Integer(blog.id),
+            ("Blog", Some("is_featured")) => PolarValue::Boolean(blog.is_featured),
+            _ => panic!("unexpected projection {:?}", projection),
+        }
+    }
+
+    fn datum(&self, filter: &Filter, post: &Post, datum: &Datum) -> PolarValue {
+        match datum {
+            Datum::Field(projection) => self.field(post, projection),
+            Datum::Immediate(value) => filter.to_polar_value(value).unwrap(),
+            Datum::Subquery(subquery) => PolarValue::List(
+                self.posts
+                    .iter()
+                    .filter(|post| self.matches(filter, subquery, post))
+                    .map(|post| PolarValue::Integer(post.id))
+                    .collect(),
+            ),
+            // Posts aren't related to other posts.
+            Datum::Fixpoint(..) => panic!("unexpected fixpoint {}", datum),
+        }
+    }
+
+    /// Whether `post` passes `conditions`, which is `filter` or one of its sub-filters.
+    fn matches(&self, filter: &Filter, conditions: &filter::Filter, post: &Post) -> bool {
+        conditions.conditions.iter().any(|conjunction| {
+            conjunction.iter().all(|Condition(left, cmp, right)| {
+                let (left, right) = (
+                    self.datum(filter, post, left),
+                    self.datum(filter, post, right),
+                );
+                match (cmp, left, right) {
+                    (Comparison::Eq, left, right) => left == right,
+                    (Comparison::Neq, left, right) => left != right,
+                    (Comparison::In, left, PolarValue::List(list)) => list.contains(&left),
+                    (Comparison::Nin, left, PolarValue::List(list)) => !list.contains(&left),
+                    (Comparison::Lt, PolarValue::Integer(l), PolarValue::Integer(r)) => l < r,
+                    (Comparison::Leq, PolarValue::Integer(l), PolarValue::Integer(r)) => l <= r,
+                    (Comparison::Gt, PolarValue::Integer(l), PolarValue::Integer(r)) => l > r,
+                    (Comparison::Geq, PolarValue::Integer(l), PolarValue::Integer(r)) => l >= r,
+                    _ => false,
+                }
+            })
+        })
+    }
+}
+
+impl Adapter<Post> for VecAdapter {
+    type Query = Vec<Post>;
+
+    fn build_query(&self, filter: &Filter) -> oso::Result<Vec<Post>> {
+        assert_eq!(filter.root, "Post");
+        Ok(self
+            .posts
+            .iter()
+            .filter(|post| self.matches(filter, filter, post))
+            .cloned()
+            .collect())
+    }
+
+    fn execute_query(&self, query: Vec<Post>) -> oso::Result<Vec<Post>> {
+        Ok(query)
+    }
+}
+
+/// A toy adapter that evaluates filters over users.
+struct UserAdapter {
+    users: Vec<User>,
+}
+
+impl UserAdapter {
+    fn new() -> Self {
+        let users = ["alice", "bob", "admin"]
+            .iter()
+            .map(|name| User {
+                name: name.to_string(),
+            })
+            .collect();
+        Self { users }
+    }
+
+    fn datum(filter: &Filter, user: &User, datum: &Datum) -> PolarValue {
+        match datum {
+            Datum::Field(Projection(typ, Some(field))) if typ == "User" && field == "name" => {
+                PolarValue::String(user.name.clone())
+            }
+            Datum::Immediate(value) => filter.to_polar_value(value).unwrap(),
+            _ => panic!("unexpected datum {}", datum),
+        }
+    }
+}
+
+impl Adapter<User> for UserAdapter {
+    type Query = Vec<User>;
+
+    fn build_query(&self, filter: &Filter) -> oso::Result<Vec<User>> {
+        assert_eq!(filter.root, "User");
+        Ok(self
+            .users
+            .iter()
+            .filter(|user| {
+                filter.conditions.iter().any(|conjunction| {
+                    conjunction.iter().all(|Condition(left, cmp, right)| {
+                        let left = Self::datum(filter, user, left);
+                        let right = Self::datum(filter, user, right);
+                        match (cmp, right) {
+                            (Comparison::Eq, right) => left == right,
+                            (Comparison::Neq, right) => left != right,
+                            (Comparison::In, PolarValue::List(list)) => list.contains(&left),
+                            _ => panic!("unexpected comparison {}", cmp),
+                        }
+                    })
+                })
+            })
+            .cloned()
+            .collect())
+    }
+
+    fn execute_query(&self, query: Vec<User>) -> oso::Result<Vec<User>> {
+        Ok(query)
+    }
+}
+
+/// An adapter whose queries are the filters themselves.
+struct FilterAdapter;
+
+impl Adapter<Post> for FilterAdapter {
+    type Query = filter::Filter;
+
+    fn build_query(&self, filter: &Filter) -> oso::Result<filter::Filter> {
+        Ok((**filter).clone())
+    }
+
+    fn execute_query(&self, _: filter::Filter) -> oso::Result<Vec<Post>> {
+        Err(oso::OsoError::Custom {
+            message: "FilterAdapter only builds queries".to_owned(),
+        })
+    }
+}
+
+fn test_oso() -> Oso {
+    let mut oso = Oso::new();
+    oso.register_class(
+        User::get_polar_class_builder()
+            .add_field::<String>("name")
+            .build(),
+    )
+    .unwrap();
+    oso.register_class(
+        Blog::get_polar_class_builder()
+            .add_field::<i64>("id")
+            .add_field::<bool>("is_featured")
+            .build(),
+    )
+    .unwrap();
+    oso.register_class(
+        Post::get_polar_class_builder()
+            .add_field::<i64>("id")
+            .add_field::<bool>("is_published")
+            .add_field::<String>("owner")
+            .add_relation("blog", Relation::one("Blog", "blog_id", "id"))
+            .add_relation(
+                "tags",
+                Relation::many_through("Tag", "PostTag", "id", "post_id", "tag_id", "id"),
+            )
+            .build(),
+    )
+    .unwrap();
+    oso.register_class(
+        Tag::get_polar_class_builder()
+            .add_field::<i64>("id")
+            .add_field::<String>("name")
+            .build(),
+    )
+    .unwrap();
+    oso
+}
+
+fn ids(posts: Vec<Post>) -> Vec<i64> {
+    let mut ids: Vec<_> = posts.into_iter().map(|post| post.id).collect();
+    ids.sort_unstable();
+    ids
+}
+
+#[test]
+fn test_authorized_resources_field() -> oso::Result<()> {
+    let mut oso = test_oso();
+    oso.load_str(r#"allow(_: User, "get", post: Post) if post.is_published = true;"#)?;
+
+    let user = User {
+        name: "alice".to_owned(),
+    };
+    let posts: Vec<Post> = oso.authorized_resources(&VecAdapter::new(), user, "get")?;
+    assert_eq!(ids(posts), vec![2]);
+    Ok(())
+}
+
+#[test]
+fn test_authorized_resources_actor_attribute() -> oso::Result<()> {
+    let mut oso = test_oso();
+    oso.load_str(r#"allow(user: User, "get", post: Post) if post.owner = user.name;"#)?;
+
+    let user = User {
+        name: "alice".to_owned(),
+    };
+    let posts: Vec<Post> = oso.authorized_resources(&VecAdapter::new(), user, "get")?;
+    assert_eq!(ids(posts), vec![1, 3]);
+    Ok(())
+}
+
+#[test]
+fn test_authorized_resources_relation() -> oso::Result<()> {
+    let mut oso = test_oso();
+    oso.load_str(
+        r#"allow(_: User, "get", post: Post) if post.blog.is_featured = true;
+           allow(_: User, "get", post: Post) if post.id = 3;"#,
+    )?;
+
+    let user = User {
+        name: "alice".to_owned(),
+    };
+    let adapter = VecAdapter::new();
+    let posts: Vec<Post> = oso.authorized_resources(&adapter, user.clone(), "get")?;
+    assert_eq!(ids(posts), vec![1, 3]);
+
+    let posts: Vec<Post> = oso.authorized_resources(&adapter, user, "delete")?;
+    assert!(posts.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_authorized_resources_comparisons() -> oso::Result<()> {
+    let mut oso = test_oso();
+    oso.load_str(
+        r#"allow(_: User, "get", post: Post) if post.id > 1 and post.blog.id <= 2;
+           allow(_: User, "delete", post: Post) if post.id >= 3 and post.id < 4;
+           allow(_: User, "edit", post: Post) if post.id > 1 and post.id >= 3 and post.id != 5;
+           allow(_: User, "edit", post: Post) if post.id > 3 and post.id < 2;"#,
+    )?;
+
+    let user = User {
+        name: "alice".to_owned(),
+    };
+    let adapter = VecAdapter::new();
+    let posts: Vec<Post> = oso.authorized_resources(&adapter, user.clone(), "get")?;
+    assert_eq!(ids(posts), vec![2, 3]);
+
+    let posts: Vec<Post> = oso.authorized_resources(&adapter, user.clone(), "delete")?;
+    assert_eq!(ids(posts), vec![3]);
+
+    // Bounds on the same field are merged, and unsatisfiable rules are dropped.
+    let filter = oso.authorized_query(&FilterAdapter, user, "edit")?;
+    let conditions: Vec<Vec<String>> = filter
+        .conditions
+        .iter()
+        .map(|conjuncts| {
+            let mut conjuncts: Vec<_> = conjuncts.iter().map(|c| c.to_string()).collect();
+            conjuncts.sort();
+            conjuncts
+        })
+        .collect();
+    assert_eq!(conditions, vec![vec!["Post.id != 5", "Post.id >= 3"]]);
+    Ok(())
+}
+
+#[test]
+fn test_authorized_resources_negation() -> oso::Result<()> {
+    let mut oso = test_oso();
+    oso.load_str(
+        r#"allow(_: User, "get", post: Post) if not post.is_published and not post.id = 1;
+           allow(_: User, "delete", post: Post) if
+             not (blog = post.blog and blog.is_featured = true and post.is_published = false);"#,
+    )?;
+
+    let user = User {
+        name: "alice".to_owned(),
+    };
+    let adapter = VecAdapter::new();
+    let posts: Vec<Post> = oso.authorized_resources(&adapter, user.clone(), "get")?;
+    assert_eq!(ids(posts), vec![3]);
+
+    let posts: Vec<Post> = oso.authorized_resources(&adapter, user, "delete")?;
+    assert_eq!(ids(posts), vec![2, 3]);
+    Ok(())
+}
+
+#[test]
+fn test_authorized_actors() -> oso::Result<()> {
+    let mut oso = test_oso();
+    oso.load_str(
+        r#"allow(user: User, "edit", post: Post) if post.owner = user.name;
+           allow(user: User, _, _: Post) if user.name = "admin";"#,
+    )?;
+
+    let post = Post {
+        id: 3,
+        blog_id: 2,
+        is_published: false,
+        owner: "alice".to_owned(),
+    };
+    let adapter = UserAdapter::new();
+    let names = |users: Vec<User>| -> Vec<String> {
+        let mut names: Vec<_> = users.into_iter().map(|user| user.name).collect();
+        names.sort();
+        names
+    };
+    let users: Vec<User> = oso.authorized_actors(&adapter, "edit", post.clone())?;
+    assert_eq!(names(users), vec!["admin", "alice"]);
+
+    let users: Vec<User> = oso.authorized_actors(&adapter, "delete", post)?;
+    assert_eq!(names(users), vec!["admin"]);
+    Ok(())
+}
+
+#[test]
+fn test_query_rule_partial() -> oso::Result<()> {
+    let mut oso = test_oso();
+    oso.load_str(r#"allow(user: User, "get", post: Post) if post.owner = user.name;"#)?;
+
+    let user = User {
+        name: "alice".to_owned(),
+    };
+    let partial = PolarValue::Variable("post".to_owned());
+    let results = oso
+        .query_rule_partial("allow", (user, "get", partial))?
+        .collect::<oso::Result<Vec<_>>>()?;
+    let constraints = match &results[..] {
+        [result] => result.get("post").unwrap(),
+        _ => panic!("expected one result, got {:?}", results),
+    };
+    let expression = match &constraints {
+        PolarValue::Expression(expression) => expression,
+        _ => panic!("expected an expression, got {:?}", constraints),
+    };
+    assert_eq!(expression.operator, Operator::And);
+    assert_eq!(
+        constraints.to_polar(),
+        r#"_this matches Post{} and "alice" = _this.owner"#
+    );
+
+    let filter = oso.data_filter(vec![constraints], "Post")?;
+    let posts = VecAdapter::new().build_query(&filter)?;
+    assert_eq!(ids(posts), vec![1, 3]);
+
+    // Without partials, expressions are still an error.
+    let mut query = oso.query_rule(
+        "allow",
+        (
+            PolarValue::Variable("user".to_owned()),
+            "get",
+            PolarValue::Variable("post".to_owned()),
+        ),
+    )?;
+    assert!(query.next().unwrap().is_err());
+    Ok(())
+}
+
+#[test]
+fn test_specialize() -> oso::Result<()> {
+    let mut oso = test_oso();
+    oso.load_str(
+        r#"allow(user: User, "get", post: Post) if post.owner = user.name;
+           allow(user: User, "edit", post: Post) if user.name = "admin" and post.id > 0;"#,
+    )?;
+
+    let specialize = |name: &str| -> oso::Result<Vec<String>> {
+        let user = User {
+            name: name.to_owned(),
+        };
+        Ok(oso
+            .specialize(user)?
+            .iter()
+            .map(oso::Rule::to_polar)
+            .collect())
+    };
+    assert_eq!(
+        specialize("alice")?,
+        vec![r#"allow(_actor, "get", resource: Post{}) if "alice" = resource.owner;"#]
+    );
+    assert_eq!(
+        specialize("admin")?,
+        vec![
+            r#"allow(_actor, "get", resource: Post{}) if "admin" = resource.owner;"#,
+            r#"allow(_actor, "edit", resource: Post{}) if resource.id > 0;"#,
+        ]
+    );
+
+    // The residual policy allows the same posts without the user.
+    let mut residual = test_oso();
+    residual.load_str(&specialize("alice")?.join("\n"))?;
+    let posts = residual.authorized_resources(&VecAdapter::new(), "anyone", "get")?;
+    assert_eq!(ids(posts), vec![1, 3]);
+    Ok(())
+}
+
+#[test]
+fn test_authorized_query_many_to_many() -> oso::Result<()> {
+    let mut oso = test_oso();
+    oso.load_str(
+        r#"allow(_: User, "get", post: Post) if tag in post.tags and tag.name = "rust";"#,
+    )?;
+
+    let user = User {
+        name: "alice".to_owned(),
+    };
+    let filter = oso.authorized_query(&FilterAdapter, user, "get")?;
+    let relation = |src: &str, field: &str, dst: &str| {
+        filter::Relation(src.to_owned(), field.to_owned(), dst.to_owned())
+    };
+    assert_eq!(
+        filter.relations,
+        vec![
+            relation("Post", "tags", "PostTag"),
+            relation("PostTag", "Post.tags", "Tag")
+        ]
+        .into_iter()
+        .collect()
+    );
+    let conditions: Vec<Vec<String>> = filter
+        .conditions
+        .iter()
+        .map(|conjuncts| conjuncts.iter().map(|c| c.to_string()).collect())
+        .collect();
+    assert_eq!(conditions, vec![vec![r#""rust" = Tag.name"#]]);
+    Ok(())
+}
+
+#[test]
+fn test_authorized_query_forall() -> oso::Result<()> {
+    let mut oso = test_oso();
+    oso.load_str(
+        r#"allow(_: User, "get", post: Post) if
+             forall(tag in post.tags, tag.name in ["rust", "polar"]);"#,
+    )?;
+
+    let user = User {
+        name: "alice".to_owned(),
+    };
+    let filter = oso.authorized_query(&FilterAdapter, user, "get")?;
+    // No tag of the post may have a name outside the list.
+    let subquery = match &filter.conditions[..] {
+        [conjunction] => match Vec::from_iter(conjunction)[..] {
+            [Condition(
+                Datum::Field(Projection(root, None)),
+                Comparison::Nin,
+                Datum::Subquery(subquery),
+            )] if root == "Post" => subquery,
+            _ => panic!("expected an anti-join, got {:?}", conjunction),
+        },
+        _ => panic!("expected one conjunction, got {:?}", filter.conditions),
+    };
+    let relation = |src: &str, field: &str, dst: &str| {
+        filter::Relation(src.to_owned(), field.to_owned(), dst.to_owned())
+    };
+    assert_eq!(
+        subquery.relations,
+        vec![
+            relation("Post", "tags", "PostTag"),
+            relation("PostTag", "Post.tags", "Tag")
+        ]
+        .into_iter()
+        .collect()
+    );
+    let conditions: Vec<Vec<String>> = subquery
+        .conditions
+        .iter()
+        .map(|conjuncts| conjuncts.iter().map(|c| c.to_string()).collect())
+        .collect();
+    assert_eq!(
+        conditions,
+        vec![vec![r#"Tag.name NOT IN ["rust", "polar"]"#]]
+    );
+    Ok(())
+}
+
+#[test]
+fn test_authorized_query_unregistered_field() -> oso::Result<()> {
+    let mut oso = test_oso();
+    oso.load_str(r#"allow(_: User, "get", post: Post) if post.baz.id = 1;"#)?;
+
+    let user = User {
+        name: "alice".to_owned(),
+    };
+    let err = oso
+        .authorized_query(&VecAdapter::new(), user, "get")
+        .unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("Unregistered field or relation: Post.baz"),
+        "{}",
+        err
+    );
+    Ok(())
+}
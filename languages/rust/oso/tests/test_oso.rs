@@ -1,4 +1,5 @@
-use oso::{Action, Oso, PolarClass};
+use oso::{Action, Oso, PolarClass, PolarConfig};
+use polar_core::error::{ErrorKind, PolarError, RuntimeError};
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 
@@ -222,3 +223,357 @@ fn test_get_allowed_actions() -> oso::Result<()> {
 
     Ok(())
 }
+
+#[derive(PolarClass, Debug, Clone, PartialEq)]
+struct Request {
+    #[polar(attribute)]
+    path: String,
+}
+
+#[test]
+fn test_authorize() -> oso::Result<()> {
+    common::setup();
+    let mut oso = Oso::new();
+
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.register_class(Widget::get_polar_class()).unwrap();
+
+    oso.load_str(
+        r#"allow(_: User{name: "sally"}, "read", _: Widget{id: 1});
+           allow(_: User{name: "sally"}, "update", _: Widget{id: 2});
+           allow(_: User{name: "fred"}, "view", _: Widget{id: 1});"#,
+    )?;
+
+    let sally = User::new(String::from("sally"));
+    oso.authorize(sally.clone(), "read", Widget::new(1))?;
+
+    // Sally can read widget 1, so updating it is forbidden.
+    let err = oso
+        .authorize(sally.clone(), "update", Widget::new(1))
+        .unwrap_err();
+    assert!(matches!(err, oso::OsoError::ForbiddenError), "{}", err);
+
+    // Sally can't read widget 2, so it's not found -- even though she can update it.
+    oso.authorize(sally.clone(), "update", Widget::new(2))?;
+    let err = oso
+        .authorize(sally.clone(), "delete", Widget::new(2))
+        .unwrap_err();
+    assert!(matches!(err, oso::OsoError::NotFoundError), "{}", err);
+    let err = oso.authorize(sally, "read", Widget::new(2)).unwrap_err();
+    assert!(matches!(err, oso::OsoError::NotFoundError), "{}", err);
+
+    // The read action is configurable.
+    let fred = User::new(String::from("fred"));
+    let err = oso
+        .authorize(fred.clone(), "delete", Widget::new(1))
+        .unwrap_err();
+    assert!(matches!(err, oso::OsoError::NotFoundError), "{}", err);
+    oso.set_read_action("view");
+    let err = oso.authorize(fred, "delete", Widget::new(1)).unwrap_err();
+    assert!(matches!(err, oso::OsoError::ForbiddenError), "{}", err);
+
+    Ok(())
+}
+
+#[test]
+fn test_authorize_request() -> oso::Result<()> {
+    common::setup();
+    let mut oso = Oso::new();
+
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.register_class(Request::get_polar_class()).unwrap();
+
+    oso.load_str(
+        r#"allow(_, _, _) if false;
+           allow_request(_: User{name: "sally"}, request: Request) if
+               request.path = "/repos";"#,
+    )?;
+
+    let sally = User::new(String::from("sally"));
+    let request = Request {
+        path: String::from("/repos"),
+    };
+    oso.authorize_request(sally.clone(), request)?;
+
+    let request = Request {
+        path: String::from("/admin"),
+    };
+    let err = oso.authorize_request(sally, request).unwrap_err();
+    assert!(matches!(err, oso::OsoError::ForbiddenError), "{}", err);
+
+    Ok(())
+}
+
+#[test]
+fn test_authorize_field() -> oso::Result<()> {
+    common::setup();
+    let mut oso = Oso::new();
+
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.register_class(Widget::get_polar_class()).unwrap();
+
+    oso.load_str(
+        r#"allow(_, _, _) if false;
+           allow_field(_: User{name: "sally"}, "read", _: Widget, field) if
+               field in ["id", "name"];
+           allow_field(_: User{name: "fred"}, "read", _: Widget, _field);"#,
+    )?;
+
+    let sally = User::new(String::from("sally"));
+    oso.authorize_field(sally.clone(), "read", Widget::new(1), "name")?;
+    let err = oso
+        .authorize_field(sally.clone(), "read", Widget::new(1), "secret")
+        .unwrap_err();
+    assert!(matches!(err, oso::OsoError::ForbiddenError), "{}", err);
+
+    let fields: HashSet<String> = oso.authorized_fields(sally, "read", Widget::new(1), false)?;
+    assert_eq!(
+        fields,
+        vec!["id".to_owned(), "name".to_owned()]
+            .into_iter()
+            .collect()
+    );
+
+    let fred = User::new(String::from("fred"));
+    assert!(oso
+        .authorized_fields::<_, _, _, String>(fred.clone(), "read", Widget::new(1), false)
+        .is_err());
+    let fields: HashSet<String> = oso.authorized_fields(fred, "read", Widget::new(1), true)?;
+    assert_eq!(fields, vec!["*".to_owned()].into_iter().collect());
+
+    Ok(())
+}
+
+#[test]
+fn test_authorized_actions() -> oso::Result<()> {
+    common::setup();
+    let mut oso = Oso::new();
+
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.register_class(Widget::get_polar_class()).unwrap();
+
+    oso.load_str(
+        r#"allow(_: User{name: "sally"}, action, _: Widget{id: 1}) if
+               action in ["CREATE", "READ"];
+           allow(_: User{name: "fred"}, _action, _: Widget{id: 1});"#,
+    )?;
+
+    let sally = User::new(String::from("sally"));
+    let actions: HashSet<String> = oso.authorized_actions(sally, Widget::new(1), false)?;
+    assert_eq!(
+        actions,
+        vec!["CREATE".to_owned(), "READ".to_owned()]
+            .into_iter()
+            .collect()
+    );
+
+    let fred = User::new(String::from("fred"));
+    let err = oso
+        .authorized_actions::<_, _, String>(fred.clone(), Widget::new(1), false)
+        .unwrap_err();
+    assert!(err.to_string().contains("allow_wildcard"), "{}", err);
+    let actions: HashSet<String> = oso.authorized_actions(fred, Widget::new(1), true)?;
+    assert_eq!(actions, vec!["*".to_owned()].into_iter().collect());
+
+    Ok(())
+}
+
+#[test]
+fn test_explain() -> oso::Result<()> {
+    common::setup();
+    let mut oso = Oso::new();
+
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.register_class(Widget::get_polar_class()).unwrap();
+
+    oso.load_str(
+        r#"allow(user: User, "edit", widget: Widget) if
+               user.name = "sally" and widget.id = 1;
+           allow(user: User, "edit", _: Widget) if user.name = "admin";"#,
+    )?;
+
+    let explanation = oso.explain(User::new(String::from("sally")), "edit", Widget::new(1))?;
+    assert!(explanation.allowed);
+    assert!(explanation.failures.is_empty());
+    let proof = explanation.proof.unwrap();
+    let rule = &proof.children[0];
+    assert_eq!(rule.kind, oso::ExplainKind::Rule);
+    let goals: Vec<_> = rule
+        .children
+        .iter()
+        .map(|goal| goal.bound.as_deref().unwrap())
+        .collect();
+    assert_eq!(
+        goals,
+        vec![
+            r#""sally" = "sally""#,
+            r#"User.name = "sally""#,
+            "1 = 1",
+            "Widget.id = 1"
+        ]
+    );
+
+    let explanation = oso.explain(User::new(String::from("sally")), "edit", Widget::new(2))?;
+    assert!(!explanation.allowed);
+    assert!(explanation.proof.is_none());
+    let failed_goals: Vec<_> = explanation
+        .failures
+        .iter()
+        .map(|failure| failure.goal.as_ref().unwrap().bound.as_deref().unwrap())
+        .collect();
+    assert_eq!(
+        failed_goals,
+        vec!["Widget.id = 1", r#"User.name = "admin""#]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_reload() -> oso::Result<()> {
+    common::setup();
+    let mut oso = Oso::new();
+
+    oso.register_class(User::get_polar_class()).unwrap();
+    oso.register_class(Widget::get_polar_class()).unwrap();
+    oso.register_class(Company::get_polar_class()).unwrap();
+
+    oso.load_str(r#"allow(_: User, "read", _: Widget);"#)?;
+    let sally = User::new(String::from("sally"));
+
+    // A query started before the reload finishes against the old policy.
+    let mut query = oso.query_rule("allow", (sally.clone(), "read", Widget::new(1)))?;
+    oso.reload_str(r#"allow(_: User, "write", _: Widget);"#)?;
+    assert!(query.next().unwrap().is_ok());
+    assert!(!oso.is_allowed(sally.clone(), "read", Widget::new(1))?);
+    assert!(oso.is_allowed(sally.clone(), "write", Widget::new(1))?);
+
+    // If the new policy doesn't load, the current one stays in place.
+    assert!(oso.reload_str(r#"allow(_, _, _) if undefined();"#).is_err());
+    assert!(oso.reload_str(r#"allow(_, _, _); ?= 1 = 2;"#).is_err());
+    assert!(oso.is_allowed(sally.clone(), "write", Widget::new(1))?);
+
+    // Reloading from no files doesn't wipe the policy.
+    assert!(oso.reload_files(Vec::<&str>::new()).is_err());
+    assert!(oso.is_allowed(sally.clone(), "write", Widget::new(1))?);
+
+    oso.reload_files(vec![test_file_path()])?;
+    assert!(!oso.is_allowed(sally.clone(), "write", Widget::new(1))?);
+    assert!(oso.is_allowed(sally, "get", Widget::new(1))?);
+
+    Ok(())
+}
+
+#[test]
+fn test_config() -> oso::Result<()> {
+    common::setup();
+    let policy = "f(x) if x = 0 or f(x - 1);";
+    let mut oso = Oso::with_config(PolarConfig::new());
+    let mut shallow = Oso::with_config(PolarConfig::new().stack_limit(50));
+    oso.load_str(policy)?;
+    shallow.load_str(policy)?;
+
+    let overflow = |result: Option<oso::Result<oso::ResultSet>>| {
+        let err = result.unwrap().unwrap_err();
+        assert!(err.to_string().contains("Goal stack overflow"), "{}", err);
+    };
+    assert!(oso.query_rule("f", (100,))?.next().unwrap().is_ok());
+    overflow(shallow.query_rule("f", (100,))?.next());
+
+    // Per-query configuration overrides the instance's.
+    overflow(
+        oso.query_rule_with_config("f", (100,), &PolarConfig::new().stack_limit(50))?
+            .next(),
+    );
+    let config = PolarConfig::new().stack_limit(10_000);
+    assert!(shallow
+        .query_rule_with_config("f", (100,), &config)?
+        .next()
+        .unwrap()
+        .is_ok());
+
+    let budget = PolarConfig::new().max_goals(Some(1_000));
+    let err = oso
+        .query_rule_with_config("f", (100,), &budget)?
+        .next()
+        .unwrap()
+        .unwrap_err();
+    assert!(
+        err.to_string().contains("exceeded its limit of 1000 goals"),
+        "{}",
+        err
+    );
+    Ok(())
+}
+
+#[test]
+fn test_cancellation() -> oso::Result<()> {
+    common::setup();
+    // Without a cancellation, this would run until it ran out of memory.
+    let config = PolarConfig::new()
+        .query_timeout_ms(0)
+        .stack_limit(usize::MAX);
+    let mut oso = Oso::with_config(config);
+    oso.load_str("loop(x) if loop(x);")?;
+
+    let mut query = oso.query_rule("loop", (1,))?;
+    let token = query.cancellation_token();
+    let canceller = std::thread::spawn(move || {
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        token.cancel();
+    });
+    let err = query.next().unwrap().unwrap_err();
+    canceller.join().unwrap();
+    assert!(
+        matches!(
+            err,
+            oso::OsoError::Polar(PolarError {
+                kind: ErrorKind::Runtime(RuntimeError::Cancelled),
+                ..
+            })
+        ),
+        "{}",
+        err
+    );
+    Ok(())
+}
+
+#[test]
+fn test_tabled_rules() -> oso::Result<()> {
+    common::setup();
+
+    #[derive(PolarClass, Clone)]
+    struct Folder;
+
+    // Folder 1 is in 2, which is in 3, which is in both 1 and 4.
+    fn parents(id: i64) -> Vec<i64> {
+        match id {
+            1 => vec![2],
+            2 => vec![3],
+            3 => vec![1, 4],
+            _ => vec![],
+        }
+    }
+
+    let mut oso = Oso::new();
+    oso.register_class(
+        Folder::get_polar_class_builder()
+            .add_class_method("parents", parents)
+            .build(),
+    )?;
+    oso.load_str(
+        r#"tabled type ancestor(folder, other);
+           ancestor(folder, parent) if parent in Folder.parents(folder);
+           ancestor(folder, other) if
+               ancestor(folder, parent) and other in Folder.parents(parent);"#,
+    )?;
+
+    let mut ancestors = oso
+        .query("ancestor(1, x)")?
+        .map(|result| result.and_then(|set| set.get_typed::<i64>("x")))
+        .collect::<oso::Result<Vec<_>>>()?;
+    ancestors.sort_unstable();
+    assert_eq!(ancestors, vec![1, 2, 3, 4]);
+    assert!(oso.query_rule("ancestor", (4, 1))?.next().is_none());
+    Ok(())
+}
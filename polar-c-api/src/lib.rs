@@ -1,6 +1,7 @@
 // everything in this file is unsafe, clippy
 #![allow(clippy::not_unsafe_ptr_arg_deref)]
 
+use polar_core::config::PolarConfig;
 use polar_core::error::PolarError;
 pub use polar_core::polar::Polar;
 pub use polar_core::query::Query;
@@ -99,6 +100,13 @@ pub extern "C" fn polar_new() -> *mut Polar {
     box_ptr!(Polar::new())
 }
 
+/// Create a `Polar` from a JSON-serialized `PolarConfig`. Fields missing
+/// from the JSON take their default values; the environment is not read.
+#[no_mangle]
+pub extern "C" fn polar_new_with_config(config: *const c_char) -> *mut CResult<Polar> {
+    ffi_try!({ from_json(config).map(|config| box_ptr!(Polar::with_config(config))) })
+}
+
 #[no_mangle]
 pub extern "C" fn polar_load(
     polar_ptr: *mut Polar,
@@ -170,6 +178,24 @@ pub extern "C" fn polar_new_query_from_term(
     })
 }
 
+/// Like `polar_new_query_from_term`, with a JSON-serialized `PolarConfig`
+/// overriding the query's timeout, stack limit and logging.
+#[no_mangle]
+pub extern "C" fn polar_new_query_from_term_with_config(
+    polar_ptr: *mut Polar,
+    query_term: *const c_char,
+    trace: u32,
+    config: *const c_char,
+) -> *mut CResult<Query> {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        let config: PolarConfig = from_json(config)?;
+        from_json(query_term).map(|query| {
+            box_ptr!(polar.new_query_from_term_with_config(query, trace != 0, &config))
+        })
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn polar_new_query(
     polar_ptr: *mut Polar,
@@ -317,6 +343,20 @@ pub extern "C" fn polar_bind(
     })
 }
 
+/// Represent recursive relations in the query's partial results as closures,
+/// which only `polar_build_data_filter` understands.
+#[no_mangle]
+pub extern "C" fn polar_query_set_fixpoints(
+    query_ptr: *mut Query,
+    enabled: u32,
+) -> *mut CResult<c_void> {
+    ffi_try!({
+        let query = unsafe { ffi_ref!(query_ptr) };
+        query.set_fixpoints(enabled != 0);
+        Ok(())
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn polar_get_external_id(polar_ptr: *mut Polar) -> u64 {
     let polar = unsafe { ffi_ref!(polar_ptr) };
@@ -385,6 +425,34 @@ pub extern "C" fn polar_build_data_filter(
     })
 }
 
+#[no_mangle]
+pub extern "C" fn polar_build_data_filter_explained(
+    polar_ptr: *mut Polar,
+    types: *const c_char,
+    results: *const c_char,
+    variable: *const c_char,
+    class_tag: *const c_char,
+) -> *mut CResult<c_char> {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        let variable = unsafe { ffi_string!(variable) };
+        let class_tag = unsafe { ffi_string!(class_tag) };
+
+        from_json(types)
+            .and_then(|types| from_json(results).map(|results| (types, results)))
+            .and_then(|(types, results)| {
+                polar
+                    .build_data_filter_explained(types, results, &variable, &class_tag)
+                    .map(|explained| {
+                        let explained_json = serde_json::to_string(&explained).unwrap();
+                        CString::new(explained_json)
+                            .expect("JSON should not contain any 0 bytes")
+                            .into_raw()
+                    })
+            })
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn polar_build_filter_plan(
     polar_ptr: *mut Polar,
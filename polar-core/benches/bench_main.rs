@@ -4,4 +4,8 @@ mod benchmarks;
 
 use criterion::criterion_main;
 
-criterion_main!(benchmarks::queries::benches, benchmarks::partial::benches);
+criterion_main!(
+    benchmarks::queries::benches,
+    benchmarks::partial::benches,
+    benchmarks::symbols::benches
+);
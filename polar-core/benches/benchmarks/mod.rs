@@ -1,3 +1,4 @@
 pub mod partial;
 pub mod queries;
 mod runner;
+pub mod symbols;
@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+
+use criterion::{criterion_group, BenchmarkId, Criterion};
+
+use polar_core::terms::*;
+
+use super::runner::runner_from_query;
+
+// Benchmarks measuring how quickly symbols are made, hashed and compared.
+
+/// Bench: recurse `n` times through a rule with many variables. Every call
+/// renames the rule's variables to fresh symbols and binds them, so this is
+/// dominated by making, hashing and looking up symbols.
+pub fn rename_vars(c: &mut Criterion) {
+    let policy = "
+        vars(a, b, c, d, e, f, g, h) if a = b and b = c and c = d and d = e and e = f and f = g and g = h;
+        recurse(0);
+        recurse(n) if n > 0 and vars(x, _y, _z, 1, _u, _v, _w, 1) and x = 1 and recurse(n - 1);
+    ";
+
+    let mut group = c.benchmark_group("rename_vars");
+    for n in &[1, 10, 50] {
+        group.bench_function(BenchmarkId::from_parameter(format!("{}", n)), |b| {
+            b.iter_batched_ref(
+                || {
+                    let mut runner = runner_from_query(&format!("recurse({})", n));
+                    runner.load_str(policy).unwrap();
+                    runner.expected_result(maplit::hashmap!());
+                    runner
+                },
+                |runner| {
+                    runner.run();
+                },
+                criterion::BatchSize::SmallInput,
+            )
+        });
+    }
+
+    group.finish();
+}
+
+/// Bench: look up each of 100 variables in a map keyed by symbol, as the VM
+/// does for bindings and rules.
+pub fn symbol_lookup(c: &mut Criterion) {
+    let symbols: Vec<Symbol> = (0..100)
+        .map(|i| Symbol::new(&format!("_variable_{}", i)))
+        .collect();
+    let table: HashMap<&Symbol, usize> = symbols.iter().zip(0..).collect();
+    c.bench_function("symbol_lookup", |b| {
+        b.iter(|| symbols.iter().map(|sym| table[sym]).sum::<usize>())
+    });
+}
+
+criterion_group!(benches, rename_vars, symbol_lookup);
@@ -0,0 +1,172 @@
+//! Aggregates over the solutions of a goal: `count`, `collect`, `min`, `max` and `sum`.
+//!
+//! `count(x, goal, n)` runs `goal` in a sub-VM to exhaustion, like `forall`, and
+//! aggregates the values that the template `x` takes in its solutions: one per
+//! solution, except that `count` counts distinct values.
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+use crate::counter::Counter;
+use crate::error::RuntimeError;
+use crate::events::QueryEvent;
+use crate::formatting::ToPolarString;
+use crate::numerics::Numeric;
+use crate::runnable::Runnable;
+use crate::terms::{Operator, Term, Value};
+use crate::vm::{compare, Goals, PolarVirtualMachine};
+
+type Result<T> = core::result::Result<T, RuntimeError>;
+
+#[derive(Clone)]
+pub struct Aggregator {
+    vm: PolarVirtualMachine,
+    /// The aggregate operation, e.g., `count(x, f(x), n)`.
+    term: Term,
+    operator: Operator,
+    template: Term,
+    values: Vec<Term>,
+    /// The values seen so far, so that `count` only counts each one once.
+    distinct: HashSet<Term>,
+    /// Where to put the aggregate, if there is one.
+    result: Rc<RefCell<Option<Term>>>,
+}
+
+impl Aggregator {
+    pub fn new(
+        vm: &PolarVirtualMachine,
+        goals: Goals,
+        term: Term,
+        operator: Operator,
+        template: Term,
+        result: Rc<RefCell<Option<Term>>>,
+    ) -> Self {
+        Self {
+            vm: vm.clone_with_goals(goals),
+            term,
+            operator,
+            template,
+            values: vec![],
+            distinct: HashSet::new(),
+            result,
+        }
+    }
+
+    fn unsupported(&self, msg: String) -> RuntimeError {
+        RuntimeError::Unsupported {
+            msg,
+            term: self.term.clone(),
+        }
+    }
+
+    /// Aggregate the values collected so far. `None` if there isn't an aggregate,
+    /// i.e., the `min` or `max` of no values.
+    fn aggregate(&self) -> Result<Option<Term>> {
+        let values = self.values.clone();
+        let aggregate = match self.operator {
+            Operator::Count => Some(Value::Number(Numeric::Integer(values.len() as i64))),
+            Operator::Collect => Some(Value::List(values)),
+            Operator::Sum => {
+                let mut sum = Numeric::Integer(0);
+                for value in &values {
+                    sum = match value.value() {
+                        Value::Number(n) => {
+                            (sum + *n).ok_or_else(|| RuntimeError::ArithmeticError {
+                                term: self.term.clone(),
+                            })?
+                        }
+                        _ => {
+                            return Err(self.unsupported(format!(
+                                "cannot sum non-numeric value {} in {}",
+                                value.to_polar(),
+                                self.term.to_polar()
+                            )))
+                        }
+                    };
+                }
+                Some(Value::Number(sum))
+            }
+            Operator::Min | Operator::Max => {
+                let better = if self.operator == Operator::Min {
+                    Operator::Lt
+                } else {
+                    Operator::Gt
+                };
+                let mut best: Option<Term> = None;
+                for value in values {
+                    best = match best {
+                        Some(best) => {
+                            let is_better = compare(better, &value, &best, Some(&self.term))
+                                .map_err(|_| {
+                                    self.unsupported(format!(
+                                        "cannot compare {} and {} in {}",
+                                        value.to_polar(),
+                                        best.to_polar(),
+                                        self.term.to_polar()
+                                    ))
+                                })?;
+                            Some(if is_better { value } else { best })
+                        }
+                        None => Some(value),
+                    };
+                }
+                best.map(|best| best.value().clone())
+            }
+            _ => {
+                return Err(RuntimeError::InvalidState {
+                    msg: format!("{} is not an aggregate", self.operator.to_polar()),
+                })
+            }
+        };
+        Ok(aggregate.map(|value| self.term.clone_with_value(value)))
+    }
+}
+
+impl Runnable for Aggregator {
+    fn run(&mut self, _: Option<&mut Counter>) -> Result<QueryEvent> {
+        loop {
+            match self.vm.run(None)? {
+                QueryEvent::Result { .. } => {
+                    let value = self.vm.deref(&self.template);
+                    if self.vm.aliases(std::slice::from_ref(&value)).is_none() {
+                        return Err(self.unsupported(format!(
+                            "aggregating partially evaluated values in {}",
+                            self.term.to_polar()
+                        )));
+                    }
+                    if self.operator != Operator::Count || self.distinct.insert(value.clone()) {
+                        self.values.push(value);
+                    }
+                }
+                QueryEvent::Done { .. } => {
+                    let aggregate = self.aggregate()?;
+                    let result = aggregate.is_some();
+                    *self.result.borrow_mut() = aggregate;
+                    return Ok(QueryEvent::Done { result });
+                }
+                event => return Ok(event),
+            }
+        }
+    }
+
+    fn external_question_result(&mut self, call_id: u64, answer: bool) -> Result<()> {
+        self.vm.external_question_result(call_id, answer)
+    }
+
+    fn external_call_result(&mut self, call_id: u64, term: Option<Term>) -> Result<()> {
+        self.vm.external_call_result(call_id, term)
+    }
+
+    fn debug_command(&mut self, command: &str) -> Result<()> {
+        self.vm.debug_command(command)
+    }
+
+    fn clone_runnable(&self) -> Box<dyn Runnable> {
+        Box::new(self.clone())
+    }
+
+    fn handle_error(&mut self, error: RuntimeError) -> Result<QueryEvent> {
+        self.vm.handle_error(error)
+    }
+}
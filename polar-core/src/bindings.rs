@@ -337,6 +337,50 @@ impl BindingManager {
         self.variable_state_at_point(variable, &self.bsp())
     }
 
+    /// The unbound variables that `variable` is aliased to, including itself.
+    /// `None` if it's bound or constrained.
+    pub fn aliases(&self, variable: &Symbol) -> Option<Vec<Symbol>> {
+        match self._variable_state(variable) {
+            BindingManagerVariableState::Unbound => Some(vec![variable.clone()]),
+            BindingManagerVariableState::Cycle(cycle) => Some(cycle),
+            _ => None,
+        }
+    }
+
+    /// The variables that `variable` was unified with at `bsp`, including itself,
+    /// whether or not they have been constrained since they were aliased.
+    pub fn unified_at_point(&self, variable: &Symbol, bsp: &Bsp) -> HashSet<Symbol> {
+        let mut unified = HashSet::new();
+        unified.insert(variable.clone());
+        match self._variable_state_at_point(variable, bsp) {
+            BindingManagerVariableState::Cycle(cycle) => unified.extend(cycle),
+            BindingManagerVariableState::Partial(e) => loop {
+                let before = unified.len();
+                for arg in e.args.iter() {
+                    if let Ok(Operation {
+                        operator: Operator::Unify,
+                        args,
+                    }) = arg.value().as_expression()
+                    {
+                        if let (Value::Variable(l), Value::Variable(r)) =
+                            (args[0].value(), args[1].value())
+                        {
+                            if unified.contains(l) || unified.contains(r) {
+                                unified.insert(l.clone());
+                                unified.insert(r.clone());
+                            }
+                        }
+                    }
+                }
+                if unified.len() == before {
+                    break;
+                }
+            },
+            _ => {}
+        }
+        unified
+    }
+
     pub fn variable_state_at_point(&self, variable: &Symbol, bsp: &Bsp) -> VariableState {
         let index = bsp.bindings_index;
         let mut next = variable;
@@ -394,6 +438,13 @@ impl BindingManager {
         bindings
     }
 
+    /// Whether any partial was constrained after `after`.
+    pub fn constrained_after(&self, after: &Bsp) -> bool {
+        self.bindings[after.bindings_index..]
+            .iter()
+            .any(|Binding(_, value)| matches!(value.value(), Value::Expression(_)))
+    }
+
     pub fn variable_bindings(&self, variables: &HashSet<Symbol>) -> Bindings {
         let mut bindings = HashMap::new();
         for var in variables.iter() {
@@ -650,7 +701,7 @@ mod test {
         );
         assert_eq!(
             bindings._variable_state(&z),
-            BindingManagerVariableState::Cycle(vec![z.clone(), x.clone(), y])
+            BindingManagerVariableState::Cycle(vec![z, x.clone(), y])
         );
 
         // Expression.
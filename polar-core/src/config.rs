@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+
+use super::vm::{DEFAULT_TIMEOUT_MS, MAX_STACK_SIZE};
+
+/// Where the VM sends its log lines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LogSink {
+    /// Queue them as `Print` messages for the host to print.
+    Messages,
+    /// Print them immediately, to stderr (or the console in WASM).
+    Stderr,
+}
+
+/// Configuration for a `Polar` instance and the queries it makes.
+///
+/// `PolarConfig::default()` ignores the environment; `PolarConfig::from_env()` reads
+/// the `POLAR_TIMEOUT_MS`, `POLAR_LOG`, `POLAR_EXPLAIN` and
+/// `POLAR_IGNORE_NO_ALLOW_WARNING` variables, as `Polar::new()` does.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PolarConfig {
+    pub(crate) query_timeout_ms: u64,
+    pub(crate) stack_limit: usize,
+    pub(crate) vm_log: bool,
+    pub(crate) polar_log: bool,
+    pub(crate) log_sink: LogSink,
+    pub(crate) explain: bool,
+    pub(crate) ignore_no_allow_warning: bool,
+    pub(crate) max_goals: Option<u64>,
+    pub(crate) max_backtracks: Option<u64>,
+    pub(crate) max_external_calls: Option<u64>,
+}
+
+impl Default for PolarConfig {
+    fn default() -> Self {
+        Self {
+            query_timeout_ms: DEFAULT_TIMEOUT_MS,
+            stack_limit: MAX_STACK_SIZE,
+            vm_log: false,
+            polar_log: false,
+            log_sink: LogSink::Messages,
+            explain: false,
+            ignore_no_allow_warning: false,
+            max_goals: None,
+            max_backtracks: None,
+            max_external_calls: None,
+        }
+    }
+}
+
+impl PolarConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The default configuration, overridden by any `POLAR_*` environment variables.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Some(timeout) = std::env::var("POLAR_TIMEOUT_MS")
+            .ok()
+            .and_then(|timeout_str| timeout_str.parse::<u64>().ok())
+        {
+            config.query_timeout_ms = timeout;
+        }
+        // get all comma-delimited POLAR_LOG variables
+        let polar_log = std::env::var("POLAR_LOG");
+        let polar_log_vars = polar_log
+            .iter()
+            .flat_map(|pl| pl.split(','))
+            .collect::<Vec<&str>>();
+        config.vm_log = polar_log_vars.iter().any(|var| var == &"trace");
+        config.polar_log = !polar_log_vars.is_empty()
+            && !polar_log_vars.iter().any(|var| ["0", "off"].contains(var));
+        if polar_log_vars.iter().any(|var| var == &"now") {
+            config.log_sink = LogSink::Stderr;
+        }
+        config.explain = std::env::var("POLAR_EXPLAIN").is_ok();
+        config.ignore_no_allow_warning = std::env::var("POLAR_IGNORE_NO_ALLOW_WARNING").is_ok();
+        config
+    }
+
+    /// Fail queries that run for longer than `ms` milliseconds. `0` disables the timeout.
+    pub fn query_timeout_ms(mut self, ms: u64) -> Self {
+        self.query_timeout_ms = ms;
+        self
+    }
+
+    /// Fail queries whose goal stack grows beyond `limit` goals.
+    pub fn stack_limit(mut self, limit: usize) -> Self {
+        self.stack_limit = limit;
+        self
+    }
+
+    /// Log the internal operation of the VM (`POLAR_LOG=trace`).
+    pub fn vm_log(mut self, enabled: bool) -> Self {
+        self.vm_log = enabled;
+        self
+    }
+
+    /// Log the evaluation of policies (`POLAR_LOG=1`).
+    pub fn polar_log(mut self, enabled: bool) -> Self {
+        self.polar_log = enabled;
+        self
+    }
+
+    /// Where to send log lines (`POLAR_LOG=now` prints them to stderr).
+    pub fn log_sink(mut self, sink: LogSink) -> Self {
+        self.log_sink = sink;
+        self
+    }
+
+    /// Print data filtering plans and filters as they are built (`POLAR_EXPLAIN`).
+    pub fn explain(mut self, enabled: bool) -> Self {
+        self.explain = enabled;
+        self
+    }
+
+    /// Don't warn about policies without an `allow` rule (`POLAR_IGNORE_NO_ALLOW_WARNING`).
+    pub fn ignore_no_allow_warning(mut self, ignore: bool) -> Self {
+        self.ignore_no_allow_warning = ignore;
+        self
+    }
+
+    /// Fail queries that run more than `limit` VM goals. `None` (the default) is unlimited.
+    ///
+    /// Unlike the timeout, budgets are deterministic: the same query against the
+    /// same policy and data always does the same amount of work.
+    pub fn max_goals(mut self, limit: Option<u64>) -> Self {
+        self.max_goals = limit;
+        self
+    }
+
+    /// Fail queries that backtrack more than `limit` times. `None` (the default) is unlimited.
+    pub fn max_backtracks(mut self, limit: Option<u64>) -> Self {
+        self.max_backtracks = limit;
+        self
+    }
+
+    /// Fail queries that make more than `limit` calls into the host (lookups, `isa`
+    /// checks, comparisons, etc.). `None` (the default) is unlimited.
+    pub fn max_external_calls(mut self, limit: Option<u64>) -> Self {
+        self.max_external_calls = limit;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserializes_partial_configs() {
+        let config: PolarConfig =
+            serde_json::from_str(r#"{"query_timeout_ms": 0, "log_sink": "Stderr"}"#).unwrap();
+        assert_eq!(
+            config,
+            PolarConfig::new()
+                .query_timeout_ms(0)
+                .log_sink(LogSink::Stderr)
+        );
+    }
+}
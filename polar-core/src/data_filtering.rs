@@ -36,6 +36,18 @@ pub enum Type {
         my_field: FieldName,
         other_field: FieldName,
     },
+    /// A many-to-many relation through records of a join type, e.g., `UserGroup`
+    /// for a `user_groups(user_id, group_id)` table. A record is related to the
+    /// join records whose `join_my_field` equals its `my_field`, and through them
+    /// to the other records whose `other_field` equals their `join_other_field`.
+    JoinRelation {
+        other_class_tag: TypeName,
+        join_class_tag: TypeName,
+        my_field: FieldName,
+        join_my_field: FieldName,
+        join_other_field: FieldName,
+        other_field: FieldName,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
@@ -129,8 +141,9 @@ pub fn build_filter_plan(
     partial_results: PartialResults,
     variable: &str,
     class_tag: &str,
+    explain: bool,
 ) -> Result<FilterPlan> {
-    FilterPlan::build(types, partial_results, variable, class_tag)
+    FilterPlan::build(types, partial_results, variable, class_tag, explain)
 }
 
 impl From<Term> for Constraint {
@@ -186,7 +199,7 @@ impl VarInfo {
             _ => {
                 let new_var = sym!(&format!(
                     "_{}_dot_{}_{}",
-                    sym.0,
+                    sym,
                     field_str,
                     self.counter.next()
                 ));
@@ -227,7 +240,7 @@ impl VarInfo {
         match rhs.value().as_pattern() {
             Ok(Pattern::Instance(i)) if i.fields.fields.is_empty() => {
                 let lhs = self.symbolize(lhs);
-                self.types.push((lhs, i.tag.0.clone()));
+                self.types.push((lhs, i.tag.to_string()));
                 Ok(self)
             }
             _ => unsupported_op_error(Operation {
@@ -298,6 +311,29 @@ impl VarInfo {
         }
     }
 
+    /// The VM keeps `not x in [a, b]` over a ground list whole; here it's the
+    /// conjunction `a != x and b != x`.
+    fn do_not(self, arg: &Term) -> Result<Self> {
+        match arg.value().as_expression() {
+            Ok(Operation {
+                operator: Operator::In,
+                args,
+            }) if args.len() == 2 && args[1].is_ground() => match args[1].value() {
+                Value::List(items) => items
+                    .iter()
+                    .try_fold(self, |this, item| this.do_neq(item, &args[0])),
+                _ => unsupported_op_error(Operation {
+                    operator: Operator::Not,
+                    args: vec![arg.clone()],
+                }),
+            },
+            _ => unsupported_op_error(Operation {
+                operator: Operator::Not,
+                args: vec![arg.clone()],
+            }),
+        }
+    }
+
     /// Process an expression in the context of this VarInfo. Just does side effects.
     fn process_exp(self, exp: &Operation) -> Result<Self> {
         use Operator::*;
@@ -308,6 +344,7 @@ impl VarInfo {
             Isa if args.len() == 2 => self.do_isa(&args[0], &args[1]),
             Neq if args.len() == 2 => self.do_neq(&args[0], &args[1]),
             In if args.len() == 2 => self.do_in(&args[0], &args[1]),
+            Not if args.len() == 1 => self.do_not(&args[0]),
             Unify | Eq | Assign if args.len() == 2 => self.do_unify(&args[0], &args[1]),
             _ => unsupported_op_error(exp.clone()),
         }
@@ -331,12 +368,8 @@ impl FilterPlan {
         partial_results: PartialResults,
         var: &str,
         class_tag: &str,
+        explain: bool,
     ) -> Result<FilterPlan> {
-        // @NOTE(steve): Just reading an env var here sucks (see all the stuff we had to do
-        // to get POLAR_LOG to work in all libs, wasm etc...) but that's what I'm doing today.
-        // At some point surface this info better.
-        let explain = std::env::var("POLAR_EXPLAIN").is_ok();
-
         if explain {
             eprintln!("\n===Data Filtering Query===");
             eprintln!("\n==Bindings==")
@@ -805,10 +838,10 @@ impl<'a> ResultSetBuilder<'a> {
             invalid_state_error(format!(
                 "Unsupported field access: {}.{} = {}",
                 self.var_name(id)
-                    .unwrap_or_else(|| Symbol(format!("{}", id))),
+                    .unwrap_or_else(|| Symbol::new(&id.to_string())),
                 field,
                 self.var_name(child)
-                    .unwrap_or_else(|| Symbol(format!("{}", child))),
+                    .unwrap_or_else(|| Symbol::new(&child.to_string())),
             ))
         }
     }
@@ -837,6 +870,13 @@ impl<'a> ResultSetBuilder<'a> {
                     }) => {
                         this.constrain_relation(id, *child, other_class_tag, my_field, other_field)
                     }
+                    Some(Type::JoinRelation { .. }) => RuntimeError::unsupported(
+                        format!(
+                            "many-to-many relation {}.{} in a filter plan; use a data filter instead",
+                            var_type, field
+                        ),
+                        Term::from(Value::String(field.clone())),
+                    ),
                     _ => {
                         let before = this.result_set.requests.get(&id).unwrap().len();
                         this.constrain_field_eq(id, field, *child)?
@@ -934,7 +974,7 @@ impl Vars {
         for (id, set) in &self.variables {
             let values = set
                 .iter()
-                .map(|sym| sym.0.clone())
+                .map(|sym| sym.to_string())
                 .collect::<Vec<String>>()
                 .join(", ");
             eprintln!("      {}:  vars: {{{}}}", id, values);
@@ -1093,7 +1133,7 @@ mod test {
                 }
             }
         };
-        build_filter_plan(types, vec![bindings], "resource", "A")?;
+        build_filter_plan(types, vec![bindings], "resource", "A", false)?;
         Ok(())
     }
 
@@ -1104,7 +1144,7 @@ mod test {
             sym!("resource") => partial
         });
 
-        build_filter_plan(hashmap! {}, vec![bindings], "resource", "SomeClass")?;
+        build_filter_plan(hashmap! {}, vec![bindings], "resource", "SomeClass", false)?;
         Ok(())
     }
 
@@ -1128,7 +1168,7 @@ mod test {
             "A".to_owned() => hashmap! { },
         };
 
-        let err = build_filter_plan(types, vec![bindings], "resource", "A").unwrap_err();
+        let err = build_filter_plan(types, vec![bindings], "resource", "A", false).unwrap_err();
         match err {
             RuntimeError::DataFilteringFieldMissing { var_type, field }
                 if var_type == "A" && field == "field" => {}
@@ -1137,6 +1177,51 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_negated_in_plan() -> TestResult {
+        let polar = crate::polar::Polar::new();
+        polar
+            .load_str(r#"f(r: A) if not r.name in ["a", "b"];"#)
+            .unwrap();
+        let mut query = polar.new_query_from_term(term!(call!("f", [sym!("r")])), false);
+        let bindings = loop {
+            match query.next_event().unwrap() {
+                crate::events::QueryEvent::Result { bindings, .. } => break bindings,
+                crate::events::QueryEvent::ExternalIsSubclass { call_id, .. } => {
+                    query.question_result(call_id, false).unwrap();
+                }
+                event => panic!("unexpected event: {:?}", event),
+            }
+        };
+
+        // The VM keeps the list whole, which the plan turns back into `!=`s.
+        let types = hashmap! {
+            "A".to_owned() => hashmap! {
+                "name".to_owned() => Type::Base {
+                    class_tag: "String".to_owned()
+                }
+            }
+        };
+        let plan = build_filter_plan(types, vec![bindings.into()], "r", "A", false)?;
+        let [result_set] = &plan.result_sets[..] else {
+            panic!("unexpected plan: {:?}", plan)
+        };
+        let request = &result_set.requests[&result_set.result_id];
+        assert_eq!(request.class_tag, "A");
+        let mut names = vec![];
+        for constraint in &request.constraints {
+            assert_eq!(constraint.kind, ConstraintKind::Neq);
+            assert_eq!(constraint.field.as_deref(), Some("name"));
+            match &constraint.value {
+                ConstraintValue::Term(term) => names.push(term.value().as_string()?.to_owned()),
+                value => panic!("unexpected value: {:?}", value),
+            }
+        }
+        names.sort();
+        assert_eq!(names, vec!["a", "b"]);
+        Ok(())
+    }
+
     #[test]
     fn test_partition_equivs() {
         let pairs = vec![(1, 2), (2, 3), (4, 3), (5, 6), (8, 8), (6, 7)];
@@ -17,7 +17,7 @@ impl PolarVirtualMachine {
         let relevant_bindings = self.relevant_bindings(&[query]);
         let bindings_str = relevant_bindings
             .iter()
-            .map(|(var, val)| format!("{} = {}", var.0, val.to_polar()))
+            .map(|(var, val)| format!("{} = {}", var, val.to_polar()))
             .collect::<Vec<String>>()
             .join(", ");
         let query_str = query.to_polar();
@@ -368,14 +368,15 @@ impl Debugger {
 /// if we find it. otherwise, show that the variable is unbound.
 pub fn get_binding_for_var(name: &str, vm: &PolarVirtualMachine) -> Binding {
     let var = Symbol::new(name);
-    let bindings = simplify_bindings(vm.bindings(true)).unwrap();
+    let bindings = simplify_bindings(vm.bindings(true)).unwrap_or_default();
     bindings.get(&var).cloned().map_or_else(
         || {
             let prefix = KnowledgeBase::temp_prefix(name);
             bindings
                 .keys()
                 .filter_map(|k| {
-                    k.0.strip_prefix(&prefix)
+                    k.to_string()
+                        .strip_prefix(&prefix)
                         .and_then(|i| i.parse::<i64>().map_or(None, |i| Some((k, i))))
                 })
                 .max_by(|a, b| a.1.cmp(&b.1))
@@ -383,7 +384,7 @@ pub fn get_binding_for_var(name: &str, vm: &PolarVirtualMachine) -> Binding {
                     || Binding(sym!(name), Term::from(sym!("<unbound>"))),
                     |b| {
                         Binding(
-                            sym!(format!("{}@{}", name, b.0 .0).as_str()),
+                            sym!(format!("{}@{}", name, b.0).as_str()),
                             bindings.get(b.0).unwrap().clone(),
                         )
                     },
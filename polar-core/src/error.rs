@@ -43,6 +43,8 @@ impl PolarError {
             Runtime(ArithmeticError { .. }) => "RuntimeError::ArithmeticError",
             Runtime(IncompatibleBindings { .. }) => "RuntimeError::IncompatibleBindings",
             Runtime(QueryTimeout { .. }) => "RuntimeError::QueryTimeout",
+            Runtime(BudgetExhausted { .. }) => "RuntimeError::BudgetExhausted",
+            Runtime(Cancelled) => "RuntimeError::Cancelled",
             Runtime(StackOverflow { .. }) => "RuntimeError::StackOverflow",
             Runtime(TypeError { .. }) => "RuntimeError::TypeError",
             Runtime(UnhandledPartial { .. }) => "RuntimeError::UnhandledPartial",
@@ -268,6 +270,13 @@ pub enum RuntimeError {
     QueryTimeout {
         msg: String,
     },
+    /// A query did more work than its configured budget allows.
+    BudgetExhausted {
+        budget: Budget,
+        limit: u64,
+    },
+    /// A query was stopped through its `CancellationToken`.
+    Cancelled,
     Application {
         msg: String,
         stack_trace: String,
@@ -321,6 +330,8 @@ impl RuntimeError {
             // These errors never have context.
             StackOverflow { .. }
             | QueryTimeout { .. }
+            | BudgetExhausted { .. }
+            | Cancelled
             | IncompatibleBindings { .. }
             | DataFilteringFieldMissing { .. }
             | DataFilteringUnsupportedOp { .. }
@@ -360,6 +371,12 @@ impl fmt::Display for RuntimeError {
                 write!(f, "{}", msg)
             }
             Self::QueryTimeout { msg } => write!(f, "Query timeout: {}", msg),
+            Self::BudgetExhausted { budget, limit } => write!(
+                f,
+                "Query budget exhausted: the query exceeded its limit of {} {}",
+                limit, budget
+            ),
+            Self::Cancelled => write!(f, "Query cancelled"),
             Self::Application {
                 msg, stack_trace, ..
             } => {
@@ -431,6 +448,24 @@ The expression is: {expr}
     }
 }
 
+/// A limit on the work a query may do, set in `PolarConfig`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Budget {
+    Goals,
+    Backtracks,
+    ExternalCalls,
+}
+
+impl fmt::Display for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Goals => write!(f, "goals"),
+            Self::Backtracks => write!(f, "backtracks"),
+            Self::ExternalCalls => write!(f, "external calls"),
+        }
+    }
+}
+
 // NOTE(gj): both of these errors are only constructed/used in the `polar-c-api` crate.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum OperationalError {
@@ -535,7 +570,7 @@ impl ValidationError {
 
             // These errors track `rule_type`, from which we sometimes calculate the span.
             MissingRequiredRule { rule_type } => {
-                if rule_type.name.0 == "has_relation" {
+                if rule_type.name.as_str() == "has_relation" {
                     rule_type.span().zip(kb.get_rule_source(rule_type))
                 } else {
                     // TODO(gj): copy source info from the appropriate resource block term for
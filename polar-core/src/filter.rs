@@ -1,17 +1,18 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet},
     fmt::{self, Display, Formatter},
-    hash::Hash,
+    hash::{Hash, Hasher},
 };
 
 use crate::{
     data_filtering::{unregistered_field_error, unsupported_op_error, PartialResults, Type},
     error::{invalid_state_error, RuntimeError},
     events::ResultEvent,
+    partial::{invert_operation, sub_this},
     terms::*,
 };
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 type FilterResult<A> = core::result::Result<A, RuntimeError>;
 
@@ -33,11 +34,35 @@ type Set<A> = HashSet<A>;
 /// hold over the data source: for every record in the data source, if for some
 /// top-level set in `conditions` every inner condition holds on the record, then
 /// the record passes through the filter.
-#[derive(Clone, Eq, Debug, Serialize, PartialEq)]
+#[derive(Clone, Eq, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Filter {
-    root: TypeName,                  // the host already has this, so we could leave it off
-    relations: Set<Relation>,        // this & root determine the "joins" (or whatever)
-    conditions: Vec<Set<Condition>>, // disjunctive normal form
+    pub root: TypeName, // the host already has this, so we could leave it off
+    pub relations: Set<Relation>, // this & root determine the "joins" (or whatever)
+    pub conditions: Vec<Set<Condition>>, // disjunctive normal form
+}
+
+// Filters are hashed as sub-filters in conditions. Relations and conditions are
+// sets, so they're hashed in sorted order; conditions by their own hashes, as
+// values aren't ordered.
+impl Hash for Filter {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.root.hash(state);
+        let mut relations: Vec<_> = self.relations.iter().collect();
+        relations.sort_by_key(|Relation(src, field, dst)| (src, field, dst));
+        relations.hash(state);
+        for conditions in self.conditions.iter() {
+            let mut hashes: Vec<u64> = conditions
+                .iter()
+                .map(|condition| {
+                    let mut hasher = DefaultHasher::new();
+                    condition.hash(&mut hasher);
+                    hasher.finish()
+                })
+                .collect();
+            hashes.sort_unstable();
+            hashes.hash(state);
+        }
+    }
 }
 
 /// A named logical extension of a data set. Corresponds to a "join" in relational
@@ -48,37 +73,91 @@ pub struct Filter {
 /// For example, Relation("Foo", "bar", "Bar") represents a Relation
 /// from the `Foo` type to the `Bar` type, accessed using the `bar` field
 /// on `Foo`.
-#[derive(PartialEq, Eq, Debug, Serialize, Clone, Hash)]
-pub struct Relation(TypeName, FieldName, TypeName);
+///
+/// A many-to-many relation through a join type is two relations: from the source
+/// type to the join type through the relation's field, and from the join type to
+/// the target type through the field named by [`join_field`], e.g.,
+/// `Relation("User", "groups", "UserGroup")` and
+/// `Relation("UserGroup", "User.groups", "Group")`.
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Hash)]
+pub struct Relation(pub TypeName, pub FieldName, pub TypeName);
 
 /// A constraint that must hold for a record in the data source.
-#[derive(PartialEq, Eq, Debug, Serialize, Clone, Hash)]
-pub struct Condition(Datum, Comparison, Datum);
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Hash)]
+pub struct Condition(pub Datum, pub Comparison, pub Datum);
 
 /// The left or right side of a Condition.
-#[derive(PartialEq, Eq, Debug, Serialize, Clone, Hash)]
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Hash)]
 pub enum Datum {
     Field(Projection),
     Immediate(Value),
+    /// The records of a filter's root type that pass the filter. Used for negated
+    /// sub-filters, i.e., anti-joins: `Condition(Field(Projection(root, None)),
+    /// Comparison::Nin, Subquery(filter))`.
+    Subquery(Box<Filter>),
+    /// The records that reach a record passing the filter by following a relation
+    /// from a type to itself one or more times, e.g., the folders inside a folder
+    /// passing the filter through `parent`. Used for recursive rules:
+    /// `Condition(Field(Projection(typ, None)), Comparison::In,
+    /// Fixpoint(Relation(typ, field, typ), filter))`.
+    Fixpoint(Relation, Box<Filter>),
 }
 
 /// The comparison operation applied by a Condition.
-#[derive(PartialEq, Debug, Serialize, Copy, Clone, Eq, Hash)]
+#[derive(PartialEq, Debug, Serialize, Deserialize, Copy, Clone, Eq, Hash)]
 pub enum Comparison {
     Eq,
     Neq,
     In,
+    Nin,
+    Lt,
+    Leq,
+    Gt,
+    Geq,
 }
 
 /// An abstract "field reference" on a record from a named data source.
-#[derive(PartialEq, Eq, Debug, Serialize, Clone, Hash)]
-pub struct Projection(TypeName, Option<FieldName>);
+#[derive(PartialEq, Eq, Debug, Serialize, Deserialize, Clone, Hash)]
+pub struct Projection(pub TypeName, pub Option<FieldName>);
+
+/// A filter together with an account of how it was built.
+#[derive(PartialEq, Eq, Debug, Serialize, Clone)]
+pub struct ExplainedFilter {
+    pub filter: Filter,
+    pub explanation: Explanation,
+}
+
+/// How a filter was built from the partial results of a query.
+#[derive(PartialEq, Eq, Debug, Serialize, Clone, Default)]
+pub struct Explanation {
+    /// One entry for each partial result, in order.
+    pub results: Vec<ResultExplanation>,
+}
+
+/// How one partial result became part of a filter.
+#[derive(PartialEq, Eq, Debug, Serialize, Clone)]
+pub struct ResultExplanation {
+    /// The simplified constraints on the filtered variable.
+    pub partial: String,
+    /// The conjunctions left after distributing any disjunctions in `partial`.
+    /// Each one becomes a set of conditions in the filter.
+    pub conjunctions: Vec<ConjunctionExplanation>,
+}
+
+/// How one conjunction of constraints became a set of conditions.
+#[derive(PartialEq, Eq, Debug, Serialize, Clone)]
+pub struct ConjunctionExplanation {
+    pub constraints: Vec<String>,
+    /// The type of every variable or path of field lookups whose type is known,
+    /// e.g., `_this.org` => `Org`.
+    pub entities: BTreeMap<String, TypeName>,
+}
 
 type TypeInfo = Map<TypeName, Map<FieldName, Type>>;
 type VarTypes = Map<PathVar, TypeName>;
 
 /// Used to keep track of information for building a Filter
-#[derive(Default)]
+#[derive(Clone, Default)]
 struct FilterInfo {
     type_info: TypeInfo,
     entities: VarTypes,
@@ -120,38 +199,66 @@ impl PathVar {
                 pv.path.push(dot);
                 Ok(pv)
             }
-            Variable(Symbol(var)) => Ok(var.clone().into()),
+            Variable(var) => Ok(var.to_string().into()),
             _ => invalid_state_error(format!("PathVar::from_term({})", t.to_polar())),
         }
     }
 }
 
 impl Filter {
+    /// Build a filter, printing how it was built to stderr if `explain` is set.
     pub fn build(
         types: TypeInfo,
         ors: PartialResults,
         var: &str,
         class: &str,
+        explain: bool,
     ) -> FilterResult<Self> {
-        let explain = std::env::var("POLAR_EXPLAIN").is_ok();
-
         if explain {
-            eprintln!("\n===Data Filtering Query===");
-            eprintln!("\n==Bindings==")
+            let explained = Self::build_explained(types, ors, var, class)?;
+            eprintln!("{}", explained);
+            Ok(explained.filter)
+        } else {
+            Self::build_with(types, ors, var, class, None)
         }
+    }
 
-        let var = Symbol(var.to_string());
-        let filter = ors
+    /// Build a filter along with an explanation of how it was built.
+    pub fn build_explained(
+        types: TypeInfo,
+        ors: PartialResults,
+        var: &str,
+        class: &str,
+    ) -> FilterResult<ExplainedFilter> {
+        let mut explanation = Explanation::default();
+        let filter = Self::build_with(types, ors, var, class, Some(&mut explanation))?;
+        Ok(ExplainedFilter {
+            filter,
+            explanation,
+        })
+    }
+
+    fn build_with(
+        types: TypeInfo,
+        ors: PartialResults,
+        var: &str,
+        class: &str,
+        mut explanation: Option<&mut Explanation>,
+    ) -> FilterResult<Self> {
+        let var = Symbol::new(var);
+        Ok(ors
             .into_iter()
-            .map(|ands| Self::from_result_event(&types, ands, &var, class))
+            .map(|ands| {
+                let (filter, explained) =
+                    Self::from_result_event(&types, ands, &var, class, explanation.is_some())?;
+                if let (Some(explanation), Some(explained)) = (explanation.as_mut(), explained) {
+                    explanation.results.push(explained);
+                }
+                Ok(filter)
+            })
             .reduce(|l, r| Ok(l?.union(r?)))
-            .unwrap_or_else(|| Ok(Self::empty(class)))?;
-
-        if explain {
-            eprintln!("\n==Filter==\n{}", filter);
-        }
-
-        Ok(filter)
+            .unwrap_or_else(|| Ok(Self::empty(class)))?
+            .minimize())
     }
 
     fn from_result_event(
@@ -159,20 +266,28 @@ impl Filter {
         ands: ResultEvent,
         var: &Symbol,
         class: &str,
-    ) -> FilterResult<Self> {
-        ands.bindings
-            .get(var)
-            .map(|ands| Self::from_partial(types, ands, class))
-            .unwrap_or_else(|| invalid_state_error(format!("unbound variable: {}", var.0)))
+        explain: bool,
+    ) -> FilterResult<(Self, Option<ResultExplanation>)> {
+        let ands = match ands.bindings.get(var) {
+            Some(ands) => ands,
+            None => return invalid_state_error(format!("unbound variable: {}", var)),
+        };
+        let (filter, conjunctions) = Self::from_partial(types, ands, class, explain)?;
+        let explanation = explain.then(|| ResultExplanation {
+            partial: ands.to_polar(),
+            conjunctions,
+        });
+        Ok((filter, explanation))
     }
 
-    fn from_partial(types: &TypeInfo, ands: &Term, class: &str) -> FilterResult<Self> {
+    fn from_partial(
+        types: &TypeInfo,
+        ands: &Term,
+        class: &str,
+        explain: bool,
+    ) -> FilterResult<(Self, Vec<ConjunctionExplanation>)> {
         use {Datum::*, Operator::*, Value::*};
 
-        if std::env::var("POLAR_EXPLAIN").is_ok() {
-            eprintln!("{}", ands.to_polar());
-        }
-
         match ands.value() {
             // most of the time we're dealing with expressions from the
             // simplifier.
@@ -183,26 +298,77 @@ impl Filter {
                 .iter()
                 .map(|and| Ok(and.value().as_expression()?.clone()))
                 .collect::<FilterResult<Vec<_>>>()
-                .and_then(|ands| FilterInfo::build_filter(types.clone(), ands, class)),
+                .and_then(|ands| Self::explain_conjunction(types, ands, class, explain)),
 
             // sometimes we get an instance back. that means the variable
             // is exactly this instance, so return a filter that matches it.
-            i @ ExternalInstance(_) => Ok(Filter {
-                root: class.to_string(),
-                relations: HashSet::new(),
-                conditions: vec![singleton(Condition(
-                    Field(Projection(class.to_string(), None)),
-                    Comparison::Eq,
-                    Immediate(i.clone()),
-                ))],
-            }),
+            i @ ExternalInstance(_) => Ok((
+                Filter {
+                    root: class.to_string(),
+                    relations: HashSet::new(),
+                    conditions: vec![singleton(Condition(
+                        Field(Projection(class.to_string(), None)),
+                        Comparison::Eq,
+                        Immediate(i.clone()),
+                    ))],
+                },
+                vec![],
+            )),
 
             // oops, we don't know how to handle this!
             _ => invalid_state_error(ands.to_polar()),
         }
     }
 
-    fn empty(class: &str) -> Self {
+    /// Build a filter from a conjunction that may contain disjunctions, which are
+    /// distributed into separate conjunctions of the result.
+    fn from_conjunction(types: &TypeInfo, ands: Vec<Operation>, class: &str) -> FilterResult<Self> {
+        Self::explain_conjunction(types, ands, class, false).map(|(filter, _)| filter)
+    }
+
+    /// `from_conjunction`, also explaining each distributed conjunction if `explain`
+    /// is set.
+    fn explain_conjunction(
+        types: &TypeInfo,
+        ands: Vec<Operation>,
+        class: &str,
+        explain: bool,
+    ) -> FilterResult<(Self, Vec<ConjunctionExplanation>)> {
+        let mut explanations = vec![];
+        let filter = distribute_ors(ands)?
+            .into_iter()
+            .map(|ands| {
+                let constraints = match explain {
+                    true => ands.iter().map(|op| op.to_polar()).collect(),
+                    false => vec![],
+                };
+                let (filter, entities) = FilterInfo::build_filter(types.clone(), ands, class)?;
+                if explain {
+                    let entities = entities
+                        .into_iter()
+                        .map(|(PathVar { var, path }, typ)| {
+                            (
+                                std::iter::once(var)
+                                    .chain(path)
+                                    .collect::<Vec<_>>()
+                                    .join("."),
+                                typ,
+                            )
+                        })
+                        .collect();
+                    explanations.push(ConjunctionExplanation {
+                        constraints,
+                        entities,
+                    });
+                }
+                Ok(filter)
+            })
+            .reduce(|l, r| Ok(l?.union(r?)))
+            .unwrap_or_else(|| Ok(Self::empty(class)))?;
+        Ok((filter, explanations))
+    }
+
+    pub(crate) fn empty(class: &str) -> Self {
         use {Datum::Immediate, Value::Boolean};
         Self {
             root: class.to_string(),
@@ -220,22 +386,160 @@ impl Filter {
         other.relations.extend(self.relations);
         other
     }
+
+    /// The relations in an order where each one's source type is the root or the
+    /// target of an earlier relation, so they can be joined one at a time.
+    pub(crate) fn ordered_relations(&self) -> FilterResult<Vec<&Relation>> {
+        let mut joined = singleton(self.root.as_str());
+        let mut pending: Vec<&Relation> = self.relations.iter().collect();
+        pending.sort_by_key(|Relation(src, field, dst)| (src, field, dst));
+
+        let mut ordered = vec![];
+        while !pending.is_empty() {
+            match pending
+                .iter()
+                .position(|Relation(src, _, _)| joined.contains(src.as_str()))
+            {
+                Some(next) => {
+                    let relation = pending.remove(next);
+                    joined.insert(relation.2.as_str());
+                    ordered.push(relation);
+                }
+                None => {
+                    return invalid_state_error(format!(
+                        "relation `{}` is not connected to `{}`",
+                        pending[0], self.root
+                    ))
+                }
+            }
+        }
+        Ok(ordered)
+    }
+}
+
+/// Distribute the disjunctions in a conjunction over it, e.g., `a and (b or c)`
+/// becomes `[[a, b], [a, c]]`.
+///
+/// The inverter negates `not (x in y.z and x.a = 1)` one constraint at a time, giving
+/// `not x in y.z or x.a != 1` where `x` occurs nowhere else. Such a disjunction
+/// means there is no `x` at all, so it's left in place to become an anti-join.
+fn distribute_ors(ands: Vec<Operation>) -> FilterResult<Vec<Vec<Operation>>> {
+    let ands = flatten_ands(ands)?;
+    let mut conjunctions = vec![vec![]];
+    for (i, and) in ands.iter().enumerate() {
+        let alternatives = match and.operator {
+            Operator::Or if !has_local_variables(and, i, &ands) => and
+                .args
+                .iter()
+                .filter_map(|or| disjunct(or).transpose())
+                .collect::<FilterResult<Vec<_>>>()?,
+            _ => vec![vec![and.clone()]],
+        };
+        conjunctions = conjunctions
+            .into_iter()
+            .flat_map(|conjunction| {
+                alternatives.iter().map(move |alternative| {
+                    let mut conjunction = conjunction.clone();
+                    conjunction.extend(alternative.iter().cloned());
+                    conjunction
+                })
+            })
+            .collect();
+    }
+    Ok(conjunctions)
+}
+
+/// Splice the arguments of nested conjunctions into `ands`.
+fn flatten_ands(ands: Vec<Operation>) -> FilterResult<Vec<Operation>> {
+    let mut flat = vec![];
+    for and in ands {
+        match and.operator {
+            Operator::And => {
+                let args = and
+                    .args
+                    .iter()
+                    .map(|arg| Ok(arg.value().as_expression()?.clone()))
+                    .collect::<FilterResult<Vec<_>>>()?;
+                flat.extend(flatten_ands(args)?);
+            }
+            _ => flat.push(and),
+        }
+    }
+    Ok(flat)
+}
+
+/// The conjuncts of one side of a disjunction, or `None` if it's false.
+fn disjunct(or: &Term) -> FilterResult<Option<Vec<Operation>>> {
+    use {Operator::*, Value::*};
+    match or.value() {
+        Boolean(true) => Ok(Some(vec![])),
+        Boolean(false) => Ok(None),
+        Expression(Operation {
+            operator: And,
+            args,
+        }) => args
+            .iter()
+            .map(|and| Ok(and.value().as_expression()?.clone()))
+            .collect::<FilterResult<Vec<_>>>()
+            .map(Some),
+        Expression(op) => match (op.operator, op.args.first().map(|arg| arg.value())) {
+            // We only filter records of the types they're asserted to be.
+            (Not, Some(Expression(Operation { operator: Isa, .. }))) => Ok(None),
+            _ => Ok(Some(vec![op.clone()])),
+        },
+        _ => invalid_state_error(or.to_polar()),
+    }
+}
+
+/// Whether the `i`th conjunct has variables that don't occur in any other.
+fn has_local_variables(and: &Operation, i: usize, ands: &[Operation]) -> bool {
+    and.variables().iter().any(|var| {
+        !var.is_this_var()
+            && ands
+                .iter()
+                .enumerate()
+                .all(|(j, other)| j == i || !other.variables().contains(var))
+    })
 }
 
 impl FilterInfo {
     /// try to match a type and a field name with a relation
     fn get_relation_def(&mut self, typ: &str, dot: &str) -> Option<Relation> {
-        if let Some(Type::Relation {
-            other_class_tag, ..
-        }) = self.type_info.get(typ).and_then(|map| map.get(dot))
-        {
-            Some(Relation(
+        match self.type_info.get(typ).and_then(|map| map.get(dot)) {
+            Some(Type::Relation {
+                other_class_tag, ..
+            })
+            | Some(Type::JoinRelation {
+                other_class_tag, ..
+            }) => Some(Relation(
                 typ.to_string(),
                 dot.to_string(),
                 other_class_tag.to_string(),
-            ))
-        } else {
-            None
+            )),
+            _ => None,
+        }
+    }
+
+    /// The join type of a many-to-many relation.
+    fn get_join_type(&self, Relation(typ, dot, _): &Relation) -> Option<&str> {
+        match self.type_info.get(typ).and_then(|map| map.get(dot)) {
+            Some(Type::JoinRelation { join_class_tag, .. }) => Some(join_class_tag),
+            _ => None,
+        }
+    }
+
+    /// Add a relation, or both steps of a many-to-many relation.
+    fn add_relation(&mut self, rel: Relation) {
+        match self.get_join_type(&rel).map(str::to_string) {
+            Some(join) => {
+                let Relation(src, dot, dst) = rel;
+                let through = join_field(&src, &dot);
+                self.relations.insert(Relation(src, dot, join.clone()));
+                self.relations.insert(Relation(join, through, dst));
+            }
+            None => {
+                self.relations.insert(rel);
+            }
         }
     }
 
@@ -265,7 +569,7 @@ impl FilterInfo {
                     typ = right.clone();
                     pv.path.push(name.clone());
                     self.entities.insert(pv.clone(), right.clone());
-                    self.relations.insert(rel);
+                    self.add_relation(rel);
                 }
             }
         }
@@ -282,7 +586,7 @@ impl FilterInfo {
                 let tag = rel.2.clone();
                 pv.path.push(rel.1.clone());
                 self.entities.insert(pv, tag.clone());
-                self.relations.insert(rel);
+                self.add_relation(rel);
                 Ok(Projection(tag, None))
             }
         }
@@ -304,6 +608,9 @@ impl FilterInfo {
                 match self.type_info.get(typ)?.get(dot)? {
                     Type::Relation {
                         other_class_tag, ..
+                    }
+                    | Type::JoinRelation {
+                        other_class_tag, ..
                     } => typ = other_class_tag,
                     _ => return None,
                 }
@@ -318,6 +625,12 @@ impl FilterInfo {
     /// digest a conjunct from the partial results & add a new constraint.
     fn add_constraint(&mut self, op: Operation) -> FilterResult<()> {
         use {Datum::*, Operator::*};
+        match op.operator {
+            Not => return self.add_not_in_condition(op),
+            Or => return self.add_anti_join(op.args.clone()),
+            _ => (),
+        }
+
         let (left, right) = (self.term2datum(&op.args[0])?, self.term2datum(&op.args[1])?);
         match op.operator {
             Unify => self.add_eq_condition(left, right),
@@ -329,6 +642,10 @@ impl FilterInfo {
                 }
                 _ => self.add_in_condition(left, right),
             },
+            Lt => self.add_ordering_condition(op, left, Comparison::Lt, right),
+            Leq => self.add_ordering_condition(op, left, Comparison::Leq, right),
+            Gt => self.add_ordering_condition(op, left, Comparison::Gt, right),
+            Geq => self.add_ordering_condition(op, left, Comparison::Geq, right),
             _ => unsupported_op_error(op),
         }
     }
@@ -354,6 +671,175 @@ impl FilterInfo {
         self.add_condition(left, Comparison::In, right)
     }
 
+    /// `not x in y`, the only negation the simplifier leaves in partial results.
+    /// If `y` is a collection field that's `x NOT IN y`, but if it's a relation to
+    /// other records, there must not be any related record equal to `x`.
+    fn add_not_in_condition(&mut self, op: Operation) -> FilterResult<()> {
+        use Datum::*;
+        let args = match op.args[0].value().as_expression() {
+            Ok(Operation {
+                operator: Operator::In,
+                args,
+            }) => args.clone(),
+            _ => return unsupported_op_error(op),
+        };
+        // Look before we leap: resolving `y` adds its relations to the filter.
+        let mut info = self.clone();
+        match info.term2datum(&args[1])? {
+            Field(Projection(_, None)) => self.add_anti_join(vec![Term::from(op)]),
+            right => {
+                *self = info;
+                let left = self.term2datum(&args[0])?;
+                self.add_condition(left, Comparison::Nin, right)
+            }
+        }
+    }
+
+    /// The related records `y` of `not x in y`, or `None` if `y` isn't a relation.
+    fn negated_relation(&self, op: &Operation) -> Option<Datum> {
+        match op.args[0].value().as_expression() {
+            Ok(Operation {
+                operator: Operator::In,
+                args,
+            }) => match self.clone().term2datum(&args[1]) {
+                Ok(records @ Datum::Field(Projection(_, None))) => Some(records),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Require that there are no records satisfying the negations of `ors`, e.g.,
+    /// `not x in y.z or x.a != 1` means there's no `x` in `y.z` with `x.a = 1`.
+    fn add_anti_join(&mut self, ors: TermList) -> FilterResult<()> {
+        let root = match self.get_type(PathVar::from(String::from("_this"))) {
+            Some(root) => root,
+            None => return invalid_state_error(String::from("unknown type for `_this`")),
+        };
+        let mut related = vec![];
+        let ands = ors
+            .iter()
+            .map(|or| {
+                let or = or.value().as_expression()?;
+                match or.operator {
+                    Operator::Not => {
+                        related.extend(self.negated_relation(or));
+                        Ok(invert_operation(or.clone()))
+                    }
+                    Operator::Unify
+                    | Operator::Eq
+                    | Operator::Neq
+                    | Operator::Lt
+                    | Operator::Leq
+                    | Operator::Gt
+                    | Operator::Geq
+                    | Operator::In => Ok(invert_operation(or.clone())),
+                    _ => unsupported_op_error(or.clone()),
+                }
+            })
+            .collect::<FilterResult<Vec<_>>>()?;
+        let mut subquery = Filter::from_conjunction(&self.type_info, ands, &root)?;
+        // `x in y.z` adds no condition on `x` by itself, but the sub-filter needs
+        // there to be such a record, which `LEFT JOIN`s don't ensure.
+        for conditions in subquery.conditions.iter_mut() {
+            conditions.extend(
+                related
+                    .iter()
+                    .map(|records| Condition(records.clone(), Comparison::Eq, records.clone())),
+            );
+        }
+        self.add_condition(
+            Datum::Field(Projection(root, None)),
+            Comparison::Nin,
+            Datum::Subquery(Box::new(subquery)),
+        )
+    }
+
+    /// `y in x.f+`, where `y` stands for the records reached by following `f` from
+    /// `x` one or more times. The constraints on `y` become a sub-filter, and `x`
+    /// must reach a record passing it. Returns the constraints that are left.
+    fn add_closures(&mut self, mut ops: Set<Operation>) -> FilterResult<Set<Operation>> {
+        let closures: Vec<_> = ops
+            .iter()
+            .filter(|op| as_closure(op).is_some())
+            .cloned()
+            .collect();
+        for op in closures {
+            // Already part of the sub-filter of another closure.
+            if !ops.remove(&op) {
+                continue;
+            }
+            let (ancestor, object, field) = as_closure(&op).unwrap();
+            let object = self.term2datum(object)?;
+            let relation = match &object {
+                Datum::Field(Projection(typ, None)) => match self.get_relation_def(typ, field) {
+                    Some(relation)
+                        if &relation.2 == typ && self.get_join_type(&relation).is_none() =>
+                    {
+                        relation
+                    }
+                    Some(_) => return unsupported_op_error(op),
+                    None => return unregistered_field_error(typ, field),
+                },
+                _ => return unsupported_op_error(op),
+            };
+
+            // Gather the constraints connected to `y`, which may not involve `x`.
+            let mut vars = singleton(ancestor.clone());
+            let mut ands = vec![];
+            loop {
+                let (connected, rest): (Set<_>, Set<_>) = ops
+                    .into_iter()
+                    .partition(|op| op.variables().iter().any(|var| vars.contains(var)));
+                ops = rest;
+                if connected.is_empty() {
+                    break;
+                }
+                for and in connected {
+                    vars.extend(and.variables());
+                    ands.push(and);
+                }
+            }
+            let mut correlated = HashSet::new();
+            op.args[1].variables(&mut correlated);
+            if vars.contains(&Symbol::new("_this")) || !vars.is_disjoint(&correlated) {
+                return unsupported_op_error(op);
+            }
+
+            let ands = ands
+                .into_iter()
+                .map(|and| {
+                    let and = sub_this(ancestor.clone(), Term::from(and));
+                    Ok(and.value().as_expression()?.clone())
+                })
+                .collect::<FilterResult<Vec<_>>>()?;
+            let filter = Filter::from_conjunction(&self.type_info, ands, &relation.0)?;
+            self.add_condition(
+                object,
+                Comparison::In,
+                Datum::Fixpoint(relation, Box::new(filter)),
+            )?;
+        }
+        Ok(ops)
+    }
+
+    /// Records themselves aren't ordered, only their fields.
+    fn add_ordering_condition(
+        &mut self,
+        op: Operation,
+        left: Datum,
+        cmp: Comparison,
+        right: Datum,
+    ) -> FilterResult<()> {
+        use Datum::*;
+        match (&left, &right) {
+            (Field(Projection(_, None)), _) | (_, Field(Projection(_, None))) => {
+                unsupported_op_error(op)
+            }
+            _ => self.add_condition(left, cmp, right),
+        }
+    }
+
     /// Validate FilterInfo before constructing a Filter
     fn validate(self, root: &str) -> FilterResult<Self> {
         let mut set = singleton(root);
@@ -375,10 +861,10 @@ impl FilterInfo {
         // find pairs of implicitly equal variables
         let equivs = ops.iter().filter_map(|Operation { operator, args }| {
             use Operator::*;
-            let (l, r) = (
-                PathVar::from_term(&args[0]).ok()?,
-                PathVar::from_term(&args[1]).ok()?,
-            );
+            let (l, r) = match &args[..] {
+                [l, r] => (PathVar::from_term(l).ok()?, PathVar::from_term(r).ok()?),
+                _ => return None,
+            };
             match operator {
                 Unify | In => Some((l, r)),
                 _ => None,
@@ -413,6 +899,8 @@ impl FilterInfo {
                 self.entities.insert(k, t);
             });
 
+        let ops = self.add_closures(ops)?;
+
         // every variable that needs a type
         // should now hopefully have a type.
         // now add a condition for each partial.
@@ -428,7 +916,7 @@ impl FilterInfo {
         type_info: TypeInfo,
         parts: Vec<Operation>,
         class: &str,
-    ) -> FilterResult<Filter> {
+    ) -> FilterResult<(Filter, VarTypes)> {
         let entities =
             std::iter::once((PathVar::from(String::from("_this")), class.to_string())).collect();
 
@@ -440,6 +928,7 @@ impl FilterInfo {
         let Self {
             conditions,
             relations,
+            entities,
             ..
         } = Self {
             type_info,
@@ -448,11 +937,31 @@ impl FilterInfo {
         }
         .with_constraints(othas, class)?;
 
-        Ok(Filter {
+        let filter = Filter {
             relations,
             conditions: vec![conditions],
             root: class.to_string(),
-        })
+        };
+        Ok((filter, entities))
+    }
+}
+
+impl Display for ExplainedFilter {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
+        writeln!(f, "\n===Data Filtering Query===")?;
+        for (i, result) in self.explanation.results.iter().enumerate() {
+            writeln!(f, "\n==Result {}==\n{}", i, result.partial)?;
+            for (j, conjunction) in result.conjunctions.iter().enumerate() {
+                writeln!(f, "  =Conjunction {}=", j)?;
+                for constraint in &conjunction.constraints {
+                    writeln!(f, "    {}", constraint)?;
+                }
+                for (var, typ) in &conjunction.entities {
+                    writeln!(f, "    {}: {}", var, typ)?;
+                }
+            }
+        }
+        write!(f, "\n==Filter==\n{}", self.filter)
     }
 }
 
@@ -504,6 +1013,11 @@ impl Display for Comparison {
                 Eq => "=",
                 Neq => "!=",
                 In => "IN",
+                Nin => "NOT IN",
+                Lt => "<",
+                Leq => "<=",
+                Gt => ">",
+                Geq => ">=",
             }
         )
     }
@@ -516,10 +1030,23 @@ impl Display for Datum {
             Immediate(val) => write!(f, "{}", val.to_polar()),
             Field(Projection(typ, None)) => write!(f, "{}", typ),
             Field(Projection(typ, Some(field))) => write!(f, "{}.{}", typ, field),
+            Subquery(filter) => write!(f, "({})", collapse(filter)),
+            Fixpoint(Relation(typ, field, _), filter) => {
+                write!(f, "{}.{} ({})", typ, closure_field(field), collapse(filter))
+            }
         }
     }
 }
 
+/// A filter on one line, for nesting in conditions.
+fn collapse(filter: &Filter) -> String {
+    filter
+        .to_string()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 impl Display for Condition {
     fn fmt(&self, f: &mut Formatter) -> Result<(), fmt::Error> {
         let Condition(l, op, r) = self;
@@ -534,6 +1061,36 @@ impl Display for Relation {
     }
 }
 
+/// The name of the second step of the many-to-many relation `field` of `typ`,
+/// from the join type to the target type, e.g., `User.groups`. It can't be the
+/// name of a field of the join type, or of another relation through it.
+pub fn join_field(typ: &str, field: &str) -> String {
+    format!("{}.{}", typ, field)
+}
+
+/// The name under which partial results refer to the transitive closure of the
+/// relation `field`, e.g., `y in x.parent+`.
+pub(crate) fn closure_field(field: &str) -> String {
+    format!("{}+", field)
+}
+
+/// The parts of `y in x.field+`.
+fn as_closure(op: &Operation) -> Option<(&Symbol, &Term, &str)> {
+    match (op.operator, &op.args[..]) {
+        (Operator::In, [var, dot]) => match dot.value().as_expression() {
+            Ok(Operation {
+                operator: Operator::Dot,
+                args,
+            }) => {
+                let field = args[1].value().as_string().ok()?.strip_suffix('+')?;
+                Some((var.value().as_symbol().ok()?, &args[0], field))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 pub fn singleton<X>(x: X) -> Set<X>
 where
     X: Hash + Eq,
@@ -579,7 +1136,7 @@ mod test {
                 term!(op!(Unify, term!(1), term!(op!(Dot, term!(op!(Dot, term!(op!(Dot, var!("_this"), str!("foo"))), str!("resource"))), str!("foo")))))))
         })];
 
-        match Filter::build(types, ors, "resource", "Resource") {
+        match Filter::build(types, ors, "resource", "Resource", false) {
             Err(RuntimeError::InvalidState { msg })
                 if &msg == "Type `Resource` occurs more than once as the target of a relation" => {}
             _ => panic!("unexpected"),
@@ -612,7 +1169,7 @@ mod test {
             ))
         })];
 
-        let filter = Filter::build(types, ors, "resource", "Resource").unwrap();
+        let filter = Filter::build(types.clone(), ors, "resource", "Resource", false).unwrap();
 
         let Filter {
             root,
@@ -634,5 +1191,376 @@ mod test {
                 Condition(Datum::Immediate(value!(1)), Comparison::Eq, Datum::Field(Projection(String::from("Foo"), Some(String::from("y")))))
             }]
         );
+
+        // `x in _this.foos` by itself adds no condition.
+        let ors = vec![ResultEvent::new(hashmap! {
+            sym!("resource") => term!(op!(And,
+                term!(op!(Isa, var!("_this"), term!(pattern!(instance!("Resource"))))),
+                term!(op!(In, var!("x"), term!(op!(Dot, var!("_this"), str!("foos")))))
+            ))
+        })];
+        let filter = Filter::build(types, ors, "resource", "Resource", false).unwrap();
+        assert_eq!(filter.conditions, vec![Set::new()]);
+    }
+    #[test]
+    fn test_explain() {
+        let s = String::from;
+        let types = hashmap! {
+            s("Resource") => hashmap!{
+                s("foos") => Type::Relation {
+                   kind: s("many"),
+                   my_field: s("_"),
+                   other_field: s("_"),
+                   other_class_tag: s("Foo")
+                }
+            },
+            s("Foo") => hashmap!{
+                s("y") => Type::Base {
+                    class_tag: s("Integer")
+                }
+            }
+        };
+
+        let ors = vec![ResultEvent::new(hashmap! {
+            sym!("resource") => term!(op!(And,
+                term!(op!(Isa, var!("_this"), term!(pattern!(instance!("Resource"))))),
+                term!(op!(In, var!("x"), term!(op!(Dot, var!("_this"), str!("foos"))))),
+                term!(op!(Or,
+                    term!(op!(Unify, term!(1), term!(op!(Dot, var!("x"), str!("y"))))),
+                    term!(op!(Unify, term!(2), term!(op!(Dot, var!("x"), str!("y")))))))
+            ))
+        })];
+
+        let ExplainedFilter {
+            filter,
+            explanation,
+        } = Filter::build_explained(types.clone(), ors.clone(), "resource", "Resource").unwrap();
+        assert_eq!(
+            filter,
+            Filter::build(types, ors, "resource", "Resource", false).unwrap()
+        );
+
+        let ResultExplanation {
+            partial,
+            conjunctions,
+        } = match &explanation.results[..] {
+            [result] => result.clone(),
+            results => panic!("expected one result, got {:?}", results),
+        };
+        assert_eq!(
+            partial,
+            "_this matches Resource{} and x in _this.foos and (1 = x.y or 2 = x.y)"
+        );
+        let entities = btreemap! {
+            s("_this") => s("Resource"),
+            s("_this.foos") => s("Foo"),
+            s("x") => s("Foo"),
+        };
+        assert_eq!(
+            conjunctions,
+            vec![
+                ConjunctionExplanation {
+                    constraints: vec![
+                        s("_this matches Resource{}"),
+                        s("x in _this.foos"),
+                        s("1 = x.y")
+                    ],
+                    entities: entities.clone(),
+                },
+                ConjunctionExplanation {
+                    constraints: vec![
+                        s("_this matches Resource{}"),
+                        s("x in _this.foos"),
+                        s("2 = x.y")
+                    ],
+                    entities,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ordering_and_nin() {
+        let s = String::from;
+        let types = hashmap! {
+            s("Resource") => hashmap!{
+                s("foo") => Type::Relation {
+                   kind: s("one"),
+                   my_field: s("_"),
+                   other_field: s("_"),
+                   other_class_tag: s("Foo")
+                }
+            },
+        };
+        let field = |typ: &str, field: &str| {
+            Datum::Field(Projection(typ.to_string(), Some(field.to_string())))
+        };
+
+        let ors = vec![ResultEvent::new(hashmap! {
+            sym!("resource") => term!(op!(And,
+                term!(op!(Leq, term!(op!(Dot, var!("_this"), str!("level"))), term!(3))),
+                term!(op!(Gt, term!(op!(Dot, var!("_this"), str!("created_at"))), term!(op!(Dot, term!(op!(Dot, var!("_this"), str!("foo"))), str!("created_at"))))),
+                term!(op!(Not, term!(op!(In, str!("admin"), term!(op!(Dot, var!("_this"), str!("tags")))))))
+            ))
+        })];
+        let filter = Filter::build(types.clone(), ors, "resource", "Resource", false).unwrap();
+        assert_eq!(
+            filter.relations,
+            hashset! { Relation(s("Resource"), s("foo"), s("Foo")) }
+        );
+        assert_eq!(
+            filter.conditions,
+            vec![hashset! {
+                Condition(field("Resource", "level"), Comparison::Leq, Datum::Immediate(value!(3))),
+                Condition(field("Resource", "created_at"), Comparison::Gt, field("Foo", "created_at")),
+                Condition(Datum::Immediate(value!("admin")), Comparison::Nin, field("Resource", "tags")),
+            }]
+        );
+
+        // Records aren't ordered.
+        let ors = vec![ResultEvent::new(hashmap! {
+            sym!("resource") => term!(op!(And,
+                term!(op!(Lt, var!("_this"), term!(op!(Dot, var!("_this"), str!("foo")))))
+            ))
+        })];
+        assert!(matches!(
+            Filter::build(types, ors, "resource", "Resource", false),
+            Err(RuntimeError::DataFilteringUnsupportedOp { .. })
+        ));
+    }
+
+    #[test]
+    fn test_not() {
+        let s = String::from;
+        let types = hashmap! {
+            s("Resource") => hashmap!{
+                s("foos") => Type::Relation {
+                   kind: s("many"),
+                   my_field: s("_"),
+                   other_field: s("_"),
+                   other_class_tag: s("Foo")
+                }
+            },
+        };
+        let field = |typ: &str, field: &str| {
+            Datum::Field(Projection(typ.to_string(), Some(field.to_string())))
+        };
+        let isa = || {
+            term!(op!(
+                Isa,
+                var!("_this"),
+                term!(pattern!(instance!("Resource")))
+            ))
+        };
+        let this = || Datum::Field(Projection(s("Resource"), None));
+
+        // `not resource.id in [1, 2] and not (x in resource.foos and x.y = 1)`
+        let ors = vec![ResultEvent::new(hashmap! {
+            sym!("resource") => term!(op!(And,
+                isa(),
+                term!(op!(And,
+                    term!(op!(Or, term!(op!(Not, isa())), term!(op!(Neq, term!(1), term!(op!(Dot, var!("_this"), str!("id"))))))),
+                    term!(op!(Or, term!(op!(Not, isa())), term!(op!(Neq, term!(2), term!(op!(Dot, var!("_this"), str!("id")))))))
+                )),
+                term!(op!(Or,
+                    term!(op!(Not, term!(op!(In, var!("x"), term!(op!(Dot, var!("_this"), str!("foos"))))))),
+                    term!(op!(Neq, term!(1), term!(op!(Dot, var!("x"), str!("y")))))
+                ))
+            ))
+        })];
+        let filter = Filter::build(types.clone(), ors, "resource", "Resource", false).unwrap();
+        assert!(filter.relations.is_empty());
+        let subquery = Filter {
+            root: s("Resource"),
+            relations: singleton(Relation(s("Resource"), s("foos"), s("Foo"))),
+            conditions: vec![singleton(Condition(
+                Datum::Immediate(value!(1)),
+                Comparison::Eq,
+                field("Foo", "y"),
+            ))],
+        };
+        assert_eq!(
+            filter.conditions,
+            vec![hashset! {
+                Condition(Datum::Immediate(value!(1)), Comparison::Neq, field("Resource", "id")),
+                Condition(Datum::Immediate(value!(2)), Comparison::Neq, field("Resource", "id")),
+                Condition(this(), Comparison::Nin, Datum::Subquery(Box::new(subquery))),
+            }]
+        );
+
+        // `not (resource.a = 1 and resource.b = 2)` is a disjunction.
+        let ors = vec![ResultEvent::new(hashmap! {
+            sym!("resource") => term!(op!(And,
+                term!(op!(Or,
+                    term!(op!(Neq, term!(1), term!(op!(Dot, var!("_this"), str!("a"))))),
+                    term!(op!(Neq, term!(2), term!(op!(Dot, var!("_this"), str!("b")))))
+                ))
+            ))
+        })];
+        let filter = Filter::build(types.clone(), ors, "resource", "Resource", false).unwrap();
+        assert_eq!(filter.conditions.len(), 2);
+        assert!(filter.conditions.contains(&singleton(Condition(
+            Datum::Immediate(value!(2)),
+            Comparison::Neq,
+            field("Resource", "b")
+        ))));
+
+        // A related record that's excluded.
+        let ors = vec![ResultEvent::new(hashmap! {
+            sym!("resource") => term!(op!(And,
+                isa(),
+                term!(op!(Not, term!(op!(In, var!("foo"), term!(op!(Dot, var!("_this"), str!("foos")))))))
+            ))
+        })];
+        let filter = Filter::build(types, ors, "resource", "Resource", false).unwrap();
+        assert!(filter.relations.is_empty());
+        match &filter.conditions[..] {
+            [conditions] => match conditions.iter().collect::<Vec<_>>()[..] {
+                [Condition(left, Comparison::Nin, Datum::Subquery(subquery))] => {
+                    assert_eq!(left, &this());
+                    assert_eq!(subquery.root, "Resource");
+                    assert_eq!(
+                        subquery.relations,
+                        singleton(Relation(s("Resource"), s("foos"), s("Foo")))
+                    );
+                    // A resource with some foo.
+                    let foo = Datum::Field(Projection(s("Foo"), None));
+                    assert_eq!(
+                        subquery.conditions,
+                        vec![singleton(Condition(foo.clone(), Comparison::Eq, foo))]
+                    );
+                }
+                _ => panic!("unexpected: {}", filter),
+            },
+            _ => panic!("unexpected: {}", filter),
+        }
+    }
+
+    #[test]
+    fn test_hash() {
+        let hash = |filter: &Filter| {
+            let mut hasher = DefaultHasher::new();
+            filter.hash(&mut hasher);
+            hasher.finish()
+        };
+        let field = |name: &str| Datum::Field(Projection("Foo".into(), Some(name.into())));
+        let filter = |conditions: Vec<Condition>| Filter {
+            root: "Foo".into(),
+            relations: Set::new(),
+            conditions: vec![conditions.into_iter().collect()],
+        };
+        let (a, b) = (
+            Condition(field("a"), Comparison::Eq, Datum::Immediate(value!(1))),
+            Condition(field("b"), Comparison::Eq, Datum::Immediate(value!(1))),
+        );
+        assert_eq!(
+            hash(&filter(vec![a.clone(), b.clone()])),
+            hash(&filter(vec![b.clone(), a.clone()]))
+        );
+        assert_ne!(hash(&filter(vec![a])), hash(&filter(vec![b])));
+    }
+
+    #[test]
+    fn test_fixpoint() {
+        let s = String::from;
+        let types = hashmap! {
+            s("Folder") => hashmap!{
+                s("parent") => Type::Relation {
+                   kind: s("one"),
+                   my_field: s("parent_id"),
+                   other_field: s("id"),
+                   other_class_tag: s("Folder")
+                }
+            },
+        };
+        let partial = |owner| {
+            vec![ResultEvent::new(hashmap! {
+                sym!("folder") => term!(op!(And,
+                    term!(op!(Isa, var!("_this"), term!(pattern!(instance!("Folder"))))),
+                    term!(op!(In, var!("y"), term!(op!(Dot, var!("_this"), str!("parent+"))))),
+                    term!(op!(Unify, owner, term!(op!(Dot, var!("y"), str!("owner")))))
+                ))
+            })]
+        };
+
+        // `y in folder.parent+ and "alice" = y.owner`
+        let filter = Filter::build(
+            types.clone(),
+            partial(term!("alice")),
+            "folder",
+            "Folder",
+            false,
+        )
+        .unwrap();
+        assert!(filter.relations.is_empty());
+        let ancestors = Filter {
+            root: s("Folder"),
+            relations: HashSet::new(),
+            conditions: vec![singleton(Condition(
+                Datum::Immediate(value!("alice")),
+                Comparison::Eq,
+                Datum::Field(Projection(s("Folder"), Some(s("owner")))),
+            ))],
+        };
+        assert_eq!(
+            filter.conditions,
+            vec![singleton(Condition(
+                Datum::Field(Projection(s("Folder"), None)),
+                Comparison::In,
+                Datum::Fixpoint(
+                    Relation(s("Folder"), s("parent"), s("Folder")),
+                    Box::new(ancestors)
+                ),
+            ))]
+        );
+
+        // The ancestors can't depend on the folder.
+        let owner = term!(op!(Dot, var!("_this"), str!("owner")));
+        assert!(matches!(
+            Filter::build(types, partial(owner), "folder", "Folder", false),
+            Err(RuntimeError::DataFilteringUnsupportedOp { .. })
+        ));
+    }
+
+    #[test]
+    fn test_join_relation() {
+        let s = String::from;
+        let types = hashmap! {
+            s("User") => hashmap!{
+                s("groups") => Type::JoinRelation {
+                   other_class_tag: s("Group"),
+                   join_class_tag: s("UserGroup"),
+                   my_field: s("id"),
+                   join_my_field: s("user_id"),
+                   join_other_field: s("group_id"),
+                   other_field: s("id"),
+                }
+            },
+        };
+
+        // `group in user.groups and group.name = "admins"`
+        let ors = vec![ResultEvent::new(hashmap! {
+            sym!("user") => term!(op!(And,
+                term!(op!(Isa, var!("_this"), term!(pattern!(instance!("User"))))),
+                term!(op!(In, var!("group"), term!(op!(Dot, var!("_this"), str!("groups"))))),
+                term!(op!(Unify, term!("admins"), term!(op!(Dot, var!("group"), str!("name")))))
+            ))
+        })];
+        let filter = Filter::build(types, ors, "user", "User", false).unwrap();
+        assert_eq!(
+            filter.relations,
+            hashset! {
+                Relation(s("User"), s("groups"), s("UserGroup")),
+                Relation(s("UserGroup"), s("User.groups"), s("Group")),
+            }
+        );
+        assert_eq!(
+            filter.conditions,
+            vec![singleton(Condition(
+                Datum::Immediate(value!("admins")),
+                Comparison::Eq,
+                Datum::Field(Projection(s("Group"), Some(s("name")))),
+            ))]
+        );
     }
 }
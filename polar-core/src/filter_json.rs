@@ -0,0 +1,449 @@
+//! A versioned JSON representation of [`Filter`]s and [`FilterPlan`]s, for services
+//! that cache them or pass them between processes.
+//!
+//! Hosts receive filters serialized positionally, e.g., a relation is
+//! `["Repo", "org", "Org"]`. Here every field has a name, and every document says
+//! which version of the representation it follows. Version 1 is:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "filter": {
+//!     "root": "Repo",
+//!     "relations": [{ "from": "Repo", "name": "org", "to": "Org" }],
+//!     "conditions": [
+//!       [
+//!         {
+//!           "left": { "field": { "type": "Org", "field": "name" } },
+//!           "cmp": "Eq",
+//!           "right": { "immediate": { "String": "osohq" } }
+//!         }
+//!       ]
+//!     ]
+//!   }
+//! }
+//! ```
+//!
+//! `conditions` is an OR of ANDs, and `cmp` is one of `Eq`, `Neq`, `In`, `Nin`,
+//! `Lt`, `Leq`, `Gt` and `Geq`. Each side of a condition is one of:
+//!
+//! - `{"field": {"type": <type>, "field": <field>}}`, where a `null` field stands
+//!   for the record itself;
+//! - `{"immediate": <value>}`, a Polar value in its usual JSON form;
+//! - `{"subquery": <filter>}`, the records passing another filter;
+//! - `{"fixpoint": {"relation": <relation>, "filter": <filter>}}`, the records
+//!   that reach a record passing another filter through a relation.
+//!
+//! A filter plan is `{"version": 1, "filter_plan": <plan>}`, where the plan has
+//! the same named fields it has for hosts. Documents of any other version are
+//! rejected.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    data_filtering::FilterPlan,
+    error::{OperationalError, PolarResult},
+    filter::{Comparison, Condition, Datum, Filter, Projection, Relation},
+    terms::*,
+};
+
+/// The version of the representation that's written, and the only one read.
+pub const VERSION: u64 = 1;
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FilterDocument {
+    version: u64,
+    filter: FilterJson,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FilterPlanDocument {
+    version: u64,
+    filter_plan: FilterPlan,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FilterJson {
+    root: String,
+    relations: Vec<RelationJson>,
+    conditions: Vec<Vec<ConditionJson>>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RelationJson {
+    from: String,
+    name: String,
+    to: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConditionJson {
+    left: DatumJson,
+    cmp: Comparison,
+    right: DatumJson,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum DatumJson {
+    Field(ProjectionJson),
+    Immediate(Value),
+    Subquery(Box<FilterJson>),
+    Fixpoint {
+        relation: RelationJson,
+        filter: Box<FilterJson>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProjectionJson {
+    #[serde(rename = "type")]
+    typ: String,
+    field: Option<String>,
+}
+
+impl From<&Filter> for FilterJson {
+    fn from(filter: &Filter) -> Self {
+        // Sort relations and conditions so the same filter is always written the same way.
+        let mut relations: Vec<&Relation> = filter.relations.iter().collect();
+        relations.sort_by_key(|Relation(from, name, to)| (from, name, to));
+        let conditions = filter
+            .conditions
+            .iter()
+            .map(|conjuncts| {
+                let mut conjuncts: Vec<&Condition> = conjuncts.iter().collect();
+                conjuncts.sort_by_key(|condition| condition.to_string());
+                conjuncts.into_iter().map(ConditionJson::from).collect()
+            })
+            .collect();
+        Self {
+            root: filter.root.clone(),
+            relations: relations.into_iter().map(RelationJson::from).collect(),
+            conditions,
+        }
+    }
+}
+
+impl From<FilterJson> for Filter {
+    fn from(filter: FilterJson) -> Self {
+        Self {
+            root: filter.root,
+            relations: filter.relations.into_iter().map(Relation::from).collect(),
+            conditions: filter
+                .conditions
+                .into_iter()
+                .map(|conjuncts| conjuncts.into_iter().map(Condition::from).collect())
+                .collect(),
+        }
+    }
+}
+
+impl From<&Relation> for RelationJson {
+    fn from(Relation(from, name, to): &Relation) -> Self {
+        Self {
+            from: from.clone(),
+            name: name.clone(),
+            to: to.clone(),
+        }
+    }
+}
+
+impl From<RelationJson> for Relation {
+    fn from(RelationJson { from, name, to }: RelationJson) -> Self {
+        Self(from, name, to)
+    }
+}
+
+impl From<&Condition> for ConditionJson {
+    fn from(Condition(left, cmp, right): &Condition) -> Self {
+        Self {
+            left: left.into(),
+            cmp: *cmp,
+            right: right.into(),
+        }
+    }
+}
+
+impl From<ConditionJson> for Condition {
+    fn from(ConditionJson { left, cmp, right }: ConditionJson) -> Self {
+        Self(left.into(), cmp, right.into())
+    }
+}
+
+impl From<&Datum> for DatumJson {
+    fn from(datum: &Datum) -> Self {
+        match datum {
+            Datum::Field(Projection(typ, field)) => Self::Field(ProjectionJson {
+                typ: typ.clone(),
+                field: field.clone(),
+            }),
+            Datum::Immediate(value) => Self::Immediate(value.clone()),
+            Datum::Subquery(filter) => Self::Subquery(Box::new(filter.as_ref().into())),
+            Datum::Fixpoint(relation, filter) => Self::Fixpoint {
+                relation: relation.into(),
+                filter: Box::new(filter.as_ref().into()),
+            },
+        }
+    }
+}
+
+impl From<DatumJson> for Datum {
+    fn from(datum: DatumJson) -> Self {
+        match datum {
+            DatumJson::Field(ProjectionJson { typ, field }) => Self::Field(Projection(typ, field)),
+            DatumJson::Immediate(value) => Self::Immediate(value),
+            DatumJson::Subquery(filter) => Self::Subquery(Box::new((*filter).into())),
+            DatumJson::Fixpoint { relation, filter } => {
+                Self::Fixpoint(relation.into(), Box::new((*filter).into()))
+            }
+        }
+    }
+}
+
+fn serialization_error<A>(err: impl ToString) -> PolarResult<A> {
+    Err(OperationalError::Serialization {
+        msg: err.to_string(),
+    }
+    .into())
+}
+
+/// Check the version before reading the rest, so old readers reject new documents
+/// with a useful error.
+fn check_version(json: &str) -> PolarResult<()> {
+    #[derive(Deserialize)]
+    struct Version {
+        version: u64,
+    }
+    match serde_json::from_str::<Version>(json) {
+        Ok(Version { version }) if version == VERSION => Ok(()),
+        Ok(Version { version }) => serialization_error(format!(
+            "unsupported filter schema version {}, expected {}",
+            version, VERSION
+        )),
+        Err(err) => serialization_error(err),
+    }
+}
+
+impl Filter {
+    /// The filter in the versioned JSON representation.
+    pub fn to_versioned_json(&self) -> PolarResult<String> {
+        let document = FilterDocument {
+            version: VERSION,
+            filter: self.into(),
+        };
+        serde_json::to_string(&document).or_else(serialization_error)
+    }
+
+    /// Read a filter from the versioned JSON representation.
+    pub fn from_versioned_json(json: &str) -> PolarResult<Self> {
+        check_version(json)?;
+        match serde_json::from_str::<FilterDocument>(json) {
+            Ok(document) => Ok(document.filter.into()),
+            Err(err) => serialization_error(err),
+        }
+    }
+}
+
+impl FilterPlan {
+    /// The filter plan in the versioned JSON representation.
+    pub fn to_versioned_json(&self) -> PolarResult<String> {
+        let document = FilterPlanDocument {
+            version: VERSION,
+            filter_plan: self.clone(),
+        };
+        serde_json::to_string(&document).or_else(serialization_error)
+    }
+
+    /// Read a filter plan from the versioned JSON representation.
+    pub fn from_versioned_json(json: &str) -> PolarResult<Self> {
+        check_version(json)?;
+        match serde_json::from_str::<FilterPlanDocument>(json) {
+            Ok(document) => Ok(document.filter_plan),
+            Err(err) => serialization_error(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data_filtering::{build_filter_plan, Type};
+    use crate::error::{ErrorKind, PolarError};
+    use crate::events::ResultEvent;
+    use crate::filter::singleton;
+    use std::collections::HashSet;
+
+    fn s(s: &str) -> String {
+        s.to_string()
+    }
+
+    fn field(typ: &str, field: &str) -> Datum {
+        Datum::Field(Projection(s(typ), Some(s(field))))
+    }
+
+    /// A filter with every kind of datum.
+    fn filter() -> Filter {
+        let folder = Filter {
+            root: s("Folder"),
+            relations: HashSet::new(),
+            conditions: vec![singleton(Condition(
+                field("Folder", "owner"),
+                Comparison::Eq,
+                Datum::Immediate(value!("alice")),
+            ))],
+        };
+        let public = Filter {
+            root: s("Repo"),
+            relations: HashSet::new(),
+            conditions: vec![singleton(Condition(
+                field("Repo", "public"),
+                Comparison::Eq,
+                Datum::Immediate(value!(true)),
+            ))],
+        };
+        Filter {
+            root: s("Repo"),
+            relations: hashset! {
+                Relation(s("Repo"), s("org"), s("Org")),
+                Relation(s("Repo"), s("folder"), s("Folder")),
+            },
+            conditions: vec![
+                hashset! {
+                    Condition(field("Org", "name"), Comparison::In, Datum::Immediate(value!(["osohq", "apple"]))),
+                    Condition(field("Repo", "stars"), Comparison::Geq, Datum::Immediate(value!(1.5))),
+                    Condition(Datum::Field(Projection(s("Repo"), None)), Comparison::Nin, Datum::Subquery(Box::new(public))),
+                },
+                singleton(Condition(
+                    Datum::Field(Projection(s("Folder"), None)),
+                    Comparison::In,
+                    Datum::Fixpoint(
+                        Relation(s("Folder"), s("parent"), s("Folder")),
+                        Box::new(folder),
+                    ),
+                )),
+                HashSet::new(),
+            ],
+        }
+    }
+
+    fn is_serialization_error(err: PolarError) -> bool {
+        matches!(
+            err.kind,
+            ErrorKind::Operational(OperationalError::Serialization { .. })
+        )
+    }
+
+    #[test]
+    fn test_filter_round_trip() -> PolarResult<()> {
+        let filter = filter();
+        let json = filter.to_versioned_json()?;
+        assert_eq!(Filter::from_versioned_json(&json)?, filter);
+        // The same filter is always written the same way.
+        assert_eq!(
+            Filter::from_versioned_json(&json)?.to_versioned_json()?,
+            json
+        );
+        // Including the positional form hosts receive.
+        let positional = serde_json::to_string(&filter).unwrap();
+        assert_eq!(serde_json::from_str::<Filter>(&positional).unwrap(), filter);
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_schema() -> PolarResult<()> {
+        let json = r#"{
+            "version": 1,
+            "filter": {
+                "root": "Repo",
+                "relations": [{"from": "Repo", "name": "org", "to": "Org"}],
+                "conditions": [[
+                    {
+                        "left": {"field": {"type": "Org", "field": "name"}},
+                        "cmp": "Eq",
+                        "right": {"immediate": {"String": "osohq"}}
+                    },
+                    {
+                        "left": {"field": {"type": "Repo", "field": null}},
+                        "cmp": "Nin",
+                        "right": {"subquery": {"root": "Repo", "relations": [], "conditions": []}}
+                    }
+                ]]
+            }
+        }"#;
+        let filter = Filter::from_versioned_json(json)?;
+        assert_eq!(
+            filter,
+            Filter {
+                root: s("Repo"),
+                relations: singleton(Relation(s("Repo"), s("org"), s("Org"))),
+                conditions: vec![hashset! {
+                    Condition(field("Org", "name"), Comparison::Eq, Datum::Immediate(value!("osohq"))),
+                    Condition(
+                        Datum::Field(Projection(s("Repo"), None)),
+                        Comparison::Nin,
+                        Datum::Subquery(Box::new(Filter {
+                            root: s("Repo"),
+                            relations: HashSet::new(),
+                            conditions: vec![],
+                        })),
+                    ),
+                }],
+            }
+        );
+
+        let unversioned = r#"{"filter": {"root": "Repo", "relations": [], "conditions": []}}"#;
+        assert!(is_serialization_error(
+            Filter::from_versioned_json(unversioned).unwrap_err()
+        ));
+        let future = json.replace(r#""version": 1"#, r#""version": 2"#);
+        let err = Filter::from_versioned_json(&future).unwrap_err();
+        assert!(err.to_string().contains("version 2"), "{}", err);
+        let positional = json.replace(r#""from": "Repo", "name": "org", "to": "Org""#, "");
+        assert!(is_serialization_error(
+            Filter::from_versioned_json(&positional).unwrap_err()
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_filter_plan_round_trip() -> PolarResult<()> {
+        let types = hashmap! {
+            s("Repo") => hashmap! {
+                s("org") => Type::Relation {
+                    kind: s("one"),
+                    other_class_tag: s("Org"),
+                    my_field: s("org_id"),
+                    other_field: s("id"),
+                },
+            },
+            s("Org") => hashmap! {
+                s("name") => Type::Base { class_tag: s("String") },
+            },
+        };
+        let partial = term!(op!(
+            And,
+            term!(op!(Isa, var!("_this"), term!(pattern!(instance!("Repo"))))),
+            term!(op!(
+                Unify,
+                str!("osohq"),
+                term!(op!(
+                    Dot,
+                    term!(op!(Dot, var!("_this"), str!("org"))),
+                    str!("name")
+                ))
+            ))
+        ));
+        let results = vec![ResultEvent::from(hashmap! { sym!("repo") => partial })];
+        let plan = build_filter_plan(types, results, "repo", "Repo", false).unwrap();
+        let json = plan.to_versioned_json()?;
+        assert!(json.starts_with(r#"{"version":1,"filter_plan":{"result_sets":"#));
+        assert_eq!(FilterPlan::from_versioned_json(&json)?, plan);
+        Ok(())
+    }
+}
@@ -129,6 +129,11 @@ fn precedence(o: &Operator) -> i32 {
         Operator::New => 10,
         Operator::Cut => 10,
         Operator::ForAll => 10,
+        Operator::Count => 10,
+        Operator::Collect => 10,
+        Operator::Min => 10,
+        Operator::Max => 10,
+        Operator::Sum => 10,
         Operator::Dot => 9,
         Operator::In => 8,
         Operator::Isa => 8,
@@ -192,7 +197,7 @@ pub mod display {
 
     impl fmt::Display for Symbol {
         fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
-            write!(fmt, "{}", self.0)
+            write!(fmt, "{}", self.as_str())
         }
     }
 
@@ -429,6 +434,11 @@ pub mod to_polar {
                 In => "in",
                 Cut => "cut",
                 ForAll => "forall",
+                Count => "count",
+                Collect => "collect",
+                Min => "min",
+                Max => "max",
+                Sum => "sum",
                 Debug => "debug",
                 Print => "print",
                 Isa => "matches",
@@ -451,6 +461,13 @@ pub mod to_polar {
                     self.args[0].to_polar(),
                     self.args[1].to_polar()
                 ),
+                Count | Collect | Min | Max | Sum => format!(
+                    "{}({}, {}, {})",
+                    self.operator.to_polar(),
+                    self.args[0].to_polar(),
+                    self.args[1].to_polar(),
+                    self.args[2].to_polar()
+                ),
                 New => {
                     if self.args.len() == 1 {
                         format!("new {}", to_polar_parens(self.operator, &self.args[0]))
@@ -589,7 +606,7 @@ pub mod to_polar {
 
     impl ToPolarString for Symbol {
         fn to_polar(&self) -> String {
-            self.0.to_string()
+            self.to_string()
         }
     }
 
@@ -1,5 +1,5 @@
 use std::cell::RefCell;
-use std::collections::hash_map::Entry;
+use std::collections::HashSet;
 use std::rc::Rc;
 use std::sync::atomic::{AtomicU64, Ordering};
 
@@ -7,11 +7,10 @@ use crate::bindings::{BindingManager, Bsp, FollowerId, VariableState};
 use crate::counter::Counter;
 use crate::error::RuntimeError;
 use crate::events::QueryEvent;
-use crate::formatting::ToPolarString;
 use crate::kb::Bindings;
-use crate::partial::simplify_bindings;
+use crate::partial::{simplify_bindings, simplify_partial};
 use crate::runnable::Runnable;
-use crate::terms::{Operation, Operator, Term, Value};
+use crate::terms::{Operation, Operator, Symbol, Term, Value};
 use crate::vm::{Goals, PolarVirtualMachine};
 
 type Result<T> = core::result::Result<T, RuntimeError>;
@@ -76,21 +75,37 @@ impl Inverter {
 /// 1. Invert each result.
 /// 2. AND the inverted constraints together.
 ///
-/// The output constraints are AND[!result1, !result2, ...].
-fn results_to_constraints(results: Vec<BindingManager>) -> Bindings {
-    let inverted = results.into_iter().map(invert_partials).collect();
-    let reduced = reduce_constraints(inverted);
-    let simplified = simplify_bindings(reduced).unwrap_or_else(Bindings::new);
-
-    simplified
-        .into_iter()
-        .map(|(k, v)| match v.value() {
-            Value::Expression(_) => (k, v),
-            _ => (
-                k.clone(),
-                v.clone_with_value(Value::Expression(op!(Unify, term!(k), v.clone()))),
-            ),
-        })
+/// The output constraints are AND[!result1, !result2, ...], given to every
+/// variable they constrain.
+fn results_to_constraints(
+    results: Vec<BindingManager>,
+    vm: &PolarVirtualMachine,
+    bsp: &Bsp,
+) -> Bindings {
+    let mut vars = HashSet::new();
+    let mut constraints = op!(And);
+    for result in results {
+        if let Some((result_vars, inverted)) = invert_partials(result, vm, bsp) {
+            vars.extend(result_vars);
+            for arg in inverted.args {
+                if !constraints.args.contains(&arg) {
+                    constraints.args.push(arg);
+                }
+            }
+        }
+    }
+    let var = match vars.iter().min() {
+        Some(var) => var.clone(),
+        None => return Bindings::new(),
+    };
+    // Simplify negations, e.g., `not x > 3` to `x <= 3`, keeping every variable.
+    let (simplified, _) = simplify_partial(&var, term!(constraints), vars.clone(), false);
+    let constraint = match simplified.value() {
+        Value::Expression(_) => simplified,
+        _ => term!(op!(And, term!(op!(Unify, term!(var), simplified)))),
+    };
+    vars.into_iter()
+        .map(|var| (var, constraint.clone()))
         .collect()
 }
 
@@ -98,56 +113,58 @@ fn results_to_constraints(results: Vec<BindingManager>) -> Bindings {
 ///
 /// Constraints are inverted by getting each binding as a constraint.
 /// Simplification is performed, to subsitute bindings and remove temporary variables.
-/// Then, each simplified expression is inverted.
-/// A binding of `var` to `val` after simplification is converted into `var != val`.
-fn invert_partials(bindings: BindingManager) -> Bindings {
+/// A binding of `var` to `val` after simplification is converted into `var = val`.
+///
+/// The result is the conjunction of the constraints on all its variables, so they're
+/// inverted together: `not (x = 1 and y = 2)` is `x != 1 or y != 2`. Returns the
+/// variables that the inverted constraints apply to, and the constraints, or `None`
+/// if there are none.
+fn invert_partials(
+    bindings: BindingManager,
+    vm: &PolarVirtualMachine,
+    bsp: &Bsp,
+) -> Option<(Vec<Symbol>, Operation)> {
     let mut new_bindings = Bindings::new();
 
     for var in bindings.variables() {
         let constraint = bindings.get_constraints(&var);
-        new_bindings.insert(var.clone(), term!(constraint));
+        new_bindings.insert(var, term!(constraint));
     }
 
     let simplified = simplify_bindings(new_bindings).unwrap_or_else(Bindings::new);
+    let simplified = filter_inverted_constraints(simplified, vm, bsp);
 
-    simplified
-        .into_iter()
-        .map(|(k, v)| match v.value() {
-            Value::Expression(e) => (k, e.invert().into()),
-            _ => (
-                k.clone(),
-                term!(op!(And, term!(op!(Neq, term!(k), v.clone())))),
-            ),
-        })
-        .collect::<Bindings>()
-}
+    // Each variable's simplified constraints include those on every variable it's
+    // related to, so take them from one variable of each related group.
+    let mut vars: Vec<Symbol> = simplified.keys().cloned().collect();
+    vars.sort_by_key(|var| (var.is_temporary_var(), var.to_string()));
+    let mut covered = HashSet::new();
+    let mut constraints = op!(And);
+    for var in vars.iter() {
+        if covered.contains(var) {
+            continue;
+        }
+        let value = &simplified[var];
+        let constraint = match value.value() {
+            Value::Expression(e) => e.clone(),
+            _ => op!(And, term!(op!(Unify, term!(var.clone()), value.clone()))),
+        };
+        covered.insert(var.clone());
+        covered.extend(constraint.variables());
+        // Variables that were aliases before the inversion are bound together.
+        covered.extend(vm.unified_at_point(var, bsp));
+        for arg in constraint.args {
+            if !constraints.args.contains(&arg) {
+                constraints.args.push(arg);
+            }
+        }
+    }
 
-/// Takes a vec of bindings and merges constraints on each variable.
-fn reduce_constraints(bindings: Vec<Bindings>) -> Bindings {
-    bindings
-        .into_iter()
-        .fold(Bindings::new(), |mut acc, bindings| {
-            bindings
-                .into_iter()
-                .for_each(|(var, value)| match acc.entry(var.clone()) {
-                    Entry::Occupied(mut o) => match (o.get().value(), value.value()) {
-                        (Value::Expression(x), Value::Expression(y)) => {
-                            let x = x.clone().merge_constraints(y.clone());
-                            o.insert(value.clone_with_value(value!(x)));
-                        }
-                        (existing, new) => panic!(
-                            "Illegal state reached while reducing constraints for {}: {} → {}",
-                            var,
-                            existing.to_polar(),
-                            new.to_polar()
-                        ),
-                    },
-                    Entry::Vacant(v) => {
-                        v.insert(value);
-                    }
-                });
-            acc
-        })
+    if constraints.args.is_empty() {
+        None
+    } else {
+        Some((vars, constraints.invert()))
+    }
 }
 
 /// Decide which variables come out of negation. This is hacky.
@@ -170,13 +187,13 @@ fn reduce_constraints(bindings: Vec<Bindings>) -> Bindings {
 fn filter_inverted_constraints(
     constraints: Bindings,
     vm: &PolarVirtualMachine,
-    bsp: Bsp,
+    bsp: &Bsp,
 ) -> Bindings {
     constraints
         .into_iter()
         .filter(|(k, _)| {
             !(matches!(
-                vm.variable_state_at_point(k, &bsp),
+                vm.variable_state_at_point(k, bsp),
                 VariableState::Unbound | VariableState::Bound(_)
             ))
         })
@@ -207,12 +224,11 @@ impl Runnable for Inverter {
                         // If there are results, the inversion should usually fail. However,
                         // if those results have constraints we collect them and pass them
                         // out to the parent VM.
-                        let constraints =
-                            results_to_constraints(self.results.drain(..).collect::<Vec<_>>());
-                        let mut bsp = Bsp::default();
-                        // Use mem swap to avoid cloning bsps.
-                        std::mem::swap(&mut self.bsp, &mut bsp);
-                        let constraints = filter_inverted_constraints(constraints, &self.vm, bsp);
+                        let constraints = results_to_constraints(
+                            self.results.drain(..).collect::<Vec<_>>(),
+                            &self.vm,
+                            &self.bsp,
+                        );
 
                         if !constraints.is_empty() {
                             // Return inverted constraints to parent VM.
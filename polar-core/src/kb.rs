@@ -40,6 +40,8 @@ pub struct KnowledgeBase {
 
     rules: HashMap<Symbol, GenericRule>,
     rule_types: RuleTypes,
+    /// Names of rules whose calls are tabled.
+    tabled_rules: HashSet<Symbol>,
     pub sources: Sources,
     /// For symbols returned from gensym.
     gensym_counter: Counter,
@@ -64,6 +66,18 @@ impl KnowledgeBase {
         self.id_counter.next()
     }
 
+    /// A new knowledge base with this one's constants and MROs but none of its rules
+    /// or sources. The ID counters are shared so that IDs stay unique across both.
+    pub fn fork(&self) -> Self {
+        Self {
+            constants: self.constants.clone(),
+            mro: self.mro.clone(),
+            gensym_counter: self.gensym_counter.clone(),
+            id_counter: self.id_counter.clone(),
+            ..Self::default()
+        }
+    }
+
     pub fn id_counter(&self) -> Counter {
         self.id_counter.clone()
     }
@@ -79,7 +93,10 @@ impl KnowledgeBase {
     /// Generate a new symbol.
     pub fn gensym(&self, prefix: &str) -> Symbol {
         let next = self.gensym_counter.next();
-        Symbol(format!("{}{}", Self::temp_prefix(prefix), next))
+        match prefix {
+            "_" => Symbol::with_suffix("", next),
+            _ => Symbol::with_suffix(&format!("_{}", prefix), next),
+        }
     }
 
     /// Add a generic rule to the knowledge base.
@@ -260,8 +277,8 @@ impl KnowledgeBase {
                     } else {
                         RuleParamMatch::False(format!("Rule specializer {} on parameter {} did not match rule type specializer {} because the specializer fields did not match.", rule_instance.to_polar(), index, rule_type_instance.to_polar()))
                     }
-                } else if self.is_union(&term!(sym!(&rule_type_instance.tag.0))) {
-                    if self.is_union(&term!(sym!(&rule_instance.tag.0))) {
+                } else if self.is_union(&term!(rule_type_instance.tag.clone())) {
+                    if self.is_union(&term!(rule_instance.tag.clone())) {
                         // If both specializers are the same union, check fields.
                         if rule_instance.tag == rule_type_instance.tag {
                             if self.param_fields_match(
@@ -279,10 +296,10 @@ impl KnowledgeBase {
                         }
                     }
 
-                    let members = self.get_union_members(&term!(sym!(&rule_type_instance.tag.0)));
+                    let members = self.get_union_members(&term!(rule_type_instance.tag.clone()));
                     // If the rule specializer is not a direct member of the union, we still need
                     // to check if it's a subclass of any member of the union.
-                    if !members.contains(&term!(sym!(&rule_instance.tag.0))) {
+                    if !members.contains(&term!(rule_instance.tag.clone())) {
                         let mut success = false;
                         for member in members {
                             // Turn `member` into an `InstanceLiteral` by copying fields from
@@ -299,13 +316,13 @@ impl KnowledgeBase {
                         }
                         if !success {
                             let mut err = format!("Rule specializer {} on parameter {} must be a member of rule type specializer {}", rule_instance.tag,index, rule_type_instance.tag);
-                            if rule_type_instance.tag.0 == ACTOR_UNION_NAME {
+                            if rule_type_instance.tag.as_str() == ACTOR_UNION_NAME {
                                 err.push_str(&format!("
 
 \tPerhaps you meant to add an actor block to the top of your policy, like this:
 
 \t  actor {} {{}}", rule_instance.tag));
-                            } else if rule_type_instance.tag.0 == RESOURCE_UNION_NAME {
+                            } else if rule_type_instance.tag.as_str() == RESOURCE_UNION_NAME {
                                 err.push_str(&format!("
 
 \tPerhaps you meant to add a resource block to your policy, like this:
@@ -582,12 +599,21 @@ impl KnowledgeBase {
         self.rule_types.add(rule_type);
     }
 
+    /// Table calls to the rule `name`, memoizing their answers.
+    pub fn table_rule(&mut self, name: Symbol) {
+        self.tabled_rules.insert(name);
+    }
+
+    pub fn is_tabled(&self, name: &Symbol) -> bool {
+        self.tabled_rules.contains(name)
+    }
+
     /// Define a constant variable.
     ///
     /// Error on attempts to register the "union" types (Actor & Resource) since those types have
     /// special meaning in policies that use resource blocks.
     pub fn register_constant(&mut self, name: Symbol, value: Term) -> PolarResult<()> {
-        if name.0 == ACTOR_UNION_NAME || name.0 == RESOURCE_UNION_NAME {
+        if name.as_str() == ACTOR_UNION_NAME || name.as_str() == RESOURCE_UNION_NAME {
             return Err(RuntimeError::InvalidRegistration {
                 msg: format!("'{}' is a built-in specializer.", name),
                 sym: name,
@@ -657,6 +683,7 @@ impl KnowledgeBase {
     pub fn clear_rules(&mut self) {
         self.rules.clear();
         self.rule_types.reset();
+        self.tabled_rules.clear();
         self.sources = Sources::default();
         self.inline_queries.clear();
         self.loaded_content.clear();
@@ -826,9 +853,9 @@ impl KnowledgeBase {
         }
 
         let mut rule_types = rule_types_to_create.into_iter().map(|((subject, relation, object), required)| {
-            let subject_specializer = pattern!(instance!(&subject.value().as_symbol().expect("must be symbol").0));
+            let subject_specializer = pattern!(instance!(subject.value().as_symbol().expect("must be symbol").as_str()));
             let relation_name = relation.value().as_string().expect("must be string");
-            let object_specializer = pattern!(instance!(&object.value().as_symbol().expect("must be symbol").0));
+            let object_specializer = pattern!(instance!(object.value().as_symbol().expect("must be symbol").as_str()));
 
             let src_id = relation.get_source_id().expect("must be parsed");
             let (left, right) = relation.span().expect("must be parsed");
@@ -99,7 +99,7 @@ impl ToString for Token {
             Token::Float(f) => f.to_string(),
             Token::String(s) => s.clone(),
             Token::Boolean(b) => b.to_string(),
-            Token::Symbol(sym) => sym.0.clone(),
+            Token::Symbol(sym) => sym.to_string(),
             Token::Colon => ":".to_owned(),         // :
             Token::Comma => ",".to_owned(),         // ,
             Token::LB => "[".to_owned(),            // [
@@ -7,7 +7,9 @@ extern crate maplit;
 #[macro_use]
 pub mod macros;
 
+mod aggregates;
 mod bindings;
+pub mod config;
 mod counter;
 pub mod data_filtering;
 mod debugger;
@@ -15,12 +17,15 @@ pub mod diagnostic;
 pub mod error;
 pub mod events;
 pub mod filter;
+pub mod filter_json;
 mod folder;
 pub mod formatting;
 mod inverter;
 pub mod kb;
 mod lexer;
+pub mod memory;
 pub mod messages;
+mod minimize;
 mod numerics;
 pub mod parser;
 mod partial;
@@ -31,6 +36,9 @@ mod rewrites;
 pub mod rules;
 mod runnable;
 pub mod sources;
+pub mod specialize;
+pub mod sql;
+mod tabling;
 pub mod terms;
 pub mod traces;
 mod validations;
@@ -273,7 +273,7 @@ impl From<Value> for TestHelper<Parameter> {
 
 impl<S: AsRef<str>> From<S> for TestHelper<Symbol> {
     fn from(other: S) -> Self {
-        Self(Symbol(other.as_ref().to_string()))
+        Self(Symbol::new(other.as_ref()))
     }
 }
 
@@ -0,0 +1,468 @@
+//! Evaluates a [`Filter`] directly over in-memory data, e.g., in tests or caches.
+//!
+//! Relations are followed like `LEFT JOIN`s: a record with no related records is
+//! still considered, but conditions on the missing record don't hold. As in SQL,
+//! `!=` and `NOT IN` only hold between two present values. A sub-filter is the
+//! list of the identities of the records that pass it; a fixpoint adds the records
+//! related to a listed record until no more are added.
+
+use std::{cell::RefCell, collections::HashMap};
+
+use crate::{
+    error::{invalid_state_error, RuntimeError},
+    filter::{Comparison, Condition, Datum, Filter, Projection, Relation},
+    terms::*,
+    vm::compare,
+};
+
+type FilterResult<A> = core::result::Result<A, RuntimeError>;
+
+/// A source of records for evaluating a [`Filter`].
+pub trait DataSource {
+    type Record: Clone;
+
+    /// All records of type `typ`.
+    fn records(&self, typ: &str) -> Vec<Self::Record>;
+
+    /// The value of `field` on `record`, if it has one.
+    fn field(&self, record: &Self::Record, field: &str) -> Option<Value>;
+
+    /// The records related to `record` through the relation `field`. Many-to-many
+    /// relations take two steps, the second from the join records through
+    /// [`join_field`](crate::filter::join_field).
+    fn related(&self, record: &Self::Record, field: &str) -> Vec<Self::Record>;
+
+    /// The value a whole record is compared with, e.g., its primary key.
+    fn identity(&self, record: &Self::Record) -> Value;
+}
+
+/// The records joined so far, by type. `None` if a relation had no records.
+type Row<'a, R> = HashMap<&'a str, Option<R>>;
+
+struct Evaluator<'a, S: DataSource> {
+    filter: &'a Filter,
+    relations: Vec<&'a Relation>,
+    source: &'a S,
+    /// The records of each sub-filter and fixpoint, which don't depend on the row.
+    lists: RefCell<HashMap<&'a Datum, Value>>,
+}
+
+impl<'a, S: DataSource> Evaluator<'a, S> {
+    fn value(&self, datum: &'a Datum, row: &Row<S::Record>) -> FilterResult<Option<Value>> {
+        match datum {
+            Datum::Immediate(value) => Ok(Some(value.clone())),
+            Datum::Field(Projection(typ, field)) => {
+                let record = match row.get(typ.as_str()) {
+                    Some(Some(record)) => record,
+                    _ => return Ok(None),
+                };
+                Ok(match field {
+                    Some(field) => self.source.field(record, field),
+                    None => Some(self.source.identity(record)),
+                })
+            }
+            Datum::Subquery(_) | Datum::Fixpoint(..) => {
+                if let Some(list) = self.lists.borrow().get(datum) {
+                    return Ok(Some(list.clone()));
+                }
+                let list = self.list(datum)?;
+                self.lists.borrow_mut().insert(datum, list.clone());
+                Ok(Some(list))
+            }
+        }
+    }
+
+    /// The identities of the records of a sub-filter or fixpoint.
+    fn list(&self, datum: &Datum) -> FilterResult<Value> {
+        match datum {
+            Datum::Subquery(filter) => {
+                let records = filter.evaluate(self.source)?;
+                let ids = records.iter().map(|r| Term::from(self.source.identity(r)));
+                Ok(Value::List(ids.collect()))
+            }
+            Datum::Fixpoint(Relation(typ, field, _), filter) => {
+                let records = filter.evaluate(self.source)?;
+                let mut reached: Vec<Value> =
+                    records.iter().map(|r| self.source.identity(r)).collect();
+                let mut ids = vec![];
+                // Add the records related to a reached record until there are no more.
+                let mut rest = self.source.records(typ);
+                loop {
+                    let (found, unreached): (Vec<_>, Vec<_>) =
+                        rest.into_iter().partition(|record| {
+                            self.source
+                                .related(record, field)
+                                .iter()
+                                .any(|r| reached.contains(&self.source.identity(r)))
+                        });
+                    if found.is_empty() {
+                        break;
+                    }
+                    for record in found {
+                        let id = self.source.identity(&record);
+                        ids.push(Term::from(id.clone()));
+                        reached.push(id);
+                    }
+                    rest = unreached;
+                }
+                Ok(Value::List(ids))
+            }
+            _ => invalid_state_error(format!("not a list of records: {:?}", datum)),
+        }
+    }
+
+    fn holds(
+        &self,
+        Condition(left, op, right): &'a Condition,
+        row: &Row<S::Record>,
+    ) -> FilterResult<bool> {
+        let (left, right) = match (self.value(left, row)?, self.value(right, row)?) {
+            (Some(left), Some(right)) => (left, right),
+            _ => return Ok(false),
+        };
+        let ordering = match op {
+            Comparison::Eq => return Ok(left == right),
+            Comparison::Neq => return Ok(left != right),
+            Comparison::In | Comparison::Nin => {
+                return Ok(match right {
+                    Value::List(list) => {
+                        list.iter().any(|item| item.value() == &left) == (*op == Comparison::In)
+                    }
+                    _ => false,
+                })
+            }
+            Comparison::Lt => Operator::Lt,
+            Comparison::Leq => Operator::Leq,
+            Comparison::Gt => Operator::Gt,
+            Comparison::Geq => Operator::Geq,
+        };
+        // Values that can't be compared, like a string and a number, aren't ordered.
+        let (left, right) = (Term::from(left), Term::from(right));
+        let comparison = Term::from(Operation {
+            operator: ordering,
+            args: vec![left.clone(), right.clone()],
+        });
+        Ok(compare(ordering, &left, &right, Some(&comparison)).unwrap_or(false))
+    }
+
+    /// Whether some combination of related records, starting with the `i`th
+    /// relation, satisfies the conditions.
+    fn join(&self, i: usize, row: &mut Row<'a, S::Record>) -> FilterResult<bool> {
+        let Relation(src, field, dst) = match self.relations.get(i) {
+            Some(relation) => *relation,
+            None => {
+                for conjuncts in &self.filter.conditions {
+                    if conjuncts.iter().try_fold(true, |all, c| {
+                        Ok::<_, RuntimeError>(all && self.holds(c, row)?)
+                    })? {
+                        return Ok(true);
+                    }
+                }
+                return Ok(false);
+            }
+        };
+        let related = match row.get(src.as_str()) {
+            Some(Some(record)) => self.source.related(record, field),
+            _ => vec![],
+        };
+        if related.is_empty() {
+            row.insert(dst, None);
+            return self.join(i + 1, row);
+        }
+        for record in related {
+            row.insert(dst, Some(record));
+            if self.join(i + 1, row)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+impl Filter {
+    /// The records of the root type in `source` that pass the filter.
+    pub fn evaluate<S: DataSource>(&self, source: &S) -> FilterResult<Vec<S::Record>> {
+        let evaluator = Evaluator {
+            filter: self,
+            relations: self.ordered_relations()?,
+            source,
+            lists: RefCell::new(HashMap::new()),
+        };
+        let mut records = vec![];
+        for record in source.records(&self.root) {
+            let mut row = HashMap::new();
+            row.insert(self.root.as_str(), Some(record.clone()));
+            if evaluator.join(0, &mut row)? {
+                records.push(record);
+            }
+        }
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::data_filtering::Type;
+    use crate::events::ResultEvent;
+    use crate::filter::singleton;
+    use std::{cell::Cell, collections::HashSet};
+
+    /// Repos, orgs and suborgs, as `(type, id, fields)`.
+    type Record = (&'static str, i64, HashMap<&'static str, Value>);
+
+    /// The records, and how many times they've been listed.
+    struct Source(Vec<Record>, Cell<usize>);
+
+    impl Source {
+        fn new() -> Self {
+            let repo = |id, name: &str, org_id, public| {
+                let fields = hashmap! {
+                    "name" => value!(name),
+                    "org_id" => value!(org_id),
+                    "public" => value!(public),
+                };
+                ("Repo", id, fields)
+            };
+            let org = |id, name: &str| ("Org", id, hashmap! { "name" => value!(name) });
+            let suborg = |id, name: &str, parent_id| {
+                let fields = hashmap! { "name" => value!(name), "parent_id" => value!(parent_id) };
+                ("Org", id, fields)
+            };
+            Self(
+                vec![
+                    org(1, "osohq"),
+                    org(2, "apple"),
+                    suborg(6, "infra", 1),
+                    suborg(7, "k8s", 6),
+                    repo(1, "oso", 1, false),
+                    repo(2, "demo", 1, true),
+                    repo(3, "swift", 2, true),
+                    repo(4, "ios", 2, false),
+                    repo(5, "orphan", 3, false),
+                ],
+                Cell::new(0),
+            )
+        }
+
+        fn all(&self, typ: &str) -> Vec<Record> {
+            self.0.iter().filter(|r| r.0 == typ).cloned().collect()
+        }
+    }
+
+    impl DataSource for Source {
+        type Record = Record;
+
+        fn records(&self, typ: &str) -> Vec<Record> {
+            self.1.set(self.1.get() + 1);
+            self.all(typ)
+        }
+
+        fn field(&self, record: &Record, field: &str) -> Option<Value> {
+            record.2.get(field).cloned()
+        }
+
+        fn related(&self, record: &Record, field: &str) -> Vec<Record> {
+            match (record.0, field) {
+                ("Repo", "org") => self
+                    .all("Org")
+                    .into_iter()
+                    .filter(|org| Some(value!(org.1)) == self.field(record, "org_id"))
+                    .collect(),
+                ("Org", "repos") => self
+                    .all("Repo")
+                    .into_iter()
+                    .filter(|repo| self.field(repo, "org_id") == Some(value!(record.1)))
+                    .collect(),
+                ("Org", "parent") => self
+                    .all("Org")
+                    .into_iter()
+                    .filter(|org| Some(value!(org.1)) == self.field(record, "parent_id"))
+                    .collect(),
+                _ => vec![],
+            }
+        }
+
+        fn identity(&self, record: &Record) -> Value {
+            value!(record.1)
+        }
+    }
+
+    fn names(records: Vec<Record>) -> Vec<Value> {
+        records.into_iter().map(|r| r.2["name"].clone()).collect()
+    }
+
+    fn field(typ: &str, field: &str) -> Datum {
+        Datum::Field(Projection(typ.to_string(), Some(field.to_string())))
+    }
+
+    #[test]
+    fn test_conditions() {
+        let source = Source::new();
+        let filter = |conditions| Filter {
+            root: "Repo".to_string(),
+            relations: singleton(Relation("Repo".into(), "org".into(), "Org".into())),
+            conditions,
+        };
+
+        // Public repos, or repos in the "osohq" org.
+        let or = filter(vec![
+            singleton(Condition(
+                field("Repo", "public"),
+                Comparison::Eq,
+                Datum::Immediate(value!(true)),
+            )),
+            singleton(Condition(
+                field("Org", "name"),
+                Comparison::Eq,
+                Datum::Immediate(value!("osohq")),
+            )),
+        ]);
+        assert_eq!(
+            names(or.evaluate(&source).unwrap()),
+            values!["oso", "demo", "swift"]
+        );
+
+        // Repos with an org that isn't "osohq": the orphan has no org.
+        let neq = filter(vec![singleton(Condition(
+            field("Org", "name"),
+            Comparison::Neq,
+            Datum::Immediate(value!("osohq")),
+        ))]);
+        assert_eq!(
+            names(neq.evaluate(&source).unwrap()),
+            values!["swift", "ios"]
+        );
+
+        let in_list = filter(vec![hashset! {
+            Condition(
+                Datum::Field(Projection("Repo".into(), None)),
+                Comparison::In,
+                Datum::Immediate(value!([1, 3, 5])),
+            ),
+            Condition(field("Repo", "public"), Comparison::Eq, Datum::Immediate(value!(false))),
+        }]);
+        assert_eq!(
+            names(in_list.evaluate(&source).unwrap()),
+            values!["oso", "orphan"]
+        );
+
+        let ordered = filter(vec![hashset! {
+            Condition(field("Repo", "org_id"), Comparison::Lt, Datum::Field(Projection("Repo".into(), None))),
+            Condition(field("Repo", "name"), Comparison::Nin, Datum::Immediate(value!(["demo"]))),
+            Condition(field("Org", "name"), Comparison::Leq, Datum::Immediate(value!("b"))),
+        }]);
+        assert_eq!(
+            names(ordered.evaluate(&source).unwrap()),
+            values!["swift", "ios"]
+        );
+
+        // Strings and numbers aren't ordered.
+        let mismatched = filter(vec![singleton(Condition(
+            field("Repo", "name"),
+            Comparison::Gt,
+            Datum::Immediate(value!(1)),
+        ))]);
+        assert!(mismatched.evaluate(&source).unwrap().is_empty());
+
+        // Repos that aren't public repos: the sub-filter is evaluated by itself.
+        let public = filter(vec![singleton(Condition(
+            field("Repo", "public"),
+            Comparison::Eq,
+            Datum::Immediate(value!(true)),
+        ))]);
+        let anti_join = filter(vec![singleton(Condition(
+            Datum::Field(Projection("Repo".into(), None)),
+            Comparison::Nin,
+            Datum::Subquery(Box::new(public)),
+        ))]);
+        source.1.set(0);
+        assert_eq!(
+            names(anti_join.evaluate(&source).unwrap()),
+            values!["oso", "ios", "orphan"]
+        );
+        // Once for the repos and once for the public ones, not for every repo.
+        assert_eq!(source.1.get(), 2);
+
+        // Orgs inside the "osohq" org.
+        let osohq = Filter {
+            root: "Org".to_string(),
+            relations: HashSet::new(),
+            conditions: vec![singleton(Condition(
+                field("Org", "name"),
+                Comparison::Eq,
+                Datum::Immediate(value!("osohq")),
+            ))],
+        };
+        let suborgs = Filter {
+            root: "Org".to_string(),
+            relations: HashSet::new(),
+            conditions: vec![singleton(Condition(
+                Datum::Field(Projection("Org".into(), None)),
+                Comparison::In,
+                Datum::Fixpoint(
+                    Relation("Org".into(), "parent".into(), "Org".into()),
+                    Box::new(osohq),
+                ),
+            ))],
+        };
+        assert_eq!(
+            names(suborgs.evaluate(&source).unwrap()),
+            values!["infra", "k8s"]
+        );
+
+        assert!(filter(vec![]).evaluate(&source).unwrap().is_empty());
+        assert_eq!(
+            filter(vec![HashSet::new()])
+                .evaluate(&source)
+                .unwrap()
+                .len(),
+            5
+        );
+    }
+
+    #[test]
+    fn test_policy_filter() {
+        let s = String::from;
+        let types = hashmap! {
+            s("Org") => hashmap! {
+                s("repos") => Type::Relation {
+                    kind: s("many"),
+                    my_field: s("id"),
+                    other_field: s("org_id"),
+                    other_class_tag: s("Repo"),
+                },
+            },
+            s("Repo") => hashmap! {
+                s("public") => Type::Base { class_tag: s("Boolean") },
+            },
+        };
+
+        // Orgs with a public repo.
+        let ors = vec![ResultEvent::new(hashmap! {
+            sym!("org") => term!(op!(And,
+                term!(op!(Isa, var!("_this"), term!(pattern!(instance!("Org"))))),
+                term!(op!(In, var!("repo"), term!(op!(Dot, var!("_this"), str!("repos"))))),
+                term!(op!(Unify, term!(true), term!(op!(Dot, var!("repo"), str!("public")))))
+            ))
+        })];
+        let filter = Filter::build(types.clone(), ors, "org", "Org", false).unwrap();
+        assert_eq!(
+            names(filter.evaluate(&Source::new()).unwrap()),
+            values!["osohq", "apple"]
+        );
+
+        // Orgs without any repos.
+        let ors = vec![ResultEvent::new(hashmap! {
+            sym!("org") => term!(op!(And,
+                term!(op!(Isa, var!("_this"), term!(pattern!(instance!("Org"))))),
+                term!(op!(Not, term!(op!(In, var!("repo"), term!(op!(Dot, var!("_this"), str!("repos")))))))
+            ))
+        })];
+        let filter = Filter::build(types, ors, "org", "Org", false).unwrap();
+        assert_eq!(
+            names(filter.evaluate(&Source::new()).unwrap()),
+            values!["infra", "k8s"]
+        );
+    }
+}
@@ -0,0 +1,445 @@
+//! Simplifies a [`Filter`] built from partial results, which often repeat
+//! themselves, e.g., for policies with resource blocks.
+//!
+//! Within a conjunction, conditions that follow from the others are dropped, and a
+//! conjunction that contradicts itself is dropped entirely. Between conjunctions,
+//! those that imply another one are dropped, and those that only differ in the
+//! value of a field are merged into an `IN`. Values are compared as in Polar, and
+//! only literal values are compared at all. Relations that no condition uses any
+//! more are dropped too.
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    filter::{Comparison, Condition, Datum, Filter, Projection, Relation},
+    terms::*,
+};
+
+type Conjunction = HashSet<Condition>;
+
+impl Filter {
+    /// An equivalent filter with fewer conditions and relations.
+    pub fn minimize(self) -> Self {
+        let Filter {
+            root,
+            relations,
+            conditions,
+        } = self;
+
+        let mut conjunctions: Vec<Conjunction> = conditions
+            .into_iter()
+            .filter_map(|conjunction| simplify(&root, conjunction))
+            .collect();
+        loop {
+            let before = conjunctions.len();
+            conjunctions = merge(drop_implied(conjunctions));
+            if conjunctions.len() == before {
+                break;
+            }
+        }
+        if conjunctions.is_empty() {
+            return Filter::empty(&root);
+        }
+
+        // Keep the relations to the types that are still used, and the ones on the
+        // way to them.
+        let mut used: HashSet<&str> = conjunctions
+            .iter()
+            .flatten()
+            .flat_map(|Condition(left, _, right)| [left, right])
+            .filter_map(|datum| match datum {
+                Datum::Field(Projection(typ, _)) => Some(typ.as_str()),
+                _ => None,
+            })
+            .collect();
+        loop {
+            let sources: Vec<&str> = relations
+                .iter()
+                .filter(|Relation(src, _, dst)| {
+                    used.contains(dst.as_str()) && !used.contains(src.as_str())
+                })
+                .map(|Relation(src, _, _)| src.as_str())
+                .collect();
+            if sources.is_empty() {
+                break;
+            }
+            used.extend(sources);
+        }
+        let relations = relations
+            .iter()
+            .filter(|Relation(_, _, dst)| used.contains(dst.as_str()))
+            .cloned()
+            .collect();
+
+        Filter {
+            root,
+            relations,
+            conditions: conjunctions,
+        }
+    }
+}
+
+/// Drop the conditions that follow from the others in a conjunction, or the whole
+/// conjunction if it can't hold.
+fn simplify(root: &str, conjunction: Conjunction) -> Option<Conjunction> {
+    let conditions: Vec<Condition> = conjunction.into_iter().map(minimize_datums).collect();
+
+    // The values fields are equal to, and the conditions that say so.
+    let mut values: HashMap<&Projection, (&Value, usize)> = HashMap::new();
+    for (i, Condition(left, cmp, right)) in conditions.iter().enumerate() {
+        if let (Comparison::Eq, Some((projection, value))) = (cmp, field_value(left, right)) {
+            match values.get(projection) {
+                Some((known, _)) => {
+                    if evaluate(known, Comparison::Eq, value) == Some(false) {
+                        return None;
+                    }
+                }
+                None => {
+                    values.insert(projection, (value, i));
+                }
+            }
+        }
+    }
+
+    let mut simplified = HashSet::new();
+    for (i, condition) in conditions.iter().enumerate() {
+        let Condition(left, cmp, right) = condition;
+        let holds = match (left, right) {
+            (Datum::Immediate(left), Datum::Immediate(right)) => evaluate(left, *cmp, right),
+            // A related record equal to itself only says that it exists, as does
+            // any other condition on it.
+            (Datum::Field(Projection(typ, _)), Datum::Field(_))
+                if left == right && *cmp == Comparison::Eq =>
+            {
+                let other = conditions
+                    .iter()
+                    .enumerate()
+                    .any(|(j, Condition(l, _, r))| {
+                        j != i
+                            && [l, r].into_iter().any(
+                                |datum| matches!(datum, Datum::Field(Projection(t, _)) if t == typ),
+                            )
+                    });
+                if typ == root || other {
+                    Some(true)
+                } else {
+                    None
+                }
+            }
+            _ => [(left, right, true), (right, left, false)]
+                .into_iter()
+                .find_map(|(field, other, field_first)| match (field, other) {
+                    (Datum::Field(projection), Datum::Immediate(other)) => {
+                        match values.get(projection) {
+                            Some((value, j)) if *j != i => Some(if field_first {
+                                evaluate(value, *cmp, other)
+                            } else {
+                                evaluate(other, *cmp, value)
+                            }),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                })
+                .flatten(),
+        };
+        match holds {
+            Some(true) => (),
+            Some(false) => return None,
+            None => {
+                simplified.insert(condition.clone());
+            }
+        }
+    }
+    Some(simplified)
+}
+
+/// Minimize the sub-filters in a condition.
+fn minimize_datums(Condition(left, cmp, right): Condition) -> Condition {
+    let minimize = |datum| match datum {
+        Datum::Subquery(filter) => Datum::Subquery(Box::new(filter.minimize())),
+        Datum::Fixpoint(relation, filter) => Datum::Fixpoint(relation, Box::new(filter.minimize())),
+        datum => datum,
+    };
+    Condition(minimize(left), cmp, minimize(right))
+}
+
+/// Drop the conjunctions that imply another one, keeping the first of equal ones.
+fn drop_implied(conjunctions: Vec<Conjunction>) -> Vec<Conjunction> {
+    let mut kept: Vec<Conjunction> = vec![];
+    for conjunction in conjunctions {
+        if kept.iter().any(|weaker| implies(&conjunction, weaker)) {
+            continue;
+        }
+        kept.retain(|stronger| !implies(stronger, &conjunction));
+        kept.push(conjunction);
+    }
+    kept
+}
+
+/// Merge two conjunctions that only differ in the values of a field, e.g.,
+/// `x.a = 1 and x.b = 2` and `x.a = 1 and x.b in [3, 4]` become
+/// `x.a = 1 and x.b in [2, 3, 4]`. Merges at most one pair at a time.
+fn merge(mut conjunctions: Vec<Conjunction>) -> Vec<Conjunction> {
+    for i in 0..conjunctions.len() {
+        for j in i + 1..conjunctions.len() {
+            if let Some(merged) = merge_pair(&conjunctions[i], &conjunctions[j]) {
+                conjunctions[i] = merged;
+                conjunctions.remove(j);
+                return conjunctions;
+            }
+        }
+    }
+    conjunctions
+}
+
+fn merge_pair(left: &Conjunction, right: &Conjunction) -> Option<Conjunction> {
+    if left.len() != right.len() {
+        return None;
+    }
+    for l in left {
+        let (projection, mut values) = match membership(l) {
+            Some(membership) => membership,
+            None => continue,
+        };
+        for r in right {
+            match membership(r) {
+                Some((other, other_values)) if other == projection => {
+                    let rest = |conjunction: &Conjunction, except| {
+                        conjunction
+                            .iter()
+                            .filter(|c| *c != except)
+                            .cloned()
+                            .collect::<Conjunction>()
+                    };
+                    let rest_left = rest(left, l);
+                    if !equivalent(&rest_left, &rest(right, r)) {
+                        continue;
+                    }
+                    for value in other_values {
+                        if !values.contains(&value) {
+                            values.push(value);
+                        }
+                    }
+                    let field = Datum::Field(projection.clone());
+                    let mut merged = rest_left;
+                    merged.insert(match &values[..] {
+                        [value] => Condition(
+                            field,
+                            Comparison::Eq,
+                            Datum::Immediate(value.value().clone()),
+                        ),
+                        _ => {
+                            Condition(field, Comparison::In, Datum::Immediate(Value::List(values)))
+                        }
+                    });
+                    return Some(merged);
+                }
+                _ => (),
+            }
+        }
+    }
+    None
+}
+
+/// `x = v` or `x in [v, ...]` for literal values, as `x` and the values.
+fn membership(Condition(left, cmp, right): &Condition) -> Option<(&Projection, Vec<Term>)> {
+    match (cmp, left, right) {
+        (Comparison::Eq, _, _) => {
+            let (projection, value) = field_value(left, right)?;
+            Some((projection, vec![Term::from(value.clone())]))
+        }
+        (Comparison::In, Datum::Field(projection), Datum::Immediate(Value::List(list)))
+            if list.iter().all(|item| is_literal(item.value())) =>
+        {
+            Some((projection, list.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// A field compared with a literal value, in either order.
+fn field_value<'a>(left: &'a Datum, right: &'a Datum) -> Option<(&'a Projection, &'a Value)> {
+    match (left, right) {
+        (Datum::Field(projection), Datum::Immediate(value))
+        | (Datum::Immediate(value), Datum::Field(projection))
+            if is_literal(value) =>
+        {
+            Some((projection, value))
+        }
+        _ => None,
+    }
+}
+
+fn is_literal(value: &Value) -> bool {
+    matches!(
+        value,
+        Value::Number(_) | Value::String(_) | Value::Boolean(_)
+    )
+}
+
+/// Whether `left cmp right` holds, if that doesn't depend on the data source.
+fn evaluate(left: &Value, cmp: Comparison, right: &Value) -> Option<bool> {
+    use Comparison::*;
+    if let (In | Nin, Value::List(list)) = (cmp, right) {
+        let mut found = false;
+        for item in list {
+            found |= evaluate(left, Eq, item.value())?;
+        }
+        return Some(found == (cmp == In));
+    }
+    let ordering = match (left, right) {
+        (Value::Number(left), Value::Number(right)) => left.partial_cmp(right)?,
+        (Value::String(left), Value::String(right)) => left.cmp(right),
+        (Value::Boolean(left), Value::Boolean(right)) if matches!(cmp, Eq | Neq) => left.cmp(right),
+        _ => return None,
+    };
+    match cmp {
+        Eq => Some(ordering == Ordering::Equal),
+        Neq => Some(ordering != Ordering::Equal),
+        Lt => Some(ordering == Ordering::Less),
+        Leq => Some(ordering != Ordering::Greater),
+        Gt => Some(ordering == Ordering::Greater),
+        Geq => Some(ordering != Ordering::Less),
+        In | Nin => None,
+    }
+}
+
+/// Whether two conditions are the same, up to the order of their sides.
+fn same(Condition(l1, c1, r1): &Condition, Condition(l2, c2, r2): &Condition) -> bool {
+    use Comparison::*;
+    let flipped = match c1 {
+        Eq | Neq => *c1,
+        Lt => Gt,
+        Leq => Geq,
+        Gt => Lt,
+        Geq => Leq,
+        In | Nin => return l1 == l2 && c1 == c2 && r1 == r2,
+    };
+    (l1 == l2 && c1 == c2 && r1 == r2) || (l1 == r2 && flipped == *c2 && r1 == l2)
+}
+
+/// Whether every condition in `weaker` is in `stronger`.
+fn implies(stronger: &Conjunction, weaker: &Conjunction) -> bool {
+    weaker.iter().all(|w| stronger.iter().any(|s| same(s, w)))
+}
+
+fn equivalent(left: &Conjunction, right: &Conjunction) -> bool {
+    left.len() == right.len() && implies(left, right) && implies(right, left)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filter::singleton;
+    use crate::formatting::ToPolarString;
+
+    fn field(typ: &str, field: &str) -> Datum {
+        Datum::Field(Projection(typ.to_string(), Some(field.to_string())))
+    }
+
+    fn filter(conditions: Vec<Conjunction>) -> Filter {
+        Filter {
+            root: "Repo".to_string(),
+            relations: singleton(Relation("Repo".into(), "org".into(), "Org".into())),
+            conditions,
+        }
+    }
+
+    #[test]
+    fn test_minimize() {
+        let name = |value: &str| {
+            Condition(
+                field("Repo", "name"),
+                Comparison::Eq,
+                Datum::Immediate(value!(value)),
+            )
+        };
+        let public = Condition(
+            Datum::Immediate(value!(true)),
+            Comparison::Eq,
+            field("Repo", "public"),
+        );
+        let org = Condition(
+            field("Org", "name"),
+            Comparison::Eq,
+            Datum::Immediate(value!("osohq")),
+        );
+
+        // Equal and implied conjunctions.
+        let minimized = filter(vec![
+            hashset! { public.clone(), org.clone() },
+            singleton(public.clone()),
+            singleton(Condition(
+                field("Repo", "public"),
+                Comparison::Eq,
+                Datum::Immediate(value!(true)),
+            )),
+        ])
+        .minimize();
+        assert_eq!(minimized.conditions, vec![singleton(public.clone())]);
+        assert!(minimized.relations.is_empty());
+
+        // Values of the same field.
+        let minimized = filter(vec![
+            hashset! { org.clone(), name("oso") },
+            hashset! { org.clone(), name("demo") },
+            hashset! { org.clone(), Condition(
+                field("Repo", "name"),
+                Comparison::In,
+                Datum::Immediate(value!(["oso", "swift"])),
+            )},
+        ])
+        .minimize();
+        assert_eq!(minimized.relations.len(), 1);
+        assert_eq!(minimized.conditions.len(), 1);
+        let merged = minimized.conditions[0].iter().find(|c| c != &&org).unwrap();
+        match merged {
+            Condition(left, Comparison::In, Datum::Immediate(Value::List(list))) => {
+                assert_eq!(left, &field("Repo", "name"));
+                let mut names: Vec<_> = list.iter().map(|v| v.to_polar()).collect();
+                names.sort();
+                assert_eq!(names, vec!["\"demo\"", "\"oso\"", "\"swift\""]);
+            }
+            _ => panic!("unexpected: {}", merged),
+        }
+
+        // Contradictions and conditions that follow from others.
+        let minimized = filter(vec![
+            hashset! { name("oso"), name("demo") },
+            hashset! { name("oso"), Condition(field("Repo", "name"), Comparison::Nin, Datum::Immediate(value!(["oso"]))) },
+            hashset! { public.clone(), Condition(field("Repo", "public"), Comparison::Neq, Datum::Immediate(value!(true))) },
+            hashset! {
+                name("oso"),
+                Condition(Datum::Immediate(value!("a")), Comparison::Lt, field("Repo", "name")),
+                Condition(field("Repo", "name"), Comparison::Neq, Datum::Immediate(value!("demo"))),
+                Condition(Datum::Immediate(value!(1)), Comparison::Eq, Datum::Immediate(value!(1))),
+                Condition(field("Repo", "id"), Comparison::Eq, field("Repo", "id")),
+            },
+        ])
+        .minimize();
+        assert_eq!(minimized.conditions, vec![singleton(name("oso"))]);
+
+        // A related record equal to itself exists, which only matters by itself.
+        let exists = |typ: &str| {
+            let record = Datum::Field(Projection(typ.to_string(), None));
+            Condition(record.clone(), Comparison::Eq, record)
+        };
+        let minimized = filter(vec![
+            hashset! { exists("Org"), exists("Repo"), public.clone() },
+        ])
+        .minimize();
+        assert_eq!(
+            minimized.conditions,
+            vec![hashset! { exists("Org"), public.clone() }]
+        );
+        assert_eq!(minimized.relations.len(), 1);
+        let minimized = filter(vec![hashset! { exists("Org"), org.clone() }]).minimize();
+        assert_eq!(minimized.conditions, vec![singleton(org.clone())]);
+
+        // Nothing passes.
+        let minimized = filter(vec![hashset! { name("oso"), name("demo") }]).minimize();
+        assert_eq!(minimized, Filter::empty("Repo"));
+    }
+}
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use crate::lexer::Token;
 use lalrpop_util::{lalrpop_mod, ParseError};
 
@@ -23,6 +25,15 @@ use super::terms::*;
 pub enum Line {
     Rule(Rule),
     RuleType(Rule),
+    /// A rule or rule type preceded by a keyword, e.g., `tabled type f(x);`.
+    TabledRule {
+        keyword: Term,
+        rule: Rule,
+    },
+    TabledRuleType {
+        keyword: Term,
+        rule_type: Rule,
+    },
     Query(Term),
     ResourceBlock {
         keyword: Option<Term>,
@@ -31,6 +42,49 @@ pub enum Line {
     },
 }
 
+/// A call in goal position.
+///
+/// `count`, `collect`, `min`, `max` and `sum` aren't reserved words: a call to one
+/// of them is an aggregate only if it has three arguments and the second is a goal,
+/// e.g., `count(x, f(x), n)`. Any other call, e.g., `sum(a, b, c)`, calls a rule,
+/// and all of its arguments must be values.
+pub fn goal_call(
+    name: Symbol,
+    args: Vec<(usize, ValueOrLogical)>,
+    kwargs: Option<BTreeMap<Symbol, Term>>,
+) -> Result<Value, error::ParseError> {
+    let aggregate = match &*name.as_str() {
+        "count" => Some(Operator::Count),
+        "collect" => Some(Operator::Collect),
+        "min" => Some(Operator::Min),
+        "max" => Some(Operator::Max),
+        "sum" => Some(Operator::Sum),
+        _ => None,
+    };
+    let aggregate = match (&args[..], &kwargs) {
+        ([_, (_, ValueOrLogical::Logical(_)), _], None) => aggregate,
+        _ => None,
+    };
+
+    let args = args
+        .into_iter()
+        .enumerate()
+        .map(|(i, (loc, arg))| match arg {
+            ValueOrLogical::Logical(term) if aggregate.is_some() && i == 1 => Ok(term),
+            ValueOrLogical::Logical(term) => Err(error::ParseError::WrongValueType {
+                loc,
+                term,
+                expected: "value".to_string(),
+            }),
+            ValueOrLogical::Value(term) | ValueOrLogical::Either(term) => Ok(term),
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(match aggregate {
+        Some(operator) => Value::Expression(Operation { operator, args }),
+        None => Value::Call(Call { name, args, kwargs }),
+    })
+}
+
 fn to_parse_error(e: ParseError<usize, lexer::Token, error::ParseError>) -> error::ParseError {
     match e {
         ParseError::InvalidToken { location: loc } => error::ParseError::InvalidToken { loc },
@@ -216,6 +270,23 @@ mod tests {
             line[0],
             Line::RuleType(rule!("f", ["x"; value!(instance!("String"))]))
         );
+
+        let tabled = r#"tabled type f(x: String); tabled f(x) if x = 1;"#;
+        let line = parse_lines(tabled);
+        assert_eq!(
+            line[0],
+            Line::TabledRuleType {
+                keyword: term!(sym!("tabled")),
+                rule_type: rule!("f", ["x"; value!(instance!("String"))])
+            }
+        );
+        assert_eq!(
+            line[1],
+            Line::TabledRule {
+                keyword: term!(sym!("tabled")),
+                rule: rule!("f", [sym!("x")] => op!(Unify, term!(sym!("x")), term!(1)))
+            }
+        );
     }
 
     #[test]
@@ -311,6 +382,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_aggregates() {
+        assert_eq!(
+            parse_query("count(x, f(x), n)"),
+            term!(op!(
+                Count,
+                term!(sym!("x")),
+                term!(call!("f", [sym!("x")])),
+                term!(sym!("n"))
+            ))
+        );
+
+        // Aggregate names aren't reserved, so without a goal these are calls.
+        assert_eq!(
+            parse_query("sum(1, 2, x)"),
+            term!(call!("sum", [1, 2, sym!("x")]))
+        );
+        assert_eq!(
+            parse_query("count(x, y, n)"),
+            term!(call!("count", [sym!("x"), sym!("y"), sym!("n")]))
+        );
+        assert_eq!(
+            parse_query("count > 1"),
+            term!(op!(Gt, term!(sym!("count")), term!(1)))
+        );
+        let rule = parse_rule("f(count) if count > 1;");
+        assert_eq!(rule.to_polar(), "f(count) if count > 1;");
+        let rule = parse_rule("max(a, b, a) if a > b;");
+        assert_eq!(rule.to_polar(), "max(a, b, a) if a > b;");
+    }
+
     #[test]
     fn test_catching_wrong_types() {
         for bad_query in &[
@@ -322,6 +424,7 @@ mod tests {
             "1 and 2",
             "1 + print(\"x\")",
             "forall([1, 2, 3], x < 1)",
+            "count(x, f(x), n and m)",
             "x = (1 or 2)",
             "x = (1 = 2)",
             "foo.bar(x or y)",
@@ -62,6 +62,10 @@ pub fn invert_operation(Operation { operator, args }: Operation) -> Operation {
             operator: Operator::Not,
             args: vec![term!(op!(Isa, args[0].clone(), args[1].clone()))],
         },
+        Operator::In => Operation {
+            operator: Operator::Not,
+            args: vec![term!(op!(In, args[0].clone(), args[1].clone()))],
+        },
         Operator::Not => args[0]
             .value()
             .as_expression()
@@ -304,6 +308,7 @@ mod test {
     use crate::formatting::ToPolarString;
     use crate::polar::Polar;
     use crate::query::Query;
+    use crate::terms::*;
     use crate::terms::{Call, Dictionary, InstanceLiteral, Pattern};
 
     macro_rules! assert_partial_expression {
@@ -357,7 +362,7 @@ mod test {
                         "Bindings: {}",
                         bindings
                             .iter()
-                            .map(|(k, v)| format!("{}: {}", k.0, v.to_polar()))
+                            .map(|(k, v)| format!("{}: {}", k, v.to_polar()))
                             .collect::<Vec<String>>()
                             .join("\n")
                     )
@@ -389,6 +394,33 @@ mod test {
         }
     }
 
+    /// The partial bound to `x`, with the temporaries renamed from `y` numbered by
+    /// their first appearance, so it doesn't depend on how many were made before.
+    fn renumbered_x(bindings: Bindings) -> String {
+        let expression = bindings[&sym!("x")].to_polar();
+        let mut seen = vec![];
+        let mut renumbered = String::new();
+        let mut rest = expression.as_str();
+        while let Some(start) = rest.find("_y_") {
+            let (before, var) = rest.split_at(start);
+            let end = var[3..]
+                .find(|c: char| !c.is_ascii_digit())
+                .map_or(var.len(), |end| end + 3);
+            let index = match seen.iter().position(|name| *name == &var[..end]) {
+                Some(index) => index,
+                None => {
+                    seen.push(&var[..end]);
+                    seen.len() - 1
+                }
+            };
+            renumbered.push_str(before);
+            renumbered.push_str(&format!("_y_{}", index));
+            rest = &var[end..];
+        }
+        renumbered.push_str(rest);
+        renumbered
+    }
+
     type TestResult = Result<(), PolarError>;
 
     #[test]
@@ -430,11 +462,11 @@ mod test {
         )?;
         let mut q = p.new_query_from_term(term!(call!("f", [sym!("x"), 1, 2])), false);
         let next = next_binding(&mut q)?;
-        assert_partial_expression!(next, "x", "_this < 1 and _this < 2 and _this < 3");
+        assert_partial_expression!(next, "x", "_this < 1");
         let next = next_binding(&mut q)?;
-        assert_partial_expression!(next, "x", "_this < 1 and _this < 2 and _this < 4");
+        assert_partial_expression!(next, "x", "_this < 1");
         let next = next_binding(&mut q)?;
-        assert_partial_expression!(next, "x", "_this < 1 and _this < 2 and _this < 5");
+        assert_partial_expression!(next, "x", "_this < 1");
         assert_query_done!(q);
         Ok(())
     }
@@ -686,17 +718,18 @@ mod test {
         p.load_str(
             r#"f(_: Post{id: 1});
                g(x: Post{id: 1}) if x matches {id: 2}; # Will fail.
-               h(x: Post{id: 1}) if x matches Post{id: 2};
+               h(x: Post{id: 1}) if x matches Post{id: 2}; # Will fail.
                i(x: Post{id: 1}) if x matches User{id: 2}; # Will fail.
-               j(x: Post{id: 1}) if x matches {id: 2, bar: 2};
+               j(x: Post{id: 1}) if x matches {id: 2, bar: 2}; # Will fail.
                k(x: Post{id: 1, bar: 1}) if x matches User{id: 2}; # Will fail.
-               l(x: Post{id: 1, bar: 3}) if x matches Post{id: 2} and x.y = 1;
-               m(x: {id: 1, bar: 1}) if x matches {id: 2};
-               n(x: {id: 1}) if x matches {id: 2, bar: 2};
+               l(x: Post{id: 1, bar: 3}) if x matches Post{id: 2} and x.y = 1; # Will fail.
+               m(x: {id: 1, bar: 1}) if x matches {id: 2}; # Will fail.
+               n(x: {id: 1}) if x matches {id: 2, bar: 2}; # Will fail.
                o(_: {id: 1});
-               p(x: {id: 1}) if x matches {id: 2};
-               q(x: {id: 1}) if x matches Post{id: 2};
-               r(_: 1);"#,
+               p(x: {id: 1}) if x matches {id: 2}; # Will fail.
+               q(x: {id: 1}) if x matches Post{id: 2}; # Will fail.
+               r(_: 1);
+               s(x: Post{id: 1}) if x matches Post{bar: 2};"#,
         )?;
         let next_binding = |q: &mut Query| loop {
             match q.next_event().unwrap() {
@@ -706,8 +739,13 @@ mod test {
                     left_class_tag,
                     right_class_tag,
                 } => {
-                    q.question_result(call_id, left_class_tag.0.starts_with(&right_class_tag.0))
-                        .unwrap();
+                    q.question_result(
+                        call_id,
+                        left_class_tag
+                            .as_str()
+                            .starts_with(&*right_class_tag.as_str()),
+                    )
+                    .unwrap();
                 }
                 QueryEvent::Done { .. } => return None,
                 _ => panic!("not bindings"),
@@ -723,20 +761,11 @@ mod test {
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("g", [sym!("x")])), false);
-        // TODO(gj): Inconsistent dot op unifications.
-        assert_partial_expression!(
-            next_binding(&mut q).unwrap(),
-            "x",
-            "_this matches Post{} and _this.id = 1 and _this.id = 2"
-        );
+        assert!(next_binding(&mut q).is_none());
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("h", [sym!("x")])), false);
-        assert_partial_expression!(
-            next_binding(&mut q).unwrap(),
-            "x",
-            "_this matches Post{} and _this.id = 1 and _this.id = 2"
-        );
+        assert!(next_binding(&mut q).is_none());
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("i", [sym!("x")])), false);
@@ -744,11 +773,7 @@ mod test {
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("j", [sym!("x")])), false);
-        assert_partial_expression!(
-            next_binding(&mut q).unwrap(),
-            "x",
-            "_this matches Post{} and _this.id = 1 and _this.id = 2 and _this.bar = 2"
-        );
+        assert!(next_binding(&mut q).is_none());
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("k", [sym!("x")])), false);
@@ -756,27 +781,15 @@ mod test {
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("l", [sym!("x")])), false);
-        assert_partial_expression!(
-            next_binding(&mut q).unwrap(),
-            "x",
-            "_this matches Post{} and _this.id = 1 and _this.bar = 3 and _this.id = 2 and 1 = _this.y"
-        );
+        assert!(next_binding(&mut q).is_none());
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("m", [sym!("x")])), false);
-        assert_partial_expression!(
-            next_binding(&mut q).unwrap(),
-            "x",
-            "_this.id = 1 and _this.bar = 1 and _this.id = 2"
-        );
+        assert!(next_binding(&mut q).is_none());
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("n", [sym!("x")])), false);
-        assert_partial_expression!(
-            next_binding(&mut q).unwrap(),
-            "x",
-            "_this.id = 1 and _this.id = 2 and _this.bar = 2"
-        );
+        assert!(next_binding(&mut q).is_none());
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("o", [sym!("x")])), false);
@@ -784,24 +797,24 @@ mod test {
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("p", [sym!("x")])), false);
-        assert_partial_expression!(
-            next_binding(&mut q).unwrap(),
-            "x",
-            "_this.id = 1 and _this.id = 2"
-        );
+        assert!(next_binding(&mut q).is_none());
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("q", [sym!("x")])), false);
-        assert_partial_expression!(
-            next_binding(&mut q).unwrap(),
-            "x",
-            "_this.id = 1 and _this matches Post{} and _this.id = 2"
-        );
+        assert!(next_binding(&mut q).is_none());
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("r", [sym!("x")])), false);
         assert_eq!(next_binding(&mut q).unwrap()[&sym!("x")], term!(1));
         assert_query_done!(q);
+
+        let mut q = p.new_query_from_term(term!(call!("s", [sym!("x")])), false);
+        assert_partial_expression!(
+            next_binding(&mut q).unwrap(),
+            "x",
+            "_this matches Post{} and _this.id = 1 and _this.bar = 2"
+        );
+        assert_query_done!(q);
         Ok(())
     }
 
@@ -825,8 +838,13 @@ mod test {
                     left_class_tag,
                     right_class_tag,
                 } => {
-                    q.question_result(call_id, left_class_tag.0.starts_with(&right_class_tag.0))
-                        .unwrap();
+                    q.question_result(
+                        call_id,
+                        left_class_tag
+                            .as_str()
+                            .starts_with(&*right_class_tag.as_str()),
+                    )
+                    .unwrap();
                 }
                 e => panic!("unexpected event: {:?}", e),
             }
@@ -871,8 +889,13 @@ mod test {
                     left_class_tag,
                     right_class_tag,
                 } => {
-                    q.question_result(call_id, left_class_tag.0.starts_with(&right_class_tag.0))
-                        .unwrap();
+                    q.question_result(
+                        call_id,
+                        left_class_tag
+                            .as_str()
+                            .starts_with(&*right_class_tag.as_str()),
+                    )
+                    .unwrap();
                 }
                 _ => panic!("not bindings"),
             }
@@ -896,7 +919,7 @@ mod test {
         )?;
         let mut q = p.new_query_from_term(term!(call!("positive", [sym!("a")])), false);
         assert_partial_expression!(next_binding(&mut q)?, "a", "_this > 0");
-        assert_partial_expression!(next_binding(&mut q)?, "a", "_this > 0 and _this < 0");
+        // `x > 0 and x < 0` can't be satisfied.
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("zero", [sym!("a")])), false);
@@ -946,8 +969,7 @@ mod test {
         let p = Polar::new();
         p.load_str("a_positive(x) if x.a > 0 and 0 < x.a;")?;
         let mut q = p.new_query_from_term(term!(call!("a_positive", [sym!("x")])), false);
-        // TODO(gj): Canonicalize comparisons.
-        assert_partial_expression!(next_binding(&mut q)?, "x", "_this.a > 0 and 0 < _this.a");
+        assert_partial_expression!(next_binding(&mut q)?, "x", "_this.a > 0");
         assert_query_done!(q);
         Ok(())
     }
@@ -963,12 +985,8 @@ mod test {
         assert_partial_expression!(next_binding(&mut q)?, "x", "_this.y.z > 0");
         assert_query_done!(q);
 
+        // `x.y = 0 and x.y > 1` can't be satisfied.
         let mut q = p.new_query_from_term(term!(call!("g", [sym!("x")])), false);
-        assert_partial_expression!(
-            next_binding(&mut q)?,
-            "x",
-            "0 = _this.y and _this.y > 1 and _this.y.z > 1"
-        );
         assert_query_done!(q);
         Ok(())
     }
@@ -1011,6 +1029,22 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_aggregate_over_partial() -> TestResult {
+        let p = Polar::new();
+        p.load_str("f(x, n) if x > 0 and count(y, y = x, n);")?;
+        let mut q = p.new_query_from_term(term!(call!("f", [sym!("a"), sym!("n")])), false);
+        let error = q.next_event().unwrap_err();
+        assert!(matches!(
+            error,
+            PolarError {
+                kind: ErrorKind::Runtime(RuntimeError::Unsupported { .. }),
+                ..
+            }
+        ));
+        Ok(())
+    }
+
     #[test]
     fn test_rule_filtering_with_partials() -> TestResult {
         let p = Polar::new();
@@ -1059,7 +1093,8 @@ mod test {
                         let last_segment = path.last().unwrap();
                         q.question_result(
                             call_id,
-                            last_segment.value().as_string().unwrap().to_uppercase() == class_tag.0,
+                            last_segment.value().as_string().unwrap().to_uppercase()
+                                == class_tag.as_str(),
                         )
                         .unwrap();
                     }
@@ -1450,16 +1485,12 @@ mod test {
         assert_partial_expression!(
             next_binding(&mut q)?,
             "x",
-            "_this != 2 and _this <= 3 and _this != 4 and _this > 1"
+            "_this != 2 and _this <= 3 and _this > 1"
         );
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("t", [sym!("x")])), false);
-        assert_partial_expression!(
-            next_binding(&mut q)?,
-            "x",
-            "_this > 0 and _this > 1 and _this > 2 and (_this > 3 or _this > 4 or _this > 5)"
-        );
+        assert_partial_expression!(next_binding(&mut q)?, "x", "_this > 3");
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("u", [sym!("x")])), false);
@@ -1507,7 +1538,7 @@ mod test {
         )?;
 
         let mut q = p.new_query_from_term(term!(call!("f", [sym!("x")])), false);
-        assert_partial_expression!(next_binding(&mut q)?, "x", "_this > 1 and _this >= 0");
+        assert_partial_expression!(next_binding(&mut q)?, "x", "_this > 1");
         assert_query_done!(q);
 
         let mut q = p.new_query_from_term(term!(call!("g", [sym!("x")])), false);
@@ -1615,13 +1646,52 @@ mod test {
     fn test_negated_in_partial_lhs() -> TestResult {
         let p = Polar::new();
         p.load_str("not_lhs(x) if not x in [1, 2];")?;
-        // Inverting an `in` produces a conjunction of the inverted disjunctive constraints.
+        // Inverting an `in` over a ground list keeps the list whole. The legacy
+        // `build_filter_plan` still reads it as `_this != 1 and _this != 2`.
         let mut q = p.new_query_from_term(term!(call!("not_lhs", [sym!("x")])), false);
-        assert_partial_expression!(next_binding(&mut q)?, "x", "_this != 1 and _this != 2");
+        assert_partial_expression!(next_binding(&mut q)?, "x", "not _this in [1, 2]");
         assert_query_done!(q);
         Ok(())
     }
 
+    #[test]
+    fn test_forall_partial() -> TestResult {
+        let p = Polar::new();
+        p.load_str(
+            r#"f(x) if forall(y in x.tags, y.level > 2);
+               g(x) if x.owner = "alice" and forall(y in x.tags, y in ["a", "b"]);
+               h(x) if forall(y in x, y > 1 and y < 5);
+               i(x) if forall(y in [1, 2], y < x);"#,
+        )?;
+
+        let mut q = p.new_query_from_term(term!(call!("f", [sym!("x")])), false);
+        assert_eq!(
+            renumbered_x(next_binding(&mut q)?),
+            "(not _y_0 in _this.tags or _y_0.level > 2)"
+        );
+        assert_query_done!(q);
+
+        let mut q = p.new_query_from_term(term!(call!("g", [sym!("x")])), false);
+        assert_eq!(
+            renumbered_x(next_binding(&mut q)?),
+            "\"alice\" = _this.owner and (not _y_0 in _this.tags or _y_0 in [\"a\", \"b\"])"
+        );
+        assert_query_done!(q);
+
+        let mut q = p.new_query_from_term(term!(call!("h", [sym!("x")])), false);
+        assert_eq!(
+            renumbered_x(next_binding(&mut q)?),
+            "(not _y_0 in _this or _y_0 > 1 and _y_0 < 5)"
+        );
+        assert_query_done!(q);
+
+        let mut q = p.new_query_from_term(term!(call!("i", [sym!("x")])), false);
+        assert_partial_expression!(next_binding(&mut q)?, "x", "2 < _this");
+        assert_query_done!(q);
+
+        Ok(())
+    }
+
     #[test]
     fn test_contains_partial() -> TestResult {
         let p = Polar::new();
@@ -1730,6 +1800,25 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_cut_after_partial_constraint_errors() -> TestResult {
+        let p = Polar::new();
+        p.load_str(
+            r#"f(x) if x > 1 and cut;
+               f(x) if x = 0;"#,
+        )?;
+        let mut q = p.new_query_from_term(term!(call!("f", [sym!("a")])), false);
+        let error = q.next_event().unwrap_err();
+        assert!(matches!(
+            error.kind,
+            ErrorKind::Runtime(RuntimeError::Unsupported { .. })
+        ));
+        let range = error.context.expect("cut has a source span").range;
+        assert_eq!((range.start.row, range.start.column), (0, 18));
+        assert_eq!((range.end.row, range.end.column), (0, 21));
+        Ok(())
+    }
+
     #[test]
     fn test_cut_with_partial() -> TestResult {
         let p = Polar::new();
@@ -1812,11 +1901,6 @@ mod test {
         let mut q = p.new_query_from_term(term!(call!("f", [1, sym!("y")])), false);
 
         assert_partial_expression!(next_binding(&mut q)?, "y", "1 = _this.a.b.c");
-        assert_partial_expression!(
-            next_binding(&mut q)?,
-            "y",
-            "1 > _this.a.b.c and 1 < _this.a.b and _this.a.b.c > 1"
-        );
         assert_partial_expression!(next_binding(&mut q)?, "y", "1 = _this.a");
         assert_partial_expression!(next_binding(&mut q)?, "y", "1 = _this.a.b");
         assert_partial_expression!(next_binding(&mut q)?, "y", "1 = _this.a.b.c.d");
@@ -1826,6 +1910,66 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_recursive_relation() -> TestResult {
+        let p = Polar::new();
+        p.load_str(
+            r#"f(x) if x.owner = "alice";
+               f(x) if f(x.parent);
+               g(x) if h("viewer", x);
+               h(role, x) if role = "viewer" and x.owner = "alice";
+               h(role, x) if has_relation(parent, "parent", x) and h(role, parent);
+               has_relation(parent, "parent", child) if child.parent = parent;"#,
+        )?;
+        for rule in ["f", "g"] {
+            let mut q = p.new_query_from_term(term!(call!(rule, [sym!("x")])), false);
+            q.set_fixpoints(true);
+            let mut partials = vec![];
+            while let QueryEvent::Result { bindings, .. } = q.next_event()? {
+                partials.push(bindings[&sym!("x")].to_polar());
+            }
+            assert_eq!(partials.len(), 2);
+            assert!(partials.contains(&r#""alice" = _this.owner"#.to_string()));
+            assert!(
+                partials.iter().any(|partial| {
+                    partial.contains(" in _this.parent+ and \"alice\" = _")
+                        && partial.ends_with(".owner")
+                }),
+                "{:?}",
+                partials
+            );
+        }
+
+        // Every folder on the way has to be public, which a closure can't say.
+        for recursive in [
+            "f(x) if x.public = true and f(x.parent);",
+            "f(x) if f(x.parent) and x.public = true;",
+            r#"f(x) if has_relation(parent, "parent", x) and f(parent);
+               has_relation(parent, "parent", child) if child.parent = parent and child.public = true;"#,
+        ] {
+            let p = Polar::new();
+            p.load_str(&format!(r#"f(x) if x.owner = "alice"; {}"#, recursive))?;
+            let mut q = p.new_query_from_term(term!(call!("f", [sym!("x")])), false);
+            q.set_fixpoints(true);
+            let error = loop {
+                match q.next_event() {
+                    Ok(QueryEvent::Done { .. }) => panic!("expected an error for {}", recursive),
+                    Ok(_) => (),
+                    Err(error) => break error,
+                }
+            };
+            assert!(
+                matches!(
+                    error.kind,
+                    ErrorKind::Runtime(RuntimeError::Unsupported { .. })
+                ),
+                "{}",
+                error
+            );
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_partial_negated_isa() -> TestResult {
         let p = Polar::new();
@@ -2103,13 +2247,11 @@ mod test {
             term!(call!("f", [sym!("x"), sym!("y")])),
             &[|r: Bindings| {
                 assert_partial_expressions!(r,
-                    "x" => "_this > 0 and _this != 1",
-                    // Right now we output "y" => "_this <= 1".
-                    // This constraint is incorrect. If x = 1, then y <= 1
-                    // (we reach the y > x constraint in the
+                    // If x = 1, then y <= 1 (we reach the y > x constraint in the
                     // negation). Otherwise, the query suceeds because x = 1 fails
                     // and y > x is never reached.
-                    "y" => "(_this <= 1 or _this < 3 or _this <= 5)"
+                    "x" => "_this > 0 and (_this != 1 or y <= 5)",
+                    "y" => "x > 0 and (x != 1 or _this <= 5)"
                 );
                 Ok(())
             }],
@@ -39,6 +39,194 @@ enum MaybeDrop {
     Check(Symbol, Term),
 }
 
+/// A lower or upper bound on a path, from a comparison with a number.
+#[derive(Clone, Copy)]
+struct Bound {
+    value: Numeric,
+    inclusive: bool,
+    /// Index of the comparison in its conjunction.
+    index: usize,
+}
+
+/// The values allowed for one path by the numeric comparisons in a conjunction.
+#[derive(Default)]
+struct Range {
+    lower: Option<Bound>,
+    upper: Option<Bound>,
+    eq: Option<(Numeric, usize)>,
+    neqs: Vec<(Numeric, usize)>,
+    /// Indices of every comparison on the path.
+    indices: Vec<usize>,
+}
+
+impl Range {
+    /// Narrow the range by `path op value`. Returns `false` if that leaves it empty.
+    fn constrain(&mut self, op: Operator, value: Numeric, index: usize) -> bool {
+        self.indices.push(index);
+        let bound = |inclusive| Bound {
+            value,
+            inclusive,
+            index,
+        };
+        match op {
+            Operator::Gt | Operator::Geq => {
+                let new = bound(op == Operator::Geq);
+                match self.lower {
+                    Some(old)
+                        if old.value > new.value
+                            || old.value == new.value && (!old.inclusive || new.inclusive) => {}
+                    _ => self.lower = Some(new),
+                }
+            }
+            Operator::Lt | Operator::Leq => {
+                let new = bound(op == Operator::Leq);
+                match self.upper {
+                    Some(old)
+                        if old.value < new.value
+                            || old.value == new.value && (!old.inclusive || new.inclusive) => {}
+                    _ => self.upper = Some(new),
+                }
+            }
+            Operator::Neq => self.neqs.push((value, index)),
+            _ => match self.eq {
+                Some((old, _)) if old != value => return false,
+                Some(_) => {}
+                None => self.eq = Some((value, index)),
+            },
+        }
+        true
+    }
+
+    /// Whether `value` is within the bounds.
+    fn contains(&self, value: Numeric) -> bool {
+        let above = |b: &Bound| value > b.value || b.inclusive && value == b.value;
+        let below = |b: &Bound| value < b.value || b.inclusive && value == b.value;
+        self.lower.iter().all(above) && self.upper.iter().all(below)
+    }
+
+    /// The comparisons on `path` that are needed to express the range, by index, with
+    /// the ones that must be rewritten. `None` if no value is in the range.
+    fn simplify(mut self, path: &Term) -> Option<Vec<(usize, Option<Operation>)>> {
+        let compare = |operator, value| Operation {
+            operator,
+            args: vec![path.clone(), term!(Value::Number(value))],
+        };
+        let excluded =
+            |value: Numeric, neqs: &[(Numeric, usize)]| neqs.iter().any(|(n, _)| n == &value);
+
+        if let Some((value, index)) = self.eq {
+            if !self.contains(value) || excluded(value, &self.neqs) {
+                return None;
+            }
+            return Some(vec![(index, None)]);
+        }
+
+        if let (Some(lower), Some(upper)) = (self.lower, self.upper) {
+            if lower.value > upper.value
+                || lower.value == upper.value && !(lower.inclusive && upper.inclusive)
+            {
+                return None;
+            }
+            // `x >= n and x <= n` is `x = n`.
+            if lower.value == upper.value {
+                if excluded(lower.value, &self.neqs) {
+                    return None;
+                }
+                let eq = compare(Operator::Unify, lower.value);
+                return Some(vec![(lower.index, Some(eq))]);
+            }
+        }
+
+        // `x >= n and x != n` is `x > n`.
+        let (lower, upper) = (self.lower, self.upper);
+        for (value, _) in self.neqs.iter() {
+            for bound in [&mut self.lower, &mut self.upper].into_iter().flatten() {
+                if bound.value == *value {
+                    bound.inclusive = false;
+                }
+            }
+        }
+
+        // `x != n` is implied if `n` is out of bounds.
+        let mut needed: Vec<_> = self
+            .neqs
+            .iter()
+            .filter(|(value, _)| self.contains(*value))
+            .map(|(_, index)| (*index, None))
+            .collect();
+        for (old, new, strict) in [
+            (lower, self.lower, Operator::Gt),
+            (upper, self.upper, Operator::Lt),
+        ] {
+            if let (Some(old), Some(new)) = (old, new) {
+                let rewrite = (old.inclusive != new.inclusive).then(|| compare(strict, new.value));
+                needed.push((new.index, rewrite));
+            }
+        }
+        Some(needed)
+    }
+}
+
+/// A comparison of a variable or field with a number, as `(path, op, number)` with
+/// the number on the right.
+fn as_numeric_comparison(o: &Operation) -> Option<(&Term, Operator, Numeric)> {
+    let flip = |op| match op {
+        Operator::Gt => Operator::Lt,
+        Operator::Geq => Operator::Leq,
+        Operator::Lt => Operator::Gt,
+        Operator::Leq => Operator::Geq,
+        op => op,
+    };
+    let is_path = |t: &Term| match t.value() {
+        Value::Variable(_) => true,
+        Value::Expression(Operation {
+            operator: Operator::Dot,
+            ..
+        }) => !t.is_ground(),
+        _ => false,
+    };
+    // NaN compares false with everything, so it can't bound anything.
+    let is_number = |n: &Numeric| n.partial_cmp(n).is_some();
+    match (o.operator, &o.args[..]) {
+        (
+            Operator::Gt
+            | Operator::Geq
+            | Operator::Lt
+            | Operator::Leq
+            | Operator::Eq
+            | Operator::Unify
+            | Operator::Neq,
+            [left, right],
+        ) => match (left.value(), right.value()) {
+            (_, Value::Number(n)) if is_path(left) && is_number(n) => Some((left, o.operator, *n)),
+            (Value::Number(n), _) if is_path(right) && is_number(n) => {
+                Some((right, flip(o.operator), *n))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Evaluate a comparison of two numbers.
+fn compare_numbers(o: &Operation) -> Option<bool> {
+    match &o.args[..] {
+        [left, right] => match (left.value(), right.value()) {
+            (Value::Number(l), Value::Number(r)) => match o.operator {
+                Operator::Gt => Some(l > r),
+                Operator::Geq => Some(l >= r),
+                Operator::Lt => Some(l < r),
+                Operator::Leq => Some(l <= r),
+                Operator::Eq | Operator::Unify => Some(l == r),
+                Operator::Neq => Some(l != r),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
 struct VariableSubber {
     this_var: Symbol,
 }
@@ -123,7 +311,7 @@ pub fn simplify_partial(
     simplifier.simplify_partial(&mut term);
     term = simplify_trivial_constraint(var.clone(), term);
     simplify_debug!("simplify partial done {:?}, {:?}", var, term.to_polar());
-    if matches!(term.value(), Value::Expression(e) if e.operator != Operator::And) {
+    if matches!(term.value(), Value::Expression(e) if e.operator != Operator::And && e != &FALSE) {
         (op!(And, term).into(), simplifier.perf_counters())
     } else {
         (term, simplifier.perf_counters())
@@ -580,6 +768,88 @@ impl Simplifier {
         }
     }
 
+    /// Merge the numeric comparisons on each path in a conjunction into the
+    /// tightest bounds, or an equality if they only allow one value. A conjunction
+    /// that can't be satisfied becomes false, as does a disjunction of them.
+    pub fn simplify_ranges(&mut self, o: &mut Operation, simplify_term: &TermSimplifier) {
+        for arg in &mut o.args {
+            simplify_term(self, arg);
+        }
+
+        let is_false = |t: &Term| matches!(t.value(), Value::Expression(e) if e == &FALSE);
+        match o.operator {
+            Operator::Or => {
+                o.args.retain(|arg| !is_false(arg));
+                if o.args.len() == 1 {
+                    if let Value::Expression(operation) = o.args[0].value() {
+                        *o = operation.clone();
+                    }
+                }
+            }
+            Operator::And if o.args.iter().any(is_false) => *o = FALSE,
+            Operator::And => {
+                let mut keep = vec![true; o.args.len()];
+                let mut ranges: Vec<(Term, Range)> = vec![];
+                for (i, arg) in o.args.iter().enumerate() {
+                    let op = match arg.value().as_expression() {
+                        Ok(op) => op,
+                        Err(_) => continue,
+                    };
+                    if let Some(holds) = compare_numbers(op) {
+                        if !holds {
+                            *o = FALSE;
+                            return;
+                        }
+                        keep[i] = false;
+                    } else if let Some((path, op, value)) = as_numeric_comparison(op) {
+                        let range = match ranges.iter_mut().position(|(p, _)| p == path) {
+                            Some(j) => &mut ranges[j].1,
+                            None => {
+                                ranges.push((path.clone(), Range::default()));
+                                &mut ranges.last_mut().unwrap().1
+                            }
+                        };
+                        if !range.constrain(op, value, i) {
+                            *o = FALSE;
+                            return;
+                        }
+                    }
+                }
+
+                for (path, range) in ranges {
+                    for i in range.indices.iter() {
+                        keep[*i] = false;
+                    }
+                    let needed = match range.simplify(&path) {
+                        Some(needed) => needed,
+                        None => {
+                            *o = FALSE;
+                            return;
+                        }
+                    };
+                    for (i, rewrite) in needed {
+                        keep[i] = true;
+                        if let Some(op) = rewrite {
+                            o.args[i] = o.args[i].clone_with_value(value!(op));
+                        }
+                    }
+                }
+
+                let mut i = 0;
+                o.args.retain(|_| {
+                    i += 1;
+                    keep[i - 1]
+                });
+                if o.args.len() == 1 {
+                    if let Value::Expression(operation) = o.args[0].value() {
+                        *o = operation.clone();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
     /// Simplify a term `term` in place by calling the simplification
     /// function `simplify_operation` on any Expression in that term.
     ///
@@ -657,6 +927,7 @@ impl Simplifier {
         }
 
         self.simplify_term(term, Simplifier::deduplicate_operation);
+        self.simplify_term(term, Simplifier::simplify_ranges);
 
         self.counters.finish_acc(term.clone());
     }
@@ -696,4 +967,59 @@ mod test {
             ))
         );
     }
+
+    fn simplify_ranges(src: &str) -> String {
+        let term = crate::parser::parse_query(0, src).unwrap();
+        let output = std::iter::once(sym!("x")).collect();
+        simplify_partial(&sym!("x"), term, output, false)
+            .0
+            .to_polar()
+    }
+
+    #[test]
+    fn test_simplify_ranges() {
+        // Merge bounds.
+        assert_eq!(
+            simplify_ranges("x > 3 and x > 5 and x <= 9"),
+            "x > 5 and x <= 9"
+        );
+        assert_eq!(
+            simplify_ranges("x.a >= 1 and 4 > x.a and x.a < 3.5"),
+            "x.a >= 1 and x.a < 3.5"
+        );
+        assert_eq!(
+            simplify_ranges("x.a > 1 and x.b > 2 and x.a > 0"),
+            "x.a > 1 and x.b > 2"
+        );
+
+        // Collapse to equality.
+        assert_eq!(simplify_ranges("x.a >= 2 and x.a <= 2"), "x.a = 2");
+        // ...which binds `x`, if that's all there is.
+        assert_eq!(simplify_ranges("x >= 2 and x <= 2"), "2");
+        assert_eq!(
+            simplify_ranges("x.a == 2 and x.a > 1 and x.a != 3"),
+            "x.a == 2"
+        );
+
+        // Fold `!=` into the bounds.
+        assert_eq!(
+            simplify_ranges("x >= 2 and x != 2 and x < 5"),
+            "x > 2 and x < 5"
+        );
+        assert_eq!(
+            simplify_ranges("x > 2 and x != 1 and x != 3"),
+            "x > 2 and x != 3"
+        );
+
+        // Prune what can't be satisfied.
+        assert_eq!(simplify_ranges("x > 3 and x > 5 and x < 2"), "(false)");
+        assert_eq!(simplify_ranges("x > 2 and x <= 2"), "(false)");
+        assert_eq!(simplify_ranges("x >= 2 and x <= 2 and x != 2"), "(false)");
+        assert_eq!(simplify_ranges("x.a = 1 and x.a = 2"), "(false)");
+        assert_eq!(simplify_ranges("x > 1 and 1 > 2"), "(false)");
+        assert_eq!(
+            simplify_ranges("x.a > 1 and (x.b < 0 and x.b > 1 or x.b = 2)"),
+            "x.a > 1 and x.b = 2"
+        );
+    }
 }
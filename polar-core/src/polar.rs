@@ -1,16 +1,19 @@
 use std::sync::{Arc, RwLock};
 
+use super::config::PolarConfig;
 use super::data_filtering::{build_filter_plan, FilterPlan, PartialResults, Types};
 use super::diagnostic::Diagnostic;
 use super::error::{PolarResult, RuntimeError, ValidationError};
-use super::filter::Filter;
+use super::filter::{ExplainedFilter, Filter};
 use super::kb::*;
 use super::messages::*;
 use super::parser;
 use super::query::Query;
 use super::resource_block::resource_block_from_productions;
 use super::rewrites::*;
+use super::rules::Rule;
 use super::sources::*;
+use super::specialize;
 use super::terms::*;
 use super::validations::{
     check_ambiguous_precedence, check_no_allow_rule, check_resource_blocks_missing_has_permission,
@@ -18,9 +21,9 @@ use super::validations::{
 };
 
 pub struct Polar {
-    pub kb: Arc<RwLock<KnowledgeBase>>,
+    kb: RwLock<Arc<RwLock<KnowledgeBase>>>,
     messages: MessageQueue,
-    ignore_no_allow_warning: bool,
+    config: PolarConfig,
 }
 
 impl Default for Polar {
@@ -30,22 +33,42 @@ impl Default for Polar {
 }
 
 impl Polar {
+    /// A new `Polar` configured by the `POLAR_*` environment variables.
     pub fn new() -> Self {
-        // TODO(@gkaemmer): pulling this from an environment variable is a hack
-        // and should not be used for similar cases. See set_ignore_no_allow_warning.
-        // Ideally, we'd have a single "configuration" entrypoint for both the Polar
-        // and Query types, so that we don't have to keep adding environment
-        // variables for new configuration use-cases.
-        let ignore_no_allow_warning = std::env::var("POLAR_IGNORE_NO_ALLOW_WARNING").is_ok();
+        Self::with_config(PolarConfig::from_env())
+    }
+
+    pub fn with_config(config: PolarConfig) -> Self {
         Self {
-            kb: Arc::new(RwLock::new(KnowledgeBase::new())),
+            kb: RwLock::new(Arc::new(RwLock::new(KnowledgeBase::new()))),
             messages: MessageQueue::new(),
-            ignore_no_allow_warning,
+            config,
         }
     }
 
+    pub fn config(&self) -> &PolarConfig {
+        &self.config
+    }
+
+    /// The current knowledge base. Queries keep the knowledge base they were created with,
+    /// even if it is replaced by `replace_kb`.
+    pub fn kb(&self) -> Arc<RwLock<KnowledgeBase>> {
+        self.kb.read().unwrap().clone()
+    }
+
     /// Load `sources` into the KB, returning compile-time diagnostics accumulated during the load.
     pub fn diagnostic_load(&self, sources: Vec<Source>) -> Vec<Diagnostic> {
+        /// `tabled` is the only keyword that may precede a rule or rule type.
+        fn check_tabled_keyword(keyword: &Term) -> Result<(), String> {
+            match keyword.value().as_symbol() {
+                Ok(name) if name.as_str() == "tabled" => Ok(()),
+                _ => Err(format!(
+                    "Expected 'tabled' but found '{}'.",
+                    keyword.to_polar()
+                )),
+            }
+        }
+
         // we extract this into a separate function
         // so that any errors returned with `?` are captured
         fn load_source(
@@ -92,6 +115,29 @@ impl Polar {
                             kb.add_rule_type(rule_type);
                         }
                     }
+                    parser::Line::TabledRule { keyword, rule } => {
+                        match check_tabled_keyword(&keyword) {
+                            Ok(()) => {
+                                kb.table_rule(rule.name.clone());
+                                lines.push(parser::Line::Rule(rule));
+                            }
+                            Err(msg) => diagnostics.push(Diagnostic::Error(
+                                ValidationError::InvalidRule { rule, msg }.with_context(&*kb),
+                            )),
+                        }
+                    }
+                    parser::Line::TabledRuleType { keyword, rule_type } => {
+                        match check_tabled_keyword(&keyword) {
+                            Ok(()) => {
+                                kb.table_rule(rule_type.name.clone());
+                                lines.push(parser::Line::RuleType(rule_type));
+                            }
+                            Err(msg) => diagnostics.push(Diagnostic::Error(
+                                ValidationError::InvalidRuleType { rule_type, msg }
+                                    .with_context(&*kb),
+                            )),
+                        }
+                    }
                     parser::Line::ResourceBlock {
                         keyword,
                         resource,
@@ -110,7 +156,8 @@ impl Polar {
             Ok(diagnostics)
         }
 
-        let mut kb = self.kb.write().unwrap();
+        let kb = self.kb();
+        let mut kb = kb.write().unwrap();
         let mut diagnostics = vec![];
 
         for source in &sources {
@@ -159,7 +206,7 @@ impl Polar {
         diagnostics.append(&mut kb.validate_rules());
 
         // Perform validation checks against the whole policy
-        if !self.ignore_no_allow_warning {
+        if !self.config.ignore_no_allow_warning {
             if let Some(w) = check_no_allow_rule(&kb) {
                 diagnostics.push(w)
             }
@@ -180,7 +227,7 @@ impl Polar {
 
     /// Load `Source`s into the KB.
     pub fn load(&self, sources: Vec<Source>) -> PolarResult<()> {
-        if let Ok(kb) = self.kb.read() {
+        if let Ok(kb) = self.kb().read() {
             if kb.has_rules() {
                 return Err(RuntimeError::MultipleLoadError.with_context(&*kb));
             }
@@ -203,6 +250,26 @@ impl Polar {
         Ok(())
     }
 
+    /// Load `sources` into a new `Polar` whose KB has this one's registered constants and
+    /// MROs but none of its rules, leaving this `Polar` untouched. Pass the result to
+    /// `replace_kb` to start using the new policy.
+    pub fn load_staged(&self, sources: Vec<Source>) -> PolarResult<Self> {
+        let kb = self.kb().read().unwrap().fork();
+        let staged = Self {
+            kb: RwLock::new(Arc::new(RwLock::new(kb))),
+            messages: self.messages.clone(),
+            config: self.config.clone(),
+        };
+        staged.load(sources)?;
+        Ok(staged)
+    }
+
+    /// Replace the KB with the one built by `load_staged`. Queries that are already
+    /// running finish against the KB they started with.
+    pub fn replace_kb(&self, staged: Self) {
+        *self.kb.write().unwrap() = staged.kb();
+    }
+
     // Used in integration tests
     pub fn load_str(&self, src: &str) -> PolarResult<()> {
         self.load(vec![Source {
@@ -213,12 +280,13 @@ impl Polar {
 
     /// Clear rules from the knowledge base
     pub fn clear_rules(&self) {
-        let mut kb = self.kb.write().unwrap();
+        let kb = self.kb();
+        let mut kb = kb.write().unwrap();
         kb.clear_rules();
     }
 
     pub fn next_inline_query(&self, trace: bool) -> Option<Query> {
-        let term = { self.kb.write().unwrap().inline_queries.pop() };
+        let term = { self.kb().write().unwrap().inline_queries.pop() };
         term.map(|t| self.new_query_from_term(t, trace))
     }
 
@@ -228,7 +296,8 @@ impl Polar {
             src: src.to_owned(),
         };
         let term = {
-            let mut kb = self.kb.write().unwrap();
+            let kb = self.kb();
+            let mut kb = kb.write().unwrap();
             let src_id = kb.new_id();
             let term =
                 parser::parse_query(src_id, src).map_err(|e| e.with_context(source.clone()))?;
@@ -238,26 +307,37 @@ impl Polar {
         Ok(self.new_query_from_term(term, trace))
     }
 
-    pub fn new_query_from_term(&self, mut term: Term, trace: bool) -> Query {
+    pub fn new_query_from_term(&self, term: Term, trace: bool) -> Query {
+        self.new_query_from_term_with_config(term, trace, &self.config)
+    }
+
+    /// Like `new_query_from_term`, but the query's timeout, stack limit and
+    /// logging come from `config` instead of this `Polar`'s config.
+    pub fn new_query_from_term_with_config(
+        &self,
+        mut term: Term,
+        trace: bool,
+        config: &PolarConfig,
+    ) -> Query {
         use crate::vm::{Goal, PolarVirtualMachine};
+        let kb = self.kb();
         {
-            let mut kb = self.kb.write().unwrap();
+            let mut kb = kb.write().unwrap();
             term = rewrite_term(term, &mut kb);
         }
         let query = Goal::Query { term: term.clone() };
-        let vm =
-            PolarVirtualMachine::new(self.kb.clone(), trace, vec![query], self.messages.clone());
+        let vm = PolarVirtualMachine::new(kb, trace, vec![query], self.messages.clone(), config);
         Query::new(vm, term)
     }
 
     // @TODO: Direct load_rules endpoint.
 
     pub fn get_external_id(&self) -> u64 {
-        self.kb.read().unwrap().new_id()
+        self.kb().read().unwrap().new_id()
     }
 
     pub fn register_constant(&self, name: Symbol, value: Term) -> PolarResult<()> {
-        self.kb.write().unwrap().register_constant(name, value)
+        self.kb().write().unwrap().register_constant(name, value)
     }
 
     /// Register MRO for `name` with `mro`.
@@ -267,7 +347,7 @@ impl Polar {
     /// - `mro`: Should go from `name`, `name`'s next superclass, `name's furthest away superclass.
     ///          `mro` is a list of class ids.
     pub fn register_mro(&self, name: Symbol, mro: Vec<u64>) -> PolarResult<()> {
-        self.kb.write().unwrap().add_mro(name, mro)
+        self.kb().write().unwrap().add_mro(name, mro)
     }
 
     pub fn next_message(&self) -> Option<Message> {
@@ -281,8 +361,14 @@ impl Polar {
         variable: &str,
         class_tag: &str,
     ) -> PolarResult<FilterPlan> {
-        build_filter_plan(types, partial_results, variable, class_tag)
-            .map_err(|e| e.with_context(&*self.kb.read().unwrap()))
+        build_filter_plan(
+            types,
+            partial_results,
+            variable,
+            class_tag,
+            self.config.explain,
+        )
+        .map_err(|e| e.with_context(&*self.kb().read().unwrap()))
     }
 
     pub fn build_data_filter(
@@ -292,15 +378,42 @@ impl Polar {
         variable: &str,
         class_tag: &str,
     ) -> PolarResult<Filter> {
-        Filter::build(types, partial_results, variable, class_tag)
-            .map_err(|e| e.with_context(&*self.kb.read().unwrap()))
+        Filter::build(
+            types,
+            partial_results,
+            variable,
+            class_tag,
+            self.config.explain,
+        )
+        .map_err(|e| e.with_context(&*self.kb().read().unwrap()))
+    }
+
+    /// Like `build_data_filter`, also explaining how the filter was built.
+    pub fn build_data_filter_explained(
+        &self,
+        types: Types,
+        partial_results: PartialResults,
+        variable: &str,
+        class_tag: &str,
+    ) -> PolarResult<ExplainedFilter> {
+        Filter::build_explained(types, partial_results, variable, class_tag)
+            .map_err(|e| e.with_context(&self.kb().read().unwrap()))
+    }
+
+    /// Specialize the rule `name` for `args`, turning each of `partial_results` from
+    /// querying `name(args)` into a residual rule. See [`crate::specialize`].
+    pub fn residual_rules(
+        &self,
+        name: &str,
+        args: &[Term],
+        partial_results: PartialResults,
+    ) -> PolarResult<Vec<Rule>> {
+        specialize::residual_rules(name, args, partial_results)
+            .map_err(|e| e.with_context(&self.kb().read().unwrap()))
     }
 
-    // TODO(@gkaemmer): this is a hack and should not be used for similar cases.
-    // Ideally, we'd have a single "configuration" entrypoint for both the Polar
-    // and Query types.
     pub fn set_ignore_no_allow_warning(&mut self, ignore: bool) {
-        self.ignore_no_allow_warning = ignore;
+        self.config.ignore_no_allow_warning = ignore;
     }
 }
 
@@ -367,7 +480,7 @@ mod tests {
         };
         assert_eq!(msg, "File file has already been loaded.");
 
-        assert!(!polar.kb.read().unwrap().has_rules());
+        assert!(!polar.kb().read().unwrap().has_rules());
     }
 
     #[test]
@@ -396,6 +509,36 @@ mod tests {
             "{}",
             next
         );
-        assert!(!polar.kb.read().unwrap().has_rules());
+        assert!(!polar.kb().read().unwrap().has_rules());
+    }
+
+    #[test]
+    fn staged_load_replaces_the_kb_only_when_asked() {
+        let polar = Polar::new();
+        polar.register_constant(sym!("one"), term!(1)).unwrap();
+        polar.load_str("f(1);").unwrap();
+        let old_kb = polar.kb();
+
+        // A broken policy never makes it in.
+        assert!(polar
+            .load_staged(vec![Source::new(None, "g() if h();")])
+            .is_err());
+
+        let staged = polar
+            .load_staged(vec![Source::new(None, "f(2) if one = 1;")])
+            .unwrap();
+        assert!(Arc::ptr_eq(&polar.kb(), &old_kb));
+        polar.replace_kb(staged);
+
+        let kb = polar.kb();
+        assert!(!Arc::ptr_eq(&kb, &old_kb));
+        let kb = kb.read().unwrap();
+        assert!(kb.is_constant(&sym!("one")));
+        assert_eq!(kb.get_rules()[&sym!("f")].rules.len(), 1);
+        // The old KB, which running queries may still hold, is untouched.
+        assert_eq!(
+            old_kb.read().unwrap().get_rules()[&sym!("f")].rules.len(),
+            1
+        );
     }
 }
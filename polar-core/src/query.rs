@@ -1,10 +1,37 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
 use super::error::PolarResult;
 use super::events::*;
 use super::messages::*;
 use super::runnable::Runnable;
 use super::terms::*;
+use super::traces::{ExplainNode, RuleFailure, Trace};
 use super::vm::*;
 
+/// A handle for cancelling a running query, possibly from another thread.
+///
+/// The VM checks the token before each goal, so a cancelled query fails with
+/// `RuntimeError::Cancelled` the next time it is run.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
 pub struct Query {
     runnable_stack: Vec<(Box<dyn Runnable>, u64)>, // Tuple of Runnable + call_id.
     vm: PolarVirtualMachine,
@@ -112,10 +139,32 @@ impl Query {
         self.vm.messages.next()
     }
 
+    /// Explain the trace of the current result, using its bindings.
+    pub fn explain_result(&self, trace: &Trace) -> Option<ExplainNode> {
+        self.vm.explain_trace(trace).into_iter().next()
+    }
+
+    /// The nearest failure of each rule tried by a traced query.
+    pub fn rule_failures(&self) -> Vec<RuleFailure> {
+        RuleFailure::nearest(self.vm.failures())
+    }
+
+    /// A token that cancels this query when `cancel` is called on it.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.vm.cancellation_token()
+    }
+
     pub fn source_info(&self) -> String {
         self.vm.term_source(&self.term, true)
     }
 
+    /// Represent recursive relations in the partial results of this query as
+    /// closures, e.g., `y in x.parent+`, which only `Polar::build_data_filter`
+    /// understands. Off by default.
+    pub fn set_fixpoints(&mut self, enabled: bool) {
+        self.vm.fixpoints = enabled;
+    }
+
     pub fn bind(&mut self, name: Symbol, value: Term) -> PolarResult<()> {
         self.vm
             .bind(&name, value)
@@ -29,7 +29,7 @@ pub enum Production {
 }
 
 fn validate_relation_keyword(keyword: &Term) -> Result<()> {
-    if keyword.value().as_symbol().unwrap().0 != "on" {
+    if keyword.value().as_symbol().unwrap().as_str() != "on" {
         let msg = format!(
             "Unexpected relation keyword '{}'. Did you mean 'on'?",
             keyword
@@ -48,7 +48,7 @@ pub enum ParsedDeclaration {
 }
 
 pub fn validate_parsed_declaration((name, term): (Term, Term)) -> Result<ParsedDeclaration> {
-    match (name.value().as_symbol().expect("parsed as symbol").0.as_ref(), term.value()) {
+    match (&*name.value().as_symbol().expect("parsed as symbol").as_str(), term.value()) {
         ("roles", Value::List(_)) => Ok(ParsedDeclaration::Roles(term)),
         ("permissions", Value::List(_)) => Ok(ParsedDeclaration::Permissions(term)),
         ("relations", Value::Dictionary(_)) => Ok(ParsedDeclaration::Relations(term)),
@@ -76,7 +76,7 @@ pub fn validate_parsed_declaration((name, term): (Term, Term)) -> Result<ParsedD
 
 pub fn block_type_from_keyword(keyword: Option<Term>, resource: &Term) -> Result<BlockType> {
     if let Some(keyword) = keyword {
-        match keyword.value().as_symbol().unwrap().0.as_ref() {
+        match &*keyword.value().as_symbol().unwrap().as_str() {
             "actor" => Ok(BlockType::Actor),
             "resource" => Ok(BlockType::Resource),
             other => Err(ValidationError::ResourceBlock {
@@ -463,7 +463,7 @@ fn index_declarations(
             // `"creator" => Relation(User)` so that when we encounter a shorthand rule
             // `"admin" if "creator";` we can easily look up what type of declaration `"creator"`
             // is.
-            let stringified_relation = relation_type.clone_with_value(value!(relation.0.as_str()));
+            let stringified_relation = relation_type.clone_with_value(value!(&*relation.as_str()));
             let declaration = Declaration::Relation(relation_type.clone());
 
             if let Some(existing) =
@@ -482,7 +482,7 @@ fn index_declarations(
 }
 
 fn resource_name_as_var(resource_name: &Term, related: bool) -> Value {
-    let name = &resource_name.value().as_symbol().expect("sym").0;
+    let name = &resource_name.value().as_symbol().expect("sym").as_str();
     let mut lowercased = name.to_lowercase();
 
     // If the resource's name is already lowercase, append "_instance" to distinguish the variable
@@ -577,7 +577,7 @@ fn shorthand_rule_body_to_rule_body(
 
 /// Turn a shorthand rule head into a trio of params that go in the head of the rewritten rule.
 fn shorthand_rule_head_to_params(head: &Term, resource: &Term) -> Vec<Parameter> {
-    let resource_name = &resource.value().as_symbol().expect("sym").0;
+    let resource_name = &resource.value().as_symbol().expect("sym").as_str();
     vec![
         Parameter {
             parameter: head.clone_with_value(value!(sym!("actor"))),
@@ -859,7 +859,8 @@ mod tests {
         // Create explicit scope to allow the RWLock obtained from kb.read() to
         // be dropped explicitly and independently of the function scope.
         {
-            let blocks = &p.kb.read().unwrap().resource_blocks;
+            let kb = p.kb();
+            let blocks = &kb.read().unwrap().resource_blocks;
             let declarations = blocks.declarations.get(&term!(sym!("Repo"))).unwrap();
             assert_eq!(declarations.len(), 6);
             let shorthand_rules = blocks.shorthand_rules.get(&term!(sym!("Repo"))).unwrap();
@@ -919,7 +920,8 @@ mod tests {
         // Create explicit scope to allow the RWLock obtained from kb.read() to
         // be dropped explicitly and independently of the function scope.
         {
-            let blocks = &p.kb.read().unwrap().resource_blocks;
+            let kb = p.kb();
+            let blocks = &kb.read().unwrap().resource_blocks;
             let declarations = blocks.declarations.get(&term!(sym!("Repo"))).unwrap();
             assert_eq!(declarations.len(), 6);
             let shorthand_rules = blocks.shorthand_rules.get(&term!(sym!("Repo"))).unwrap();
@@ -960,7 +962,8 @@ mod tests {
         "#;
         p.load_str(valid_policy).unwrap();
         {
-            let blocks = &p.kb.read().unwrap().resource_blocks;
+            let kb = p.kb();
+            let blocks = &kb.read().unwrap().resource_blocks;
             let declarations = blocks.declarations.get(&term!(sym!("Repo"))).unwrap();
             assert_eq!(declarations.len(), 6);
             let shorthand_rules = blocks.shorthand_rules.get(&term!(sym!("Repo"))).unwrap();
@@ -1728,7 +1731,9 @@ mod tests {
 
         polar.load_str(policy)?;
 
-        let kb = polar.kb.read().unwrap();
+        let kb = polar.kb();
+
+        let kb = kb.read().unwrap();
 
         let has_role_rule_types = kb.get_rule_types(&sym!("has_role")).unwrap();
         // has_role(actor: Actor, role: String, resource: Resource)
@@ -1772,7 +1777,9 @@ mod tests {
 
         polar.load_str(policy)?;
 
-        let kb = polar.kb.read().unwrap();
+        let kb = polar.kb();
+
+        let kb = kb.read().unwrap();
 
         let has_role_rule_types = kb.get_rule_types(&sym!("has_role")).unwrap();
         // has_role(actor: Actor, role: String, resource: Resource)
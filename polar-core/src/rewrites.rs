@@ -27,7 +27,7 @@ impl<'kb> Folder for Renamer<'kb> {
         } else if let Some(w) = self.renames.get(&v) {
             w.clone()
         } else {
-            let w = self.kb.gensym(&v.0);
+            let w = self.kb.gensym(&v.as_str());
             self.renames.insert(v, w.clone());
             w
         }
@@ -37,7 +37,7 @@ impl<'kb> Folder for Renamer<'kb> {
         if let Some(s) = self.renames.get(&r) {
             s.clone()
         } else {
-            let s = self.kb.gensym(&r.0);
+            let s = self.kb.gensym(&r.as_str());
             self.renames.insert(r, s.clone());
             s
         }
@@ -204,7 +204,7 @@ impl<'kb> Folder for Rewriter<'kb> {
     }
 
     fn fold_rest_variable(&mut self, v: Symbol) -> Symbol {
-        if v.0 == "_" {
+        if v.as_str() == "_" {
             self.kb.gensym("_")
         } else {
             v
@@ -212,7 +212,7 @@ impl<'kb> Folder for Rewriter<'kb> {
     }
 
     fn fold_variable(&mut self, v: Symbol) -> Symbol {
-        if v.0 == "_" {
+        if v.as_str() == "_" {
             self.kb.gensym("_")
         } else {
             v
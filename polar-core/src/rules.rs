@@ -289,7 +289,9 @@ mod tests {
             )
             .unwrap();
 
-        let kb = polar.kb.read().unwrap();
+        let kb = polar.kb();
+
+        let kb = kb.read().unwrap();
         let generic_rule = kb.get_generic_rule(&sym!("f")).unwrap();
         let index = &generic_rule.index;
         assert!(index.rules.is_empty());
@@ -0,0 +1,272 @@
+//! Specialize a rule for some of its arguments.
+//!
+//! Querying `allow(actor, action, resource)` with `actor` bound and `action` and
+//! `resource` left unbound gives, for each way the policy allows that actor
+//! something, the constraints on the action and the resource. Each result becomes a
+//! residual rule, e.g.,
+//!
+//! ```polar
+//! allow(_actor, "read", resource: Post{}) if "alice" = resource.owner;
+//! ```
+//!
+//! The residual rules allow the same things as the policy for that actor, but any
+//! attributes of the actor have already been looked up, so they can be evaluated
+//! without calling back into the host for it.
+
+use crate::data_filtering::PartialResults;
+use crate::error::RuntimeError;
+use crate::folder::{fold_term, Folder};
+use crate::kb::Bindings;
+use crate::rules::{Parameter, Rule};
+use crate::sources::SourceInfo;
+use crate::terms::{ExternalInstance, Operation, Operator, Symbol, Term, Value};
+use crate::visitor::{walk_term, Visitor};
+
+type Result<T> = core::result::Result<T, RuntimeError>;
+
+/// The residual rules for `name(args)`, one for each of `partial_results`.
+///
+/// The parameters of each rule are `args`, with bindings from the result substituted
+/// for variables, and its body is the constraints on them. Host instances in `args`
+/// become anonymous parameters; it's an error for one to occur anywhere else, since
+/// the rule couldn't be evaluated without the host.
+pub fn residual_rules(
+    name: &str,
+    args: &[Term],
+    partial_results: PartialResults,
+) -> Result<Vec<Rule>> {
+    let mut rules = vec![];
+    for result in partial_results {
+        let rule = residual_rule(&Symbol::new(name), args, &result.bindings)?;
+        if !rules.contains(&rule) {
+            rules.push(rule);
+        }
+    }
+    Ok(rules)
+}
+
+fn residual_rule(name: &Symbol, args: &[Term], bindings: &Bindings) -> Result<Rule> {
+    let mut params = vec![];
+    let mut body: Vec<Term> = vec![];
+    for arg in args {
+        let var = match arg.value() {
+            Value::Variable(var) => var,
+            _ if contains_instance(arg).is_some() => {
+                params.push(anonymous_param(&Symbol::new("actor")));
+                continue;
+            }
+            _ => {
+                params.push(Parameter {
+                    parameter: arg.clone(),
+                    specializer: None,
+                });
+                continue;
+            }
+        };
+        match bindings.get(var).map(Term::value) {
+            Some(Value::Expression(constraints)) => {
+                let constraints = fold_term(
+                    term!(constraints.clone()),
+                    &mut ThisSubber { var: var.clone() },
+                );
+                let mut specializer = None;
+                for constraint in constraints.value().as_expression()?.constraints() {
+                    match (constraint.operator, &constraint.args[..]) {
+                        (Operator::Isa, [left, right])
+                            if specializer.is_none()
+                                && left.value().as_symbol().ok() == Some(var)
+                                && matches!(right.value(), Value::Pattern(_)) =>
+                        {
+                            specializer = Some(right.clone())
+                        }
+                        _ => {
+                            let constraint = term!(constraint);
+                            if !body.contains(&constraint) {
+                                body.push(constraint);
+                            }
+                        }
+                    }
+                }
+                params.push(Parameter {
+                    parameter: arg.clone(),
+                    specializer,
+                });
+            }
+            Some(Value::Variable(_)) | None => params.push(Parameter {
+                parameter: arg.clone(),
+                specializer: None,
+            }),
+            Some(_) => params.push(Parameter {
+                parameter: bindings[var].clone(),
+                specializer: None,
+            }),
+        }
+    }
+
+    // Variables that aren't constrained by the body don't need names.
+    let mut referenced = std::collections::HashSet::new();
+    for constraint in body.iter() {
+        constraint.variables(&mut referenced);
+    }
+    for param in params.iter_mut() {
+        if let Value::Variable(var) = param.parameter.value() {
+            if param.specializer.is_none() && !referenced.contains(var) {
+                *param = anonymous_param(var);
+            }
+        }
+    }
+
+    let rule = Rule {
+        name: name.clone(),
+        params,
+        body: term!(Operation {
+            operator: Operator::And,
+            args: body,
+        }),
+        source_info: SourceInfo::ffi(),
+        required: false,
+    };
+    for term in rule
+        .params
+        .iter()
+        .map(|param| &param.parameter)
+        .chain(rule.body.value().as_expression()?.args.iter())
+    {
+        if let Some(instance) = contains_instance(term) {
+            return RuntimeError::unsupported(
+                format!(
+                    "cannot specialize {}: the residual rule would refer to the host instance {}",
+                    name, instance.instance_id
+                ),
+                term.clone(),
+            );
+        }
+    }
+    Ok(rule)
+}
+
+/// A parameter matching anything, named after `var` so it doesn't clash with others.
+fn anonymous_param(var: &Symbol) -> Parameter {
+    let var = match var.is_temporary_var() {
+        true => var.clone(),
+        false => Symbol::new(&format!("_{}", var)),
+    };
+    Parameter {
+        parameter: term!(var),
+        specializer: None,
+    }
+}
+
+/// Replace `_this` in a partial's constraints with the variable it's bound to.
+struct ThisSubber {
+    var: Symbol,
+}
+
+impl Folder for ThisSubber {
+    fn fold_variable(&mut self, v: Symbol) -> Symbol {
+        if v.is_this_var() {
+            self.var.clone()
+        } else {
+            v
+        }
+    }
+}
+
+/// The first host instance in `term`, if any.
+fn contains_instance(term: &Term) -> Option<ExternalInstance> {
+    struct InstanceFinder(Option<ExternalInstance>);
+
+    impl Visitor for InstanceFinder {
+        fn visit_external_instance(&mut self, e: &ExternalInstance) {
+            if self.0.is_none() {
+                self.0 = Some(e.clone());
+            }
+        }
+    }
+
+    let mut finder = InstanceFinder(None);
+    walk_term(&mut finder, term);
+    finder.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::events::{QueryEvent, ResultEvent};
+    use crate::formatting::ToPolarString;
+    use crate::parser::parse_query;
+    use crate::polar::Polar;
+
+    /// Specialize `policy` for the arguments of the call `query`.
+    fn specialize(policy: &str, query: &str) -> Vec<String> {
+        let polar = Polar::new();
+        polar.load_str(policy).unwrap();
+        let query = parse_query(0, query).unwrap();
+        let args = query.value().as_call().unwrap().args.clone();
+        let mut query = polar.new_query_from_term(query, false);
+        let mut results = vec![];
+        loop {
+            match query.next_event().unwrap() {
+                QueryEvent::Result { bindings, .. } => results.push(ResultEvent::new(bindings)),
+                QueryEvent::Done { .. } => break,
+                event => panic!("unexpected event {:?}", event),
+            }
+        }
+        residual_rules("allow", &args, results)
+            .unwrap()
+            .iter()
+            .map(Rule::to_polar)
+            .collect()
+    }
+
+    #[test]
+    fn test_residual_rules() {
+        let policy = r#"
+            allow(actor, "read", resource) if resource.owner = actor.name;
+            allow(actor, action, resource) if
+                actor.admin = true and action in ["read", "write"] and resource.id > 0;
+            allow(_, "write", resource: Post) if resource.id > 1 and resource.id > 2;
+        "#;
+        assert_eq!(
+            specialize(
+                policy,
+                r#"allow({name: "alice", admin: false}, action, resource)"#
+            ),
+            vec![
+                r#"allow({admin: false, name: "alice"}, "write", resource: Post{}) if resource.id > 2;"#,
+                r#"allow({admin: false, name: "alice"}, "read", resource) if "alice" = resource.owner;"#,
+            ]
+        );
+        assert_eq!(
+            specialize(
+                policy,
+                r#"allow({name: "admin", admin: true}, action, resource)"#
+            ),
+            vec![
+                r#"allow({admin: true, name: "admin"}, "write", resource: Post{}) if resource.id > 2;"#,
+                r#"allow({admin: true, name: "admin"}, "read", resource) if "admin" = resource.owner;"#,
+                r#"allow({admin: true, name: "admin"}, "read", resource) if resource.id > 0;"#,
+                r#"allow({admin: true, name: "admin"}, "write", resource) if resource.id > 0;"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_residual_rules_evaluate_like_the_policy() {
+        let policy = r#"allow(actor, "read", resource) if resource.owner = actor.name;"#;
+        let residual = specialize(policy, r#"allow({name: "alice"}, action, resource)"#);
+
+        let polar = Polar::new();
+        polar.load_str(&residual.join("\n")).unwrap();
+        for (owner, allowed) in [("alice", true), ("bob", false)] {
+            let query = format!(
+                r#"allow({{name: "alice"}}, "read", {{owner: "{}"}})"#,
+                owner
+            );
+            let mut query = polar.new_query(&query, false).unwrap();
+            let result = matches!(query.next_event().unwrap(), QueryEvent::Result { .. });
+            assert_eq!(result, allowed);
+        }
+    }
+}
@@ -0,0 +1,795 @@
+//! Compiles a [`Filter`] into a parameterized SQL `SELECT` statement.
+//!
+//! The filter only names types, fields and relations, so the compiler needs an
+//! [`SqlSchema`] saying which table stores each type, which column stores each
+//! field, and which fields each relation joins on. Values from the policy are
+//! never interpolated into the SQL: they're returned as parameters in the order
+//! their placeholders appear.
+//!
+//! A negated sub-filter compiles to an uncorrelated subquery, e.g.,
+//! `"Post"."id" NOT IN (SELECT "Post"."id" FROM ...)`, and a fixpoint to a
+//! subquery with a recursive common table expression.
+
+use std::collections::HashMap;
+
+use crate::{
+    data_filtering::{unregistered_field_error, Type, Types},
+    error::RuntimeError,
+    filter::{join_field, Comparison, Condition, Datum, Filter, Projection, Relation},
+    formatting::ToPolarString,
+    terms::*,
+};
+
+type SqlResult<A> = core::result::Result<A, RuntimeError>;
+
+type TypeName = String;
+type FieldName = String;
+
+/// The SQL dialect to generate: determines identifier quoting and placeholders.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dialect {
+    /// `"identifiers"` and `?` placeholders.
+    Sqlite,
+    /// `"identifiers"` and `$1, $2, ...` placeholders.
+    Postgres,
+    /// `` `identifiers` `` and `?` placeholders.
+    Mysql,
+}
+
+impl Dialect {
+    fn quote(&self, ident: &str) -> String {
+        match self {
+            Self::Sqlite | Self::Postgres => format!("\"{}\"", ident.replace('"', "\"\"")),
+            Self::Mysql => format!("`{}`", ident.replace('`', "``")),
+        }
+    }
+
+    /// The placeholder for the `n`th (1-based) parameter.
+    fn placeholder(&self, n: usize) -> String {
+        match self {
+            Self::Sqlite | Self::Mysql => "?".to_string(),
+            Self::Postgres => format!("${}", n),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct Table {
+    name: Option<String>,
+    primary_key: Option<String>,
+    columns: HashMap<FieldName, String>,
+}
+
+/// Fields on either side of a relation that must be equal for records to be related.
+#[derive(Clone, Debug)]
+struct Join {
+    my_field: FieldName,
+    other_field: FieldName,
+}
+
+/// How the types, fields and relations in a [`Filter`] map onto tables and columns.
+///
+/// Unmapped types are stored in a table named after the type, unmapped fields in
+/// a column named after the field, and records are identified by an `id` column
+/// unless another primary key is given. Relations must be mapped.
+#[derive(Clone, Debug, Default)]
+pub struct SqlSchema {
+    tables: HashMap<TypeName, Table>,
+    joins: HashMap<(TypeName, FieldName), Join>,
+}
+
+impl SqlSchema {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A schema with the relations registered for data filtering, where
+    /// `my_field` and `other_field` name the fields to join on.
+    pub fn from_types(types: &Types) -> Self {
+        let mut schema = Self::new();
+        for (typ, fields) in types {
+            for (field, typ_def) in fields {
+                match typ_def {
+                    Type::Relation {
+                        my_field,
+                        other_field,
+                        ..
+                    } => schema = schema.relation(typ, field, my_field, other_field),
+                    Type::JoinRelation {
+                        join_class_tag,
+                        my_field,
+                        join_my_field,
+                        join_other_field,
+                        other_field,
+                        ..
+                    } => {
+                        schema = schema
+                            .relation(typ, field, my_field, join_my_field)
+                            .relation(
+                                join_class_tag,
+                                &join_field(typ, field),
+                                join_other_field,
+                                other_field,
+                            )
+                    }
+                    Type::Base { .. } => (),
+                }
+            }
+        }
+        schema
+    }
+
+    fn table_mut(&mut self, typ: &str) -> &mut Table {
+        self.tables.entry(typ.to_string()).or_default()
+    }
+
+    /// Store records of type `typ` in `table`.
+    pub fn table(mut self, typ: &str, table: &str) -> Self {
+        self.table_mut(typ).name = Some(table.to_string());
+        self
+    }
+
+    /// Identify records of type `typ` by the value of `field`.
+    pub fn primary_key(mut self, typ: &str, field: &str) -> Self {
+        self.table_mut(typ).primary_key = Some(field.to_string());
+        self
+    }
+
+    /// Store `field` of records of type `typ` in `column`.
+    pub fn column(mut self, typ: &str, field: &str, column: &str) -> Self {
+        self.table_mut(typ)
+            .columns
+            .insert(field.to_string(), column.to_string());
+        self
+    }
+
+    /// Relate records of type `typ` through `field` to the records of the other
+    /// type whose `other_field` equals their `my_field`. A many-to-many relation
+    /// needs both steps: from `typ` to the join type, and from the join type
+    /// through [`join_field`]`(typ, field)` to the other type.
+    pub fn relation(mut self, typ: &str, field: &str, my_field: &str, other_field: &str) -> Self {
+        self.joins.insert(
+            (typ.to_string(), field.to_string()),
+            Join {
+                my_field: my_field.to_string(),
+                other_field: other_field.to_string(),
+            },
+        );
+        self
+    }
+
+    fn table_name<'a>(&'a self, typ: &'a str) -> &'a str {
+        self.tables
+            .get(typ)
+            .and_then(|table| table.name.as_deref())
+            .unwrap_or(typ)
+    }
+
+    fn column_name<'a>(&'a self, typ: &str, field: &'a str) -> &'a str {
+        self.tables
+            .get(typ)
+            .and_then(|table| table.columns.get(field))
+            .map_or(field, |column| column.as_str())
+    }
+
+    fn primary_key_field(&self, typ: &str) -> &str {
+        self.tables
+            .get(typ)
+            .and_then(|table| table.primary_key.as_deref())
+            .unwrap_or("id")
+    }
+}
+
+/// A compiled filter: SQL with placeholders, and the values to bind to them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SqlQuery {
+    pub sql: String,
+    pub params: Vec<Value>,
+}
+
+struct Compiler<'a> {
+    schema: &'a SqlSchema,
+    dialect: Dialect,
+    params: Vec<Value>,
+}
+
+impl<'a> Compiler<'a> {
+    fn column(&self, typ: &str, field: &str) -> String {
+        format!(
+            "{}.{}",
+            self.dialect.quote(typ),
+            self.dialect.quote(self.schema.column_name(typ, field))
+        )
+    }
+
+    fn param(&mut self, value: &Value) -> SqlResult<String> {
+        match value {
+            Value::Number(_) | Value::String(_) | Value::Boolean(_) => {
+                self.params.push(value.clone());
+                Ok(self.dialect.placeholder(self.params.len()))
+            }
+            _ => Err(RuntimeError::Unsupported {
+                msg: format!("cannot use {} as an SQL parameter", value.to_polar()),
+                term: Term::from(value.clone()),
+            }),
+        }
+    }
+
+    fn datum(&mut self, datum: &Datum) -> SqlResult<String> {
+        match datum {
+            Datum::Immediate(value) => self.param(value),
+            Datum::Field(Projection(typ, Some(field))) => Ok(self.column(typ, field)),
+            Datum::Field(Projection(typ, None)) => {
+                Ok(self.column(typ, self.schema.primary_key_field(typ)))
+            }
+            Datum::Subquery(filter) => {
+                let key = self.column(&filter.root, self.schema.primary_key_field(&filter.root));
+                Ok(format!("({})", self.select(filter, &key)?))
+            }
+            Datum::Fixpoint(relation, filter) => {
+                Ok(format!("({})", self.fixpoint(relation, filter)?))
+            }
+        }
+    }
+
+    /// The keys of the records that reach a record passing `filter` through
+    /// `relation`: starting with the records passing `filter`, collect the values of
+    /// the joined column on the other side, and the records whose column on this
+    /// side is among them.
+    fn fixpoint(
+        &mut self,
+        Relation(typ, field, _): &Relation,
+        filter: &Filter,
+    ) -> SqlResult<String> {
+        let join = match self.schema.joins.get(&(typ.clone(), field.clone())) {
+            Some(join) => join,
+            None => return unregistered_field_error(typ, field),
+        };
+        let (mine, other) = (
+            self.column(typ, &join.my_field),
+            self.column(typ, &join.other_field),
+        );
+        let table = format!(
+            "{} AS {}",
+            self.dialect.quote(self.schema.table_name(typ)),
+            self.dialect.quote(typ)
+        );
+        let base = self.select(filter, &other)?;
+        let (cte, key) = (self.dialect.quote("closure"), self.dialect.quote("key"));
+        let id = self.column(typ, self.schema.primary_key_field(typ));
+        Ok(format!(
+            "WITH RECURSIVE {0}({1}) AS ({2} UNION SELECT {3} FROM {4} JOIN {0} ON {5} = {0}.{1}) \
+             SELECT {6} FROM {4} WHERE {5} IN (SELECT {1} FROM {0})",
+            cte, key, base, other, table, mine, id
+        ))
+    }
+
+    fn condition(&mut self, condition: &Condition) -> SqlResult<String> {
+        let Condition(left, op, right) = condition;
+        let op = match op {
+            Comparison::Eq => "=",
+            Comparison::Neq => "<>",
+            Comparison::Lt => "<",
+            Comparison::Leq => "<=",
+            Comparison::Gt => ">",
+            Comparison::Geq => ">=",
+            Comparison::In | Comparison::Nin => return self.membership(condition),
+        };
+        Ok(format!(
+            "{} {} {}",
+            self.datum(left)?,
+            op,
+            self.datum(right)?
+        ))
+    }
+
+    fn membership(&mut self, condition: &Condition) -> SqlResult<String> {
+        let Condition(left, op, right) = condition;
+        let negated = *op == Comparison::Nin;
+        match right {
+            Datum::Immediate(Value::List(list)) => {
+                if list.is_empty() {
+                    return Ok(if negated { "1 = 1" } else { "1 = 0" }.to_string());
+                }
+                let left = self.datum(left)?;
+                let items = list
+                    .iter()
+                    .map(|item| self.param(item.value()))
+                    .collect::<SqlResult<Vec<_>>>()?;
+                let op = if negated { "NOT IN" } else { "IN" };
+                Ok(format!("{} {} ({})", left, op, items.join(", ")))
+            }
+            Datum::Subquery(_) | Datum::Fixpoint(..) => {
+                let op = if negated { "NOT IN" } else { "IN" };
+                Ok(format!(
+                    "{} {} {}",
+                    self.datum(left)?,
+                    op,
+                    self.datum(right)?
+                ))
+            }
+            // Membership in an array-valued column.
+            Datum::Field(Projection(_, Some(_))) if self.dialect == Dialect::Postgres => {
+                let any = format!("{} = ANY({})", self.datum(left)?, self.datum(right)?);
+                Ok(if negated {
+                    format!("NOT ({})", any)
+                } else {
+                    any
+                })
+            }
+            _ => Err(RuntimeError::Unsupported {
+                msg: format!(
+                    "cannot compile `{}` to SQL for {:?}",
+                    condition, self.dialect
+                ),
+                term: Term::from(Value::String(condition.to_string())),
+            }),
+        }
+    }
+
+    /// The `JOIN` clauses for the filter's relations, each after the one
+    /// introducing its source.
+    fn joins(&self, filter: &Filter) -> SqlResult<Vec<String>> {
+        let mut joins = vec![];
+        for Relation(src, field, dst) in filter.ordered_relations()? {
+            let join = match self.schema.joins.get(&(src.clone(), field.clone())) {
+                Some(join) => join,
+                None => return unregistered_field_error(src, field),
+            };
+            joins.push(format!(
+                "LEFT JOIN {} AS {} ON {} = {}",
+                self.dialect.quote(self.schema.table_name(dst)),
+                self.dialect.quote(dst),
+                self.column(src, &join.my_field),
+                self.column(dst, &join.other_field)
+            ));
+        }
+        Ok(joins)
+    }
+
+    /// `SELECT columns FROM ... WHERE ...` for the filter, adding its parameters.
+    fn select(&mut self, filter: &Filter, columns: &str) -> SqlResult<String> {
+        let root = &filter.root;
+        let joins = self.joins(filter)?;
+
+        // Sort conditions so the same filter always compiles to the same SQL.
+        let mut disjuncts = vec![];
+        for conjuncts in &filter.conditions {
+            let mut conjuncts: Vec<&Condition> = conjuncts.iter().collect();
+            conjuncts.sort_by_key(|condition| condition.to_string());
+            let conjuncts = conjuncts
+                .into_iter()
+                .map(|condition| self.condition(condition))
+                .collect::<SqlResult<Vec<_>>>()?;
+            disjuncts.push(if conjuncts.is_empty() {
+                "1 = 1".to_string()
+            } else {
+                format!("({})", conjuncts.join(" AND "))
+            });
+        }
+        let condition = if disjuncts.is_empty() {
+            "1 = 0".to_string()
+        } else {
+            disjuncts.join(" OR ")
+        };
+
+        let mut sql = format!(
+            "SELECT {} FROM {} AS {}",
+            columns,
+            self.dialect.quote(self.schema.table_name(root)),
+            self.dialect.quote(root)
+        );
+        for join in joins {
+            sql.push(' ');
+            sql.push_str(&join);
+        }
+        sql.push_str(" WHERE ");
+        sql.push_str(&condition);
+        Ok(sql)
+    }
+
+    fn compile(mut self, filter: &Filter) -> SqlResult<SqlQuery> {
+        // A record may be joined to many others; only return it once.
+        let distinct = if filter.relations.is_empty() {
+            ""
+        } else {
+            "DISTINCT "
+        };
+        let columns = format!("{}{}.*", distinct, self.dialect.quote(&filter.root));
+        let sql = self.select(filter, &columns)?;
+        Ok(SqlQuery {
+            sql,
+            params: self.params,
+        })
+    }
+}
+
+impl Filter {
+    /// Compile the filter into a parameterized SQL query over `schema`.
+    pub fn to_sql(&self, schema: &SqlSchema, dialect: Dialect) -> SqlResult<SqlQuery> {
+        Compiler {
+            schema,
+            dialect,
+            params: vec![],
+        }
+        .compile(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::filter::singleton;
+    use std::collections::HashSet;
+
+    use rusqlite::{params_from_iter, types::Value as SqlValue, Connection};
+
+    fn s(s: &str) -> String {
+        s.to_string()
+    }
+
+    fn field(typ: &str, field: &str) -> Datum {
+        Datum::Field(Projection(s(typ), Some(s(field))))
+    }
+
+    /// Repos that belong to the "osohq" org, or that are public.
+    fn repo_filter() -> Filter {
+        Filter {
+            root: s("Repo"),
+            relations: singleton(Relation(s("Repo"), s("org"), s("Org"))),
+            conditions: vec![
+                singleton(Condition(
+                    field("Org", "name"),
+                    Comparison::Eq,
+                    Datum::Immediate(value!("osohq")),
+                )),
+                singleton(Condition(
+                    field("Repo", "is_public"),
+                    Comparison::Eq,
+                    Datum::Immediate(value!(true)),
+                )),
+            ],
+        }
+    }
+
+    /// Repos that aren't in `repo_filter()`.
+    fn anti_join() -> Filter {
+        Filter {
+            root: s("Repo"),
+            relations: HashSet::new(),
+            conditions: vec![singleton(Condition(
+                Datum::Field(Projection(s("Repo"), None)),
+                Comparison::Nin,
+                Datum::Subquery(Box::new(repo_filter())),
+            ))],
+        }
+    }
+
+    fn repo_schema() -> SqlSchema {
+        SqlSchema::new()
+            .table("Repo", "repos")
+            .table("Org", "orgs")
+            .column("Repo", "is_public", "public")
+            .relation("Repo", "org", "org_id", "id")
+    }
+
+    #[test]
+    fn test_dialects() {
+        let filter = Filter {
+            root: s("Repo"),
+            relations: HashSet::new(),
+            conditions: vec![hashset! {
+                Condition(field("Repo", "name"), Comparison::Neq, Datum::Immediate(value!("oso"))),
+                Condition(field("Repo", "org_id"), Comparison::In, Datum::Immediate(value!([1, 2]))),
+            }],
+        };
+        let schema = repo_schema();
+
+        let query = filter.to_sql(&schema, Dialect::Sqlite).unwrap();
+        assert_eq!(
+            query.sql,
+            r#"SELECT "Repo".* FROM "repos" AS "Repo" WHERE ("Repo"."name" <> ? AND "Repo"."org_id" IN (?, ?))"#
+        );
+        assert_eq!(query.params, values!["oso", 1, 2]);
+
+        let query = filter.to_sql(&schema, Dialect::Postgres).unwrap();
+        assert_eq!(
+            query.sql,
+            r#"SELECT "Repo".* FROM "repos" AS "Repo" WHERE ("Repo"."name" <> $1 AND "Repo"."org_id" IN ($2, $3))"#
+        );
+
+        let query = filter.to_sql(&schema, Dialect::Mysql).unwrap();
+        assert_eq!(
+            query.sql,
+            "SELECT `Repo`.* FROM `repos` AS `Repo` WHERE (`Repo`.`name` <> ? AND `Repo`.`org_id` IN (?, ?))"
+        );
+    }
+
+    #[test]
+    fn test_joins() {
+        let query = repo_filter()
+            .to_sql(&repo_schema(), Dialect::Postgres)
+            .unwrap();
+        assert_eq!(
+            query.sql,
+            r#"SELECT DISTINCT "Repo".* FROM "repos" AS "Repo" LEFT JOIN "orgs" AS "Org" ON "Repo"."org_id" = "Org"."id" WHERE ("Org"."name" = $1) OR ("Repo"."public" = $2)"#
+        );
+        assert_eq!(query.params, values!["osohq", true]);
+
+        match repo_filter().to_sql(&SqlSchema::new(), Dialect::Sqlite) {
+            Err(RuntimeError::DataFilteringFieldMissing { var_type, field })
+                if var_type == "Repo" && field == "org" => {}
+            result => panic!("unexpected: {:?}", result),
+        }
+
+        let mut filter = anti_join();
+        filter.conditions[0].insert(Condition(
+            field("Repo", "name"),
+            Comparison::Neq,
+            Datum::Immediate(value!("oso")),
+        ));
+        let query = filter.to_sql(&repo_schema(), Dialect::Postgres).unwrap();
+        assert_eq!(
+            query.sql,
+            r#"SELECT "Repo".* FROM "repos" AS "Repo" WHERE ("Repo"."id" NOT IN (SELECT "Repo"."id" FROM "repos" AS "Repo" LEFT JOIN "orgs" AS "Org" ON "Repo"."org_id" = "Org"."id" WHERE ("Org"."name" = $1) OR ("Repo"."public" = $2)) AND "Repo"."name" <> $3)"#
+        );
+        assert_eq!(query.params, values!["osohq", true, "oso"]);
+    }
+
+    #[test]
+    fn test_unsupported() {
+        let filter = Filter {
+            root: s("Repo"),
+            relations: HashSet::new(),
+            conditions: vec![singleton(Condition(
+                Datum::Immediate(value!("admin")),
+                Comparison::In,
+                field("Repo", "tags"),
+            ))],
+        };
+        let query = filter.to_sql(&SqlSchema::new(), Dialect::Postgres).unwrap();
+        assert_eq!(
+            query.sql,
+            r#"SELECT "Repo".* FROM "Repo" AS "Repo" WHERE ($1 = ANY("Repo"."tags"))"#
+        );
+        assert!(matches!(
+            filter.to_sql(&SqlSchema::new(), Dialect::Sqlite),
+            Err(RuntimeError::Unsupported { .. })
+        ));
+
+        let filter = Filter {
+            conditions: vec![singleton(Condition(
+                Datum::Immediate(value!("admin")),
+                Comparison::Nin,
+                field("Repo", "tags"),
+            ))],
+            ..filter
+        };
+        let query = filter.to_sql(&SqlSchema::new(), Dialect::Postgres).unwrap();
+        assert_eq!(
+            query.sql,
+            r#"SELECT "Repo".* FROM "Repo" AS "Repo" WHERE (NOT ($1 = ANY("Repo"."tags")))"#
+        );
+    }
+
+    #[test]
+    fn test_sqlite() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE orgs (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE repos (id INTEGER PRIMARY KEY, name TEXT, org_id INTEGER, public BOOLEAN);
+             INSERT INTO orgs VALUES (1, 'osohq'), (2, 'apple');
+             INSERT INTO repos VALUES
+               (1, 'oso', 1, false), (2, 'demo', 1, true),
+               (3, 'swift', 2, true), (4, 'ios', 2, false);",
+        )
+        .unwrap();
+
+        let repos = |filter: Filter| {
+            let SqlQuery { sql, params } = filter.to_sql(&repo_schema(), Dialect::Sqlite).unwrap();
+            let params = params.into_iter().map(|param| match param {
+                Value::Number(Numeric::Integer(i)) => SqlValue::Integer(i),
+                Value::Number(Numeric::Float(f)) => SqlValue::Real(f),
+                Value::String(s) => SqlValue::Text(s),
+                Value::Boolean(b) => SqlValue::Integer(b.into()),
+                _ => unreachable!(),
+            });
+            let mut stmt = conn
+                .prepare(&format!("{} ORDER BY \"Repo\".\"id\"", sql))
+                .unwrap();
+            let names = stmt
+                .query_map(params_from_iter(params), |row| row.get::<_, String>("name"))
+                .unwrap();
+            names.collect::<Result<Vec<_>, _>>().unwrap()
+        };
+
+        assert_eq!(repos(repo_filter()), vec!["oso", "demo", "swift"]);
+        assert_eq!(
+            repos(Filter {
+                root: s("Repo"),
+                relations: HashSet::new(),
+                conditions: vec![singleton(Condition(
+                    Datum::Field(Projection(s("Repo"), None)),
+                    Comparison::In,
+                    Datum::Immediate(value!([2, 4])),
+                ))],
+            }),
+            vec!["demo", "ios"]
+        );
+        assert!(repos(Filter {
+            root: s("Repo"),
+            relations: HashSet::new(),
+            conditions: vec![],
+        })
+        .is_empty());
+        assert_eq!(
+            repos(Filter {
+                root: s("Repo"),
+                relations: HashSet::new(),
+                conditions: vec![hashset! {
+                    Condition(field("Repo", "id"), Comparison::Geq, Datum::Immediate(value!(2))),
+                    Condition(field("Repo", "name"), Comparison::Nin, Datum::Immediate(value!(["swift"]))),
+                }],
+            }),
+            vec!["demo", "ios"]
+        );
+        assert_eq!(
+            repos(Filter {
+                root: s("Repo"),
+                relations: HashSet::new(),
+                conditions: vec![singleton(Condition(
+                    field("Repo", "org_id"),
+                    Comparison::Lt,
+                    field("Repo", "id"),
+                ))],
+            }),
+            vec!["demo", "swift", "ios"]
+        );
+        assert_eq!(repos(anti_join()), vec!["ios"]);
+    }
+
+    #[test]
+    fn test_fixpoint() {
+        // Folders inside a folder owned by "alice".
+        let filter = Filter {
+            root: s("Folder"),
+            relations: HashSet::new(),
+            conditions: vec![singleton(Condition(
+                Datum::Field(Projection(s("Folder"), None)),
+                Comparison::In,
+                Datum::Fixpoint(
+                    Relation(s("Folder"), s("parent"), s("Folder")),
+                    Box::new(Filter {
+                        root: s("Folder"),
+                        relations: HashSet::new(),
+                        conditions: vec![singleton(Condition(
+                            field("Folder", "owner"),
+                            Comparison::Eq,
+                            Datum::Immediate(value!("alice")),
+                        ))],
+                    }),
+                ),
+            ))],
+        };
+        let schema = SqlSchema::new().table("Folder", "folders").relation(
+            "Folder",
+            "parent",
+            "parent_id",
+            "id",
+        );
+
+        let query = filter.to_sql(&schema, Dialect::Postgres).unwrap();
+        assert_eq!(
+            query.sql,
+            concat!(
+                r#"SELECT "Folder".* FROM "folders" AS "Folder" WHERE ("Folder"."id" IN ("#,
+                r#"WITH RECURSIVE "closure"("key") AS ("#,
+                r#"SELECT "Folder"."id" FROM "folders" AS "Folder" WHERE ("Folder"."owner" = $1) "#,
+                r#"UNION SELECT "Folder"."id" FROM "folders" AS "Folder" "#,
+                r#"JOIN "closure" ON "Folder"."parent_id" = "closure"."key") "#,
+                r#"SELECT "Folder"."id" FROM "folders" AS "Folder" "#,
+                r#"WHERE "Folder"."parent_id" IN (SELECT "key" FROM "closure")))"#,
+            )
+        );
+        assert_eq!(query.params, values!["alice"]);
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE folders (id INTEGER PRIMARY KEY, name TEXT, owner TEXT, parent_id INTEGER);
+             INSERT INTO folders VALUES
+               (1, 'home', 'root', NULL), (2, 'alice', 'alice', 1),
+               (3, 'docs', 'alice', 2), (4, 'drafts', 'bob', 3),
+               (5, 'bob', 'bob', 1), (6, 'shared', 'bob', 5);",
+        )
+        .unwrap();
+        let SqlQuery { sql, params } = filter.to_sql(&schema, Dialect::Sqlite).unwrap();
+        let mut stmt = conn
+            .prepare(&format!("{} ORDER BY \"Folder\".\"id\"", sql))
+            .unwrap();
+        let params = params.into_iter().map(|param| match param {
+            Value::String(s) => SqlValue::Text(s),
+            _ => unreachable!(),
+        });
+        let names = stmt
+            .query_map(params_from_iter(params), |row| row.get::<_, String>("name"))
+            .unwrap();
+        assert_eq!(
+            names.collect::<Result<Vec<_>, _>>().unwrap(),
+            vec!["docs", "drafts"]
+        );
+    }
+
+    #[test]
+    fn test_join_relation() {
+        let types = hashmap! {
+            s("User") => hashmap! {
+                s("groups") => Type::JoinRelation {
+                    other_class_tag: s("Group"),
+                    join_class_tag: s("UserGroup"),
+                    my_field: s("id"),
+                    join_my_field: s("user_id"),
+                    join_other_field: s("group_id"),
+                    other_field: s("id"),
+                },
+            },
+            // A relation of the join type by the same name doesn't get in the way.
+            s("UserGroup") => hashmap! {
+                s("groups") => Type::Relation {
+                    kind: s("many"),
+                    my_field: s("user_id"),
+                    other_field: s("owner_id"),
+                    other_class_tag: s("Group"),
+                },
+            },
+        };
+        let schema = SqlSchema::from_types(&types)
+            .table("User", "users")
+            .table("UserGroup", "user_groups")
+            .table("Group", "groups");
+        // Users in the "admins" group.
+        let filter = Filter {
+            root: s("User"),
+            relations: hashset! {
+                Relation(s("User"), s("groups"), s("UserGroup")),
+                Relation(s("UserGroup"), s("User.groups"), s("Group")),
+            },
+            conditions: vec![singleton(Condition(
+                field("Group", "name"),
+                Comparison::Eq,
+                Datum::Immediate(value!("admins")),
+            ))],
+        };
+
+        let query = filter.to_sql(&schema, Dialect::Sqlite).unwrap();
+        assert_eq!(
+            query.sql,
+            concat!(
+                r#"SELECT DISTINCT "User".* FROM "users" AS "User" "#,
+                r#"LEFT JOIN "user_groups" AS "UserGroup" ON "User"."id" = "UserGroup"."user_id" "#,
+                r#"LEFT JOIN "groups" AS "Group" ON "UserGroup"."group_id" = "Group"."id" "#,
+                r#"WHERE ("Group"."name" = ?)"#,
+            )
+        );
+
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE groups (id INTEGER PRIMARY KEY, name TEXT);
+             CREATE TABLE user_groups (user_id INTEGER, group_id INTEGER);
+             INSERT INTO users VALUES (1, 'alice'), (2, 'bob'), (3, 'carol');
+             INSERT INTO groups VALUES (1, 'admins'), (2, 'staff');
+             INSERT INTO user_groups VALUES (1, 1), (1, 2), (2, 2), (3, 1);",
+        )
+        .unwrap();
+        let mut stmt = conn
+            .prepare(&format!("{} ORDER BY \"User\".\"id\"", query.sql))
+            .unwrap();
+        let names = stmt
+            .query_map(["admins"], |row| row.get::<_, String>("name"))
+            .unwrap();
+        assert_eq!(
+            names.collect::<Result<Vec<_>, _>>().unwrap(),
+            vec!["alice", "carol"]
+        );
+    }
+}
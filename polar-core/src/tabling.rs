@@ -0,0 +1,349 @@
+//! Tabling (memoization) of calls to rules declared `tabled`.
+//!
+//! The first call to a tabled rule with a given variant of arguments (i.e., up to the
+//! renaming of unbound variables) is evaluated in a sub-VM until no new answers are
+//! found. Recursive calls to a variant that is still being evaluated consume the
+//! answers found so far instead of recursing, so left-recursive rules and cyclic data
+//! terminate. Later calls to a complete variant consume its answers directly.
+//!
+//! A variant whose evaluation consumed the answers of an enclosing, still incomplete
+//! variant can't be marked complete on its own: it is re-evaluated the next time it's
+//! called, seeded with the answers it already has.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::counter::Counter;
+use crate::error::RuntimeError;
+use crate::events::QueryEvent;
+use crate::folder::Folder;
+use crate::formatting::ToPolarString;
+use crate::runnable::Runnable;
+use crate::terms::{Symbol, Term, TermList};
+use crate::vm::{Goals, PolarVirtualMachine};
+
+type Result<T> = core::result::Result<T, RuntimeError>;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Status {
+    /// Being evaluated. `recursive` if the variant has been called during its own
+    /// evaluation; `dependent` if its evaluation called an enclosing incomplete variant.
+    Evaluating { recursive: bool, dependent: bool },
+    /// All answers have been found.
+    Complete,
+    /// Some answers have been found, but the variant must be re-evaluated.
+    Incomplete,
+}
+
+#[derive(Debug)]
+struct Table {
+    status: Status,
+    answers: Vec<TermList>,
+}
+
+/// The tables for one query, shared by the VMs evaluating it.
+#[derive(Debug, Default)]
+pub struct Tables {
+    tables: HashMap<String, Table>,
+    /// Variants being evaluated, innermost last.
+    evaluating: Vec<String>,
+}
+
+impl Tables {
+    /// Whether a call to `key` can consume answers from its table rather than
+    /// evaluating the rule. Records the dependencies of a recursive call.
+    pub fn consume(&mut self, key: &str) -> bool {
+        match self.tables.get(key).map(|table| table.status) {
+            Some(Status::Complete) => true,
+            Some(Status::Evaluating { .. }) => {
+                let position = self.evaluating.iter().rposition(|k| k == key).unwrap();
+                for (i, k) in self.evaluating.iter().enumerate().skip(position) {
+                    if let Some(Table {
+                        status:
+                            Status::Evaluating {
+                                recursive,
+                                dependent,
+                            },
+                        ..
+                    }) = self.tables.get_mut(k)
+                    {
+                        if i == position {
+                            *recursive = true;
+                        } else {
+                            *dependent = true;
+                        }
+                    }
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The answers found so far for `key`.
+    pub fn answers(&self, key: &str) -> Vec<TermList> {
+        self.tables
+            .get(key)
+            .map(|table| table.answers.clone())
+            .unwrap_or_default()
+    }
+
+    /// Start evaluating `key`. Returns `false` if it's already complete or being evaluated.
+    fn begin(&mut self, key: &str) -> bool {
+        let table = self.tables.entry(key.to_owned()).or_insert(Table {
+            status: Status::Incomplete,
+            answers: vec![],
+        });
+        if table.status != Status::Incomplete {
+            return false;
+        }
+        table.status = Status::Evaluating {
+            recursive: false,
+            dependent: false,
+        };
+        self.evaluating.push(key.to_owned());
+        true
+    }
+
+    /// Add an answer for `key`, returning `true` if it's new.
+    fn add_answer(&mut self, key: &str, answer: TermList) -> bool {
+        let table = self
+            .tables
+            .get_mut(key)
+            .expect("answer for an unknown table");
+        if table.answers.contains(&answer) {
+            false
+        } else {
+            table.answers.push(answer);
+            true
+        }
+    }
+
+    fn is_recursive(&self, key: &str) -> bool {
+        matches!(
+            self.tables.get(key).map(|table| table.status),
+            Some(Status::Evaluating {
+                recursive: true,
+                ..
+            })
+        )
+    }
+
+    /// Finish evaluating `key`, which must be the innermost variant being evaluated.
+    fn finish(&mut self, key: &str) {
+        assert_eq!(self.evaluating.pop().as_deref(), Some(key));
+        let table = self.tables.get_mut(key).expect("finished an unknown table");
+        table.status = match table.status {
+            Status::Evaluating {
+                dependent: true, ..
+            } => Status::Incomplete,
+            _ => Status::Complete,
+        };
+    }
+}
+
+/// Renames unbound variables to `_0`, `_1`, ... in order of appearance, so that
+/// variants of the same call (or answer) are equal. Aliased variables are first
+/// replaced by their representative.
+struct Canonicalizer<'a> {
+    aliases: &'a Aliases,
+    renames: HashMap<Symbol, Symbol>,
+}
+
+impl<'a> Folder for Canonicalizer<'a> {
+    fn fold_variable(&mut self, v: Symbol) -> Symbol {
+        let v = self.aliases.get(&v).cloned().unwrap_or(v);
+        let next = self.renames.len();
+        self.renames
+            .entry(v)
+            .or_insert_with(|| Symbol::with_suffix("", next as u64))
+            .clone()
+    }
+
+    fn fold_rest_variable(&mut self, v: Symbol) -> Symbol {
+        self.fold_variable(v)
+    }
+}
+
+/// A representative for each unbound variable that is aliased to others.
+pub type Aliases = HashMap<Symbol, Symbol>;
+
+/// Canonicalize a list of (dereferenced) terms.
+pub fn canonicalize(terms: TermList, aliases: &Aliases) -> TermList {
+    let mut canonicalizer = Canonicalizer {
+        aliases,
+        renames: HashMap::new(),
+    };
+    terms
+        .into_iter()
+        .map(|term| canonicalizer.fold_term(term))
+        .collect()
+}
+
+/// The table key for a call to `name` with (dereferenced) `args`.
+pub fn variant_key(name: &Symbol, args: &[Term], aliases: &Aliases) -> String {
+    let args = canonicalize(args.to_vec(), aliases)
+        .iter()
+        .map(|arg| arg.to_polar())
+        .collect::<Vec<_>>();
+    format!("{}({})", name, args.join(", "))
+}
+
+/// Evaluates the rules for one call variant to a fixpoint, storing the answers
+/// in its table.
+#[derive(Clone)]
+pub struct TableEvaluator {
+    key: String,
+    call: Term,
+    args: TermList,
+    tables: Rc<RefCell<Tables>>,
+    /// A VM ready to start a pass over the rules.
+    start: PolarVirtualMachine,
+    vm: PolarVirtualMachine,
+    started: bool,
+    /// Whether this pass has found new answers.
+    changed: bool,
+}
+
+impl TableEvaluator {
+    pub fn new(
+        vm: &PolarVirtualMachine,
+        goals: Goals,
+        key: String,
+        call: Term,
+        args: TermList,
+        tables: Rc<RefCell<Tables>>,
+    ) -> Self {
+        let start = vm.clone_with_goals(goals);
+        Self {
+            key,
+            call,
+            args,
+            tables,
+            vm: start.clone(),
+            start,
+            started: false,
+            changed: false,
+        }
+    }
+
+    fn answer(&self) -> Result<TermList> {
+        let answer: TermList = self.args.iter().map(|arg| self.vm.deref(arg)).collect();
+        match self.vm.aliases(&answer) {
+            Some(aliases) => Ok(canonicalize(answer, &aliases)),
+            None => Err(RuntimeError::Unsupported {
+                msg: format!(
+                    "tabled rule call {} has an answer with unresolved constraints",
+                    self.call.to_polar()
+                ),
+                term: self.call.clone(),
+            }),
+        }
+    }
+}
+
+impl Runnable for TableEvaluator {
+    fn run(&mut self, _: Option<&mut Counter>) -> Result<QueryEvent> {
+        if !self.started {
+            self.started = true;
+            if !self.tables.borrow_mut().begin(&self.key) {
+                return Ok(QueryEvent::Done { result: true });
+            }
+        }
+
+        loop {
+            match self.vm.run(None)? {
+                QueryEvent::Result { .. } => {
+                    let answer = self.answer()?;
+                    if self.tables.borrow_mut().add_answer(&self.key, answer) {
+                        self.changed = true;
+                    }
+                }
+                QueryEvent::Done { .. } => {
+                    let mut tables = self.tables.borrow_mut();
+                    // Recursive calls consumed a partial set of answers; go again
+                    // with the new ones until there aren't any.
+                    if self.changed && tables.is_recursive(&self.key) {
+                        self.changed = false;
+                        self.vm = self.start.clone();
+                        continue;
+                    }
+                    tables.finish(&self.key);
+                    return Ok(QueryEvent::Done { result: true });
+                }
+                event => return Ok(event),
+            }
+        }
+    }
+
+    fn external_question_result(&mut self, call_id: u64, answer: bool) -> Result<()> {
+        self.vm.external_question_result(call_id, answer)
+    }
+
+    fn external_call_result(&mut self, call_id: u64, term: Option<Term>) -> Result<()> {
+        self.vm.external_call_result(call_id, term)
+    }
+
+    fn debug_command(&mut self, command: &str) -> Result<()> {
+        self.vm.debug_command(command)
+    }
+
+    fn clone_runnable(&self) -> Box<dyn Runnable> {
+        Box::new(self.clone())
+    }
+
+    fn handle_error(&mut self, error: RuntimeError) -> Result<QueryEvent> {
+        self.vm.handle_error(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terms::Value;
+
+    #[test]
+    fn variants_share_a_key() {
+        let name = sym!("f");
+        let none = Aliases::new();
+        assert_eq!(
+            variant_key(
+                &name,
+                &[term!(sym!("x")), term!(1), term!(sym!("x"))],
+                &none
+            ),
+            variant_key(
+                &name,
+                &[term!(sym!("y")), term!(1), term!(sym!("y"))],
+                &none
+            )
+        );
+        assert_ne!(
+            variant_key(&name, &[term!(sym!("x")), term!(sym!("x"))], &none),
+            variant_key(&name, &[term!(sym!("x")), term!(sym!("y"))], &none)
+        );
+        let aliased = hashmap! { sym!("y") => sym!("x") };
+        assert_eq!(
+            variant_key(&name, &[term!(sym!("x")), term!(sym!("x"))], &none),
+            variant_key(&name, &[term!(sym!("x")), term!(sym!("y"))], &aliased)
+        );
+    }
+
+    #[test]
+    fn dependent_tables_are_incomplete() {
+        let mut tables = Tables::default();
+        assert!(!tables.consume("f(1)"));
+        assert!(tables.begin("f(1)"));
+        assert!(tables.begin("g(1)"));
+        // g(1) calls f(1) while f(1) is incomplete.
+        assert!(tables.consume("f(1)"));
+        assert!(tables.is_recursive("f(1)"));
+        assert!(!tables.is_recursive("g(1)"));
+        tables.finish("g(1)");
+        tables.finish("f(1)");
+        assert!(tables.consume("f(1)"));
+        assert!(!tables.consume("g(1)"));
+        assert!(tables.begin("g(1)"));
+    }
+}
@@ -1,8 +1,9 @@
+use std::borrow::Cow;
 use std::collections::hash_map::DefaultHasher;
 use std::collections::{BTreeMap, HashSet};
 use std::fmt;
 use std::hash::{Hash, Hasher};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock, RwLock};
 
 use serde::{Deserialize, Serialize};
 
@@ -62,24 +63,168 @@ pub fn has_rest_var(list: &TermList) -> bool {
     !list.is_empty() && matches!(list.last().unwrap().value(), Value::RestVariable(_))
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Ord, PartialOrd, Hash)]
-pub struct Symbol(pub String);
+/// An interned name.
+///
+/// Names are stored once in a global table, so a symbol is a reference-counted pointer
+/// to its name plus, for generated names like `_x_17`, a numeric suffix kept out of the
+/// table so that generating fresh variables doesn't grow it. Symbols are cheap to
+/// clone, compare and hash; they're ordered and serialized as their full names.
+#[derive(Clone)]
+pub struct Symbol {
+    name: Arc<str>,
+    suffix: Option<u64>,
+}
 
 impl Symbol {
     pub fn new(name: &str) -> Self {
-        Self(name.to_string())
+        // Split off a canonical numeric suffix, so that `_x_17` is the same symbol
+        // however it's made.
+        if let Some((prefix, digits)) = name.rsplit_once('_') {
+            let canonical = !digits.is_empty()
+                && digits.bytes().all(|b| b.is_ascii_digit())
+                && (digits == "0" || !digits.starts_with('0'));
+            if let (true, Ok(suffix)) = (canonical, digits.parse()) {
+                return Self::with_suffix(prefix, suffix);
+            }
+        }
+        Self {
+            name: intern(name),
+            suffix: None,
+        }
+    }
+
+    /// The symbol named `{prefix}_{suffix}`.
+    pub fn with_suffix(prefix: &str, suffix: u64) -> Self {
+        Self {
+            name: intern(prefix),
+            suffix: Some(suffix),
+        }
+    }
+
+    /// The full name of the symbol. Only a name with a suffix is formatted.
+    pub fn as_str(&self) -> Cow<'_, str> {
+        match self.suffix {
+            None => Cow::Borrowed(&self.name),
+            Some(suffix) => Cow::Owned(format!("{}_{}", self.name, suffix)),
+        }
     }
 
     pub fn is_temporary_var(&self) -> bool {
-        self.0.starts_with('_')
+        self.name.starts_with('_') || (self.name.is_empty() && self.suffix.is_some())
     }
 
     pub fn is_namespaced_var(&self) -> bool {
-        self.0.contains("::")
+        self.name.contains("::")
     }
 
     pub fn is_this_var(&self) -> bool {
-        self.0 == "_this"
+        self.suffix.is_none() && &*self.name == "_this"
+    }
+}
+
+/// The interned names, and the size at which the table is next swept.
+struct Names {
+    names: HashSet<Arc<str>>,
+    sweep_at: usize,
+}
+
+/// The fewest names the table grows to before it's swept.
+const MIN_SWEEP_AT: usize = 1024;
+
+fn names() -> &'static RwLock<Names> {
+    static TABLE: OnceLock<RwLock<Names>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        RwLock::new(Names {
+            names: HashSet::new(),
+            sweep_at: MIN_SWEEP_AT,
+        })
+    })
+}
+
+/// The interned copy of `name`.
+///
+/// A name is dropped from the table once no symbol refers to it, when the table has
+/// doubled in size since it was last swept. So names from host data, like the keys of
+/// dictionaries, don't accumulate over the life of the process.
+fn intern(name: &str) -> Arc<str> {
+    let table = names();
+    if let Some(interned) = table.read().unwrap().names.get(name) {
+        return interned.clone();
+    }
+    let mut table = table.write().unwrap();
+    if let Some(interned) = table.names.get(name) {
+        return interned.clone();
+    }
+    if table.names.len() >= table.sweep_at {
+        // Symbols are only made through the table, which is locked, so a name only
+        // the table refers to can't be picked up while it's dropped.
+        table.names.retain(|name| Arc::strong_count(name) > 1);
+        table.sweep_at = MIN_SWEEP_AT.max(2 * table.names.len());
+    }
+    let interned: Arc<str> = Arc::from(name);
+    table.names.insert(interned.clone());
+    interned
+}
+
+impl PartialEq for Symbol {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.name, &other.name) && self.suffix == other.suffix
+    }
+}
+
+impl Eq for Symbol {}
+
+impl Hash for Symbol {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Interned names are unique while they're referred to, so the address of one
+        // identifies it. Mixing in the suffix keeps hashing a symbol to a single write.
+        let suffix = self.suffix.unwrap_or(0).rotate_left(32);
+        state.write_u64(self.name.as_ptr() as u64 ^ suffix);
+    }
+}
+
+impl Ord for Symbol {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        if self == other {
+            std::cmp::Ordering::Equal
+        } else if self.suffix.is_none() && other.suffix.is_none() {
+            self.name.cmp(&other.name)
+        } else {
+            self.as_str().cmp(&other.as_str())
+        }
+    }
+}
+
+impl PartialOrd for Symbol {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Symbol").field(&self.as_str()).finish()
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> core::result::Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct("Symbol", &*self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Symbol {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> core::result::Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename = "Symbol")]
+        struct Name(String);
+
+        Name::deserialize(deserializer).map(|Name(name)| Self::new(&name))
     }
 }
 
@@ -117,6 +262,11 @@ pub enum Operator {
     And,
     ForAll,
     Assign,
+    Count,
+    Collect,
+    Min,
+    Max,
+    Sum,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq, Hash)]
@@ -496,11 +646,11 @@ impl Term {
     }
 
     pub fn is_actor_union(&self) -> bool {
-        matches!(self.value(), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) if tag.0 == ACTOR_UNION_NAME)
+        matches!(self.value(), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) if tag.as_str() == ACTOR_UNION_NAME)
     }
 
     pub fn is_resource_union(&self) -> bool {
-        matches!(self.value(), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) if tag.0 == RESOURCE_UNION_NAME)
+        matches!(self.value(), Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) | Value::Variable(tag) if tag.as_str() == RESOURCE_UNION_NAME)
     }
 }
 
@@ -535,4 +685,51 @@ mod tests {
             "b:2"
         );
     }
+
+    #[test]
+    fn test_symbol_interning() {
+        assert_eq!(sym!("x"), Symbol::new(&String::from("x")));
+        assert_ne!(sym!("x"), sym!("y"));
+
+        // Numeric suffixes are split off canonically, however the symbol is made.
+        assert_eq!(sym!("_x_17"), Symbol::with_suffix("_x", 17));
+        assert_eq!(sym!("_17"), Symbol::with_suffix("", 17));
+        assert_ne!(sym!("_x_017"), Symbol::with_suffix("_x", 17));
+        for name in ["_x_17", "_17", "_x_017", "x_", "_x_0", "__x_3_17", "_"] {
+            let symbol = sym!(name);
+            assert_eq!(symbol.to_string(), name);
+            assert_eq!(symbol.as_str(), name);
+            assert_eq!(
+                serde_json::to_string(&symbol).unwrap(),
+                format!("{:?}", name)
+            );
+            let symbol: Symbol = serde_json::from_str(&format!("{:?}", name)).unwrap();
+            assert_eq!(symbol, sym!(name));
+        }
+        assert!(Symbol::with_suffix("", 1).is_temporary_var());
+        assert!(!sym!("x_1").is_temporary_var());
+        assert!(!Symbol::with_suffix("_this", 1).is_this_var());
+
+        // Symbols are ordered by name.
+        let mut symbols = vec![sym!("b"), sym!("_x_10"), sym!("a"), sym!("_x_9")];
+        symbols.sort();
+        assert_eq!(
+            symbols,
+            vec![sym!("_x_10"), sym!("_x_9"), sym!("a"), sym!("b")]
+        );
+    }
+
+    #[test]
+    fn test_unused_names_are_dropped() {
+        let kept = sym!("kept");
+        for i in 0..10 * MIN_SWEEP_AT {
+            sym!(&format!("dropped{}", i));
+        }
+        let table = names().read().unwrap();
+        assert!(table.names.len() < 4 * MIN_SWEEP_AT);
+        assert!(table.names.contains("kept"));
+        assert!(!table.names.contains("dropped0"));
+        drop(table);
+        assert_eq!(kept, sym!("kept"));
+    }
 }
@@ -31,3 +31,74 @@ pub struct TraceResult {
     pub trace: Rc<Trace>,
     pub formatted: String,
 }
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExplainKind {
+    Rule,
+    Goal,
+}
+
+/// A node of an explanation: a rule that was tried or a goal that was queried.
+///
+/// Unlike [`Trace`], conjunctions are flattened into their conjuncts and goals
+/// carry their bound form, i.e. the goal with the bindings in effect when it ran.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExplainNode {
+    pub kind: ExplainKind,
+    pub source: String,
+    pub bound: Option<String>,
+    pub children: Vec<ExplainNode>,
+}
+
+impl ExplainNode {
+    fn size(&self) -> usize {
+        1 + self.children.iter().map(|c| c.size()).sum::<usize>()
+    }
+}
+
+/// Why a rule tried by a query failed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RuleFailure {
+    pub rule: String,
+    /// The goal that failed, or `None` if the rule's head did not match.
+    pub goal: Option<ExplainNode>,
+    /// The attempt to apply the rule, up to the failing goal.
+    pub trace: ExplainNode,
+}
+
+impl RuleFailure {
+    /// For each rule tried by a top-level query, pick the failure that got
+    /// furthest through the rule, given the explained trace of every failure.
+    pub fn nearest(failures: &[ExplainNode]) -> Vec<Self> {
+        let mut nearest: Vec<&ExplainNode> = vec![];
+        let attempts = failures
+            .iter()
+            .filter_map(|root| root.children.last())
+            .filter(|attempt| attempt.kind == ExplainKind::Rule);
+        for attempt in attempts {
+            match nearest.iter_mut().find(|n| n.source == attempt.source) {
+                Some(n) if attempt.size() >= n.size() => *n = attempt,
+                Some(_) => (),
+                None => nearest.push(attempt),
+            }
+        }
+        nearest
+            .into_iter()
+            .map(|attempt| {
+                let mut goal = None;
+                let mut node = attempt;
+                while let Some(last) = node.children.last() {
+                    if last.kind == ExplainKind::Goal {
+                        goal = Some(last.clone());
+                    }
+                    node = last;
+                }
+                Self {
+                    rule: attempt.source.clone(),
+                    goal,
+                    trace: attempt.clone(),
+                }
+            })
+            .collect()
+    }
+}
@@ -150,7 +150,7 @@ struct ResourceBlocksMissingHasPermissionVisitor {
 
 impl Visitor for ResourceBlocksMissingHasPermissionVisitor {
     fn visit_call(&mut self, call: &Call) {
-        if call.name.0 == "has_permission" {
+        if call.name.as_str() == "has_permission" {
             self.calls_has_permission = true;
         }
         walk_call(self, call)
@@ -1,4 +1,4 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::BTreeMap;
 use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
@@ -10,14 +10,17 @@ use std::sync::{Arc, RwLock, RwLockReadGuard};
 use wasm_bindgen::prelude::*;
 
 use super::visitor::{walk_term, Visitor};
+use crate::aggregates::Aggregator;
 use crate::bindings::{
     Binding, BindingManager, BindingStack, Bindings, Bsp, FollowerId, VariableState,
 };
+use crate::config::{LogSink, PolarConfig};
 use crate::counter::Counter;
 use crate::data_filtering::partition_equivs;
 use crate::debugger::{get_binding_for_var, DebugEvent, Debugger};
-use crate::error::{self, RuntimeError};
+use crate::error::{self, Budget, RuntimeError};
 use crate::events::*;
+use crate::filter::closure_field;
 use crate::folder::Folder;
 use crate::formatting::ToPolarString;
 use crate::inverter::Inverter;
@@ -26,10 +29,12 @@ use crate::lexer::loc_to_pos;
 use crate::messages::*;
 use crate::numerics::*;
 use crate::partial::{simplify_bindings_opt, simplify_partial, sub_this, IsaConstraintCheck};
+use crate::query::CancellationToken;
 use crate::rewrites::Renamer;
 use crate::rules::*;
 use crate::runnable::Runnable;
 use crate::sources::*;
+use crate::tabling::{variant_key, Aliases, TableEvaluator, Tables};
 use crate::terms::*;
 use crate::traces::*;
 
@@ -109,6 +114,11 @@ pub enum Goal {
         outer: usize,
         inner: usize,
     },
+    /// Make a choice over the answers in the table for a tabled call.
+    TableAnswers {
+        key: String,
+        args: TermList,
+    },
     TraceRule {
         trace: Rc<Trace>,
     },
@@ -134,6 +144,12 @@ pub enum Goal {
     AddConstraintsBatch {
         add_constraints: Rc<RefCell<Bindings>>,
     },
+
+    /// Unify `result` with the aggregate computed by an `Aggregator`.
+    UnifyAggregate {
+        result: Term,
+        aggregate: Rc<RefCell<Option<Term>>>,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -180,6 +196,214 @@ fn invalid_state<A>(msg: String) -> Result<A> {
     Err(RuntimeError::InvalidState { msg })
 }
 
+/// The variables that `var` is unified with by `constraints`, including itself.
+fn unified_variables(constraints: &Operation, var: &Symbol) -> HashSet<Symbol> {
+    let mut vars = HashSet::new();
+    let mut todo = vec![var.clone()];
+    while let Some(var) = todo.pop() {
+        if !vars.insert(var.clone()) {
+            continue;
+        }
+        for constraint in constraints.constraints() {
+            if let (Operator::Unify, [left, right]) = (constraint.operator, &constraint.args[..]) {
+                match (left.value(), right.value()) {
+                    (Value::Variable(l), Value::Variable(r)) if l == &var => todo.push(r.clone()),
+                    (Value::Variable(l), Value::Variable(r)) if r == &var => todo.push(l.clone()),
+                    _ => (),
+                }
+            }
+        }
+    }
+    vars
+}
+
+/// The conjuncts of a rule body.
+fn conjuncts(body: &Term) -> TermList {
+    match body.value() {
+        Value::Expression(Operation {
+            operator: Operator::And,
+            args,
+        }) => args.iter().flat_map(conjuncts).collect(),
+        _ => vec![body.clone()],
+    }
+}
+
+/// Whether `specializer` only checks the type of a parameter.
+fn is_type_check(specializer: &Option<Term>) -> bool {
+    match specializer.as_ref().map(Term::value) {
+        None => true,
+        Some(Value::Pattern(Pattern::Instance(InstanceLiteral { fields, .. }))) => {
+            fields.fields.is_empty()
+        }
+        _ => false,
+    }
+}
+
+/// Whether every clause of the rule `name` that calls itself does nothing but get
+/// the `i`th argument of the call by following `field` from its `i`th parameter.
+/// Then the records reached by following `field` from the first argument have to
+/// pass the other clauses, and nothing else.
+fn recursion_only_follows(
+    kb: &KnowledgeBase,
+    name: &Symbol,
+    arity: usize,
+    i: usize,
+    field: &str,
+) -> bool {
+    let generic_rule = match kb.get_generic_rule(name) {
+        Some(generic_rule) => generic_rule,
+        None => return false,
+    };
+    generic_rule.rules.values().all(|rule| {
+        if rule.params.len() != arity {
+            return true;
+        }
+        let mut goals = conjuncts(&rule.body);
+        let recursive: Vec<usize> = (0..goals.len())
+            .filter(|&j| {
+                matches!(goals[j].value(), Value::Call(call)
+                    if &call.name == name && call.args.len() == arity)
+            })
+            .collect();
+        let call = match recursive[..] {
+            [] => return true,
+            [j] => goals.remove(j),
+            _ => return false,
+        };
+        let child = match call.value() {
+            Value::Call(call) => call.args[i].value().as_symbol().ok(),
+            _ => None,
+        };
+        let param = &rule.params[i];
+        let parent = match param.parameter.value() {
+            Value::Variable(parent) if is_type_check(&param.specializer) => parent,
+            _ => return false,
+        };
+        let child = match child {
+            Some(child) => child,
+            None => return false,
+        };
+
+        // The other goals may only constrain the arguments the call passes along.
+        let mut vars: HashSet<Symbol> = [parent.clone(), child.clone()].into_iter().collect();
+        let mut connected = vec![];
+        loop {
+            let (more, rest): (TermList, TermList) = goals.into_iter().partition(|goal| {
+                let mut goal_vars = HashSet::new();
+                goal.variables(&mut goal_vars);
+                !goal_vars.is_disjoint(&vars)
+            });
+            goals = rest;
+            if more.is_empty() {
+                break;
+            }
+            for goal in more.iter() {
+                goal.variables(&mut vars);
+            }
+            connected.extend(more);
+        }
+        follows(kb, &connected, parent, child, field, 2)
+    })
+}
+
+/// Whether `goals` do nothing but constrain `child` to be `parent.field`, looking
+/// into the rules they call up to `depth` calls deep, e.g., `has_relation`.
+fn follows(
+    kb: &KnowledgeBase,
+    goals: &[Term],
+    parent: &Symbol,
+    child: &Symbol,
+    field: &str,
+    depth: usize,
+) -> bool {
+    let is_alias = |goal: &Term| {
+        matches!(goal.value().as_expression(), Ok(Operation { operator: Operator::Unify, args })
+            if args.iter().all(|arg| matches!(arg.value(), Value::Variable(_))))
+    };
+    let aliases = Operation {
+        operator: Operator::And,
+        args: goals
+            .iter()
+            .filter(|goal| is_alias(goal))
+            .cloned()
+            .collect(),
+    };
+    let (parents, children) = (
+        unified_variables(&aliases, parent),
+        unified_variables(&aliases, child),
+    );
+    let mut found = false;
+    for goal in goals.iter().filter(|goal| !is_alias(goal)) {
+        found = match goal.value() {
+            Value::Expression(Operation {
+                operator: Operator::Dot,
+                args,
+            }) => matches!(args.iter().map(Term::value).collect::<Vec<_>>()[..],
+                [Value::Variable(object), Value::String(name), Value::Variable(value)]
+                    if parents.contains(object) && name == field && children.contains(value)),
+            Value::Call(call) if depth > 0 => {
+                calls_follow(kb, call, &parents, &children, field, depth - 1)
+            }
+            _ => false,
+        };
+        if !found {
+            return false;
+        }
+    }
+    found
+}
+
+/// Whether the clauses of the rule that `call` applies do nothing but constrain
+/// the argument in `children` to follow `field` from the one in `parents`.
+fn calls_follow(
+    kb: &KnowledgeBase,
+    call: &Call,
+    parents: &HashSet<Symbol>,
+    children: &HashSet<Symbol>,
+    field: &str,
+    depth: usize,
+) -> bool {
+    let generic_rule = match kb.get_generic_rule(&call.name) {
+        Some(generic_rule) if call.kwargs.is_none() => generic_rule,
+        _ => return false,
+    };
+    let mut applies = false;
+    for rule in generic_rule.rules.values() {
+        if rule.params.len() != call.args.len() {
+            continue;
+        }
+        let (mut parent, mut child) = (None, None);
+        let mut matches = true;
+        for (arg, param) in call.args.iter().zip(rule.params.iter()) {
+            if !is_type_check(&param.specializer) {
+                return false;
+            }
+            match (arg.value(), param.parameter.value()) {
+                (Value::Variable(arg), Value::Variable(param)) if parents.contains(arg) => {
+                    parent = Some(param)
+                }
+                (Value::Variable(arg), Value::Variable(param)) if children.contains(arg) => {
+                    child = Some(param)
+                }
+                (Value::Variable(_), _) | (_, Value::Variable(_)) => return false,
+                (arg, param) => matches &= arg == param,
+            }
+        }
+        if !matches {
+            continue;
+        }
+        match (parent, child) {
+            (Some(parent), Some(child))
+                if follows(kb, &conjuncts(&rule.body), parent, child, field, depth) =>
+            {
+                applies = true
+            }
+            _ => return false,
+        }
+    }
+    applies
+}
+
 pub fn compare(op: Operator, left: &Term, right: &Term, context: Option<&Term>) -> Result<bool> {
     use {Operator::*, Value::*};
     // Coerce booleans to integers.
@@ -216,6 +440,57 @@ pub fn compare(op: Operator, left: &Term, right: &Term, context: Option<&Term>)
     }
 }
 
+/// Limits on the work a query may do, and the work done so far.
+///
+/// Shared between a VM and the VMs it spawns for nested queries (e.g., for `not`),
+/// so their work counts against the same budget.
+#[derive(Debug, Default)]
+struct QueryBudget {
+    max_goals: Option<u64>,
+    max_backtracks: Option<u64>,
+    max_external_calls: Option<u64>,
+    goals: Cell<u64>,
+    backtracks: Cell<u64>,
+    external_calls: Cell<u64>,
+}
+
+impl QueryBudget {
+    fn new(config: &PolarConfig) -> Self {
+        Self {
+            max_goals: config.max_goals,
+            max_backtracks: config.max_backtracks,
+            max_external_calls: config.max_external_calls,
+            ..Self::default()
+        }
+    }
+
+    fn spend(spent: &Cell<u64>, limit: Option<u64>, budget: Budget) -> Result<()> {
+        spent.set(spent.get() + 1);
+        match limit {
+            Some(limit) if spent.get() > limit => {
+                Err(RuntimeError::BudgetExhausted { budget, limit })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn spend_goal(&self) -> Result<()> {
+        Self::spend(&self.goals, self.max_goals, Budget::Goals)
+    }
+
+    fn spend_backtrack(&self) -> Result<()> {
+        Self::spend(&self.backtracks, self.max_backtracks, Budget::Backtracks)
+    }
+
+    fn spend_external_call(&self) -> Result<()> {
+        Self::spend(
+            &self.external_calls,
+            self.max_external_calls,
+            Budget::ExternalCalls,
+        )
+    }
+}
+
 #[derive(Clone)]
 pub struct PolarVirtualMachine {
     /// Stacks.
@@ -227,6 +502,7 @@ pub struct PolarVirtualMachine {
     pub tracing: bool,
     pub trace_stack: TraceStack, // Stack of traces higher up the tree.
     pub trace: Vec<Rc<Trace>>,   // Traces for the current level of the trace tree.
+    failures: Vec<ExplainNode>,  // Explained traces of failed goals, when tracing.
 
     // Errors from outside the vm.
     pub external_error: Option<String>,
@@ -240,6 +516,15 @@ pub struct PolarVirtualMachine {
     /// Maximum size of goal stack
     stack_limit: usize,
 
+    /// Deterministic limits on goals, backtracks and external calls.
+    budget: Rc<QueryBudget>,
+
+    /// Set from outside the VM to stop the query.
+    cancellation: CancellationToken,
+
+    /// Answers to calls to tabled rules.
+    tables: Rc<RefCell<Tables>>,
+
     /// Binding stack constant below here.
     csp: Bsp,
 
@@ -261,6 +546,9 @@ pub struct PolarVirtualMachine {
     // Other flags.
     pub query_contains_partial: bool,
     pub inverting: bool,
+    /// Represent recursive relations as closures, `y in x.field+`, which only
+    /// `Filter::build` understands. See `query_for_closure`.
+    pub fixpoints: bool,
 
     /// Output messages.
     pub messages: MessageQueue,
@@ -274,6 +562,7 @@ impl Default for PolarVirtualMachine {
             vec![],
             // Messages will not be exposed, only use default() for testing.
             MessageQueue::new(),
+            &PolarConfig::default(),
         )
     }
 }
@@ -294,48 +583,43 @@ impl PolarVirtualMachine {
         tracing: bool,
         goals: Goals,
         messages: MessageQueue,
+        config: &PolarConfig,
     ) -> Self {
-        let query_timeout_ms = std::env::var("POLAR_TIMEOUT_MS")
-            .ok()
-            .and_then(|timeout_str| timeout_str.parse::<u64>().ok())
-            .unwrap_or(DEFAULT_TIMEOUT_MS);
         let constants = kb
             .read()
             .expect("cannot acquire KB read lock")
             .get_registered_constants()
             .clone();
-        // get all comma-delimited POLAR_LOG variables
-        let polar_log = std::env::var("POLAR_LOG");
-        let polar_log_vars = polar_log
-            .iter()
-            .flat_map(|pl| pl.split(','))
-            .collect::<Vec<&str>>();
         let mut vm = Self {
             goals: GoalStack::new_reversed(goals),
             binding_manager: BindingManager::new(),
             query_start_time: None,
-            query_timeout_ms,
-            stack_limit: MAX_STACK_SIZE,
+            query_timeout_ms: config.query_timeout_ms,
+            stack_limit: config.stack_limit,
+            budget: Rc::new(QueryBudget::new(config)),
+            cancellation: CancellationToken::new(),
+            tables: Rc::new(RefCell::new(Tables::default())),
             csp: Bsp::default(),
             choices: vec![],
             queries: vec![],
             tracing,
             trace_stack: vec![],
             trace: vec![],
+            failures: vec![],
             external_error: None,
             debugger: Debugger::default(),
             kb,
             call_id_symbols: HashMap::new(),
             // `log` controls internal VM logging
-            log: polar_log_vars.iter().any(|var| var == &"trace"),
+            log: config.vm_log,
             // `polar_log` for tracing policy evaluation
-            polar_log: !polar_log_vars.is_empty()
-                && !polar_log_vars.iter().any(|var| ["0", "off"].contains(var)),
+            polar_log: config.polar_log,
             // `polar_log_stderr` prints things immediately to stderr
-            polar_log_stderr: polar_log_vars.iter().any(|var| var == &"now"),
+            polar_log_stderr: config.log_sink == LogSink::Stderr,
             polar_log_mute: false,
             query_contains_partial: false,
             inverting: false,
+            fixpoints: false,
             messages,
         };
         vm.bind_constants(constants);
@@ -387,14 +671,35 @@ impl PolarVirtualMachine {
 
     #[cfg(test)]
     pub fn new_test(kb: Arc<RwLock<KnowledgeBase>>, tracing: bool, goals: Goals) -> Self {
-        PolarVirtualMachine::new(kb, tracing, goals, MessageQueue::new())
+        PolarVirtualMachine::new(
+            kb,
+            tracing,
+            goals,
+            MessageQueue::new(),
+            &PolarConfig::default(),
+        )
     }
 
     /// Clone self, replacing the goal stack and retaining only the current bindings.
     pub fn clone_with_goals(&self, goals: Goals) -> Self {
-        let mut vm = Self::new(self.kb.clone(), self.tracing, goals, self.messages.clone());
+        let mut vm = Self::new(
+            self.kb.clone(),
+            self.tracing,
+            goals,
+            self.messages.clone(),
+            &PolarConfig::default(),
+        );
+        vm.query_timeout_ms = self.query_timeout_ms;
+        vm.stack_limit = self.stack_limit;
+        vm.budget = self.budget.clone();
+        vm.cancellation = self.cancellation.clone();
+        vm.tables = self.tables.clone();
+        vm.log = self.log;
+        vm.polar_log = self.polar_log;
+        vm.polar_log_stderr = self.polar_log_stderr;
         vm.binding_manager.clone_from(&self.binding_manager);
         vm.query_contains_partial = self.query_contains_partial;
+        vm.fixpoints = self.fixpoints;
         vm.debugger = self.debugger.clone();
         vm
     }
@@ -442,10 +747,14 @@ impl PolarVirtualMachine {
             self.print(&format!("{}", goal));
         }
 
+        self.check_cancelled()?;
         self.check_timeout()?;
 
         match goal.as_ref() {
-            Goal::Backtrack => self.backtrack()?,
+            Goal::Backtrack => {
+                self.trace_failure();
+                self.backtrack()?
+            }
             Goal::Cut { choice_index } => self.cut(*choice_index),
             Goal::Debug { message } => return Ok(self.debug(message)),
             Goal::Halt => return Ok(self.halt()),
@@ -522,12 +831,22 @@ impl PolarVirtualMachine {
             Goal::Unify { left, right } => self.unify(left, right)?,
             Goal::AddConstraint { term } => self.add_constraint(term)?,
             Goal::AddConstraintsBatch { add_constraints } => {
-                add_constraints
-                    .borrow_mut()
-                    .drain()
-                    .try_for_each(|(_, constraint)| self.add_constraint(&constraint))?
+                // An inverter gives every variable in a negated conjunction the same
+                // constraint, so add each one only once.
+                let mut added: Vec<Term> = vec![];
+                for (_, constraint) in add_constraints.borrow_mut().drain() {
+                    if !added.contains(&constraint) {
+                        self.add_constraint(&constraint)?;
+                        added.push(constraint);
+                    }
+                }
             }
+            Goal::UnifyAggregate { result, aggregate } => match aggregate.borrow_mut().take() {
+                Some(aggregate) => self.unify(result, &aggregate)?,
+                None => self.push_goal(Goal::Backtrack)?,
+            },
             Goal::Run { runnable } => return self.run_runnable(runnable.clone_runnable()),
+            Goal::TableAnswers { key, args } => self.table_answers(key, args)?,
         }
         Ok(QueryEvent::None)
     }
@@ -601,6 +920,7 @@ impl PolarVirtualMachine {
             self.push_choice(alternatives_iter)?;
             self.append_goals(alternative)
         } else {
+            self.trace_failure();
             self.backtrack()
         }
     }
@@ -718,13 +1038,18 @@ impl PolarVirtualMachine {
         self.binding_manager.variable_state_at_point(variable, bsp)
     }
 
+    /// The variables that `variable` was unified with at `bsp`, including itself.
+    pub fn unified_at_point(&self, variable: &Symbol, bsp: &Bsp) -> HashSet<Symbol> {
+        self.binding_manager.unified_at_point(variable, bsp)
+    }
+
     /// Investigate the current state of a variable and return a variable state variant.
     pub fn variable_state(&self, variable: &Symbol) -> VariableState {
         self.binding_manager.variable_state(variable)
     }
 
     /// Recursively dereference variables in a term, including subterms, except operations.
-    fn deref(&self, term: &Term) -> Term {
+    pub(crate) fn deref(&self, term: &Term) -> Term {
         self.binding_manager.deep_deref(term)
     }
 
@@ -781,7 +1106,7 @@ impl PolarVirtualMachine {
                         ", BINDINGS: {{{}}}",
                         relevant_bindings
                             .iter()
-                            .map(|(var, val)| format!("{} => {}", var.0, val.to_polar()))
+                            .map(|(var, val)| format!("{} => {}", var, val.to_polar()))
                             .collect::<Vec<String>>()
                             .join(", ")
                     ));
@@ -870,6 +1195,17 @@ impl PolarVirtualMachine {
         self.query_timeout_ms == 0
     }
 
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    fn check_cancelled(&self) -> Result<()> {
+        if self.cancellation.is_cancelled() {
+            return Err(RuntimeError::Cancelled);
+        }
+        Ok(())
+    }
+
     fn check_timeout(&self) -> Result<()> {
         if self.is_query_timeout_disabled() {
             // Useful for debugging
@@ -880,7 +1216,7 @@ impl PolarVirtualMachine {
         if elapsed > self.query_timeout_ms {
             return Err(error::RuntimeError::QueryTimeout {
                 msg: format!(
-                    "Query running for {}ms, which exceeds the timeout of {}ms. To disable timeouts, set the POLAR_TIMEOUT_MS environment variable to 0.",
+                    "Query running for {}ms, which exceeds the timeout of {}ms. To disable timeouts, set the query timeout in the Polar config or the POLAR_TIMEOUT_MS environment variable to 0.",
                     elapsed, self.query_timeout_ms
                 ),
             }
@@ -899,6 +1235,7 @@ impl PolarVirtualMachine {
             self.print("⇒ backtrack");
         }
         self.log("BACKTRACK", &[]);
+        self.budget.spend_backtrack()?;
 
         loop {
             match self.choices.pop() {
@@ -941,6 +1278,82 @@ impl PolarVirtualMachine {
         Ok(())
     }
 
+    /// When tracing, record the trace of the goal that is about to fail,
+    /// from the root of the query down to the failing goal.
+    fn trace_failure(&mut self) {
+        if !self.tracing {
+            return;
+        }
+        let mut level = self.trace.clone();
+        for parent in self.trace_stack.iter().rev() {
+            let mut parent = parent.as_ref().clone();
+            if let Some(mut last) = parent.pop() {
+                Rc::make_mut(&mut last).children.append(&mut level);
+                parent.push(last);
+            }
+            level = parent;
+        }
+        if let Some(root) = level.first() {
+            let mut explained = self.explain_trace(root);
+            self.failures.append(&mut explained);
+        }
+    }
+
+    /// Explained traces of the goals that failed so far, when tracing.
+    pub fn failures(&self) -> &[ExplainNode] {
+        &self.failures
+    }
+
+    /// Explain a trace using the current bindings. Conjunctions are
+    /// flattened, so this may return more than one node.
+    pub fn explain_trace(&self, trace: &Trace) -> Vec<ExplainNode> {
+        let mut children: Vec<_> = trace
+            .children
+            .iter()
+            .flat_map(|c| self.explain_trace(c))
+            .collect();
+        // Operations are queried again once their arguments are evaluated; keep one.
+        children.dedup_by(|a, b| a.children.is_empty() && a == b);
+        match &trace.node {
+            Node::Term(term)
+                if matches!(
+                    term.value(),
+                    Value::Expression(Operation {
+                        operator: Operator::And,
+                        ..
+                    })
+                ) =>
+            {
+                children
+            }
+            Node::Term(term) => vec![ExplainNode {
+                kind: ExplainKind::Goal,
+                source: self.term_source(term, false),
+                bound: Some(self.deref_goal(term).to_polar()),
+                children,
+            }],
+            Node::Rule(rule) => vec![ExplainNode {
+                kind: ExplainKind::Rule,
+                source: self.rule_source(rule),
+                bound: None,
+                children,
+            }],
+        }
+    }
+
+    /// Dereference variables in a goal, including inside operations.
+    fn deref_goal(&self, term: &Term) -> Term {
+        match term.value() {
+            Value::Expression(Operation { operator, args }) => {
+                term.clone_with_value(Value::Expression(Operation {
+                    operator: *operator,
+                    args: args.iter().map(|arg| self.deref_goal(arg)).collect(),
+                }))
+            }
+            _ => self.deref(term),
+        }
+    }
+
     /// Commit to the current choice.
     fn cut(&mut self, index: usize) {
         self.choices.truncate(index);
@@ -1075,7 +1488,7 @@ impl PolarVirtualMachine {
                     let lookup = Goal::LookupExternal {
                         instance: left.clone(),
                         call_id,
-                        field: right_value.clone_with_value(Value::String(field.0.clone())),
+                        field: right_value.clone_with_value(Value::String(field.to_string())),
                     };
                     let isa = Goal::Isa {
                         left: Term::from(answer),
@@ -1145,7 +1558,7 @@ impl PolarVirtualMachine {
                 // Produce a constraint like left.field = value
                 let to_unify = |(field, value): (&Symbol, &Term)| -> Term {
                     let value = self.deref(value);
-                    let field = right.clone_with_value(value!(field.0.as_ref()));
+                    let field = right.clone_with_value(value!(&*field.as_str()));
                     let left = left.clone_with_value(value!(op!(Dot, left.clone(), field)));
                     term!(op!(Unify, left, value))
                 };
@@ -1212,7 +1625,7 @@ impl PolarVirtualMachine {
                 // Construct field constraints.
                 let field_constraints = fields.fields.iter().rev().map(|(f, v)| {
                     let v = self.deref(v);
-                    let field = right.clone_with_value(value!(f.0.as_ref()));
+                    let field = right.clone_with_value(value!(&*f.as_str()));
                     let left = left.clone_with_value(value!(op!(Dot, left.clone(), field)));
                     op!(Unify, left, v)
                 });
@@ -1249,7 +1662,7 @@ impl PolarVirtualMachine {
             let members = kb.get_union_members(union).iter();
             members
                 .map(|member| {
-                    let tag = member.value().as_symbol().unwrap().0.as_str();
+                    let tag = member.value().as_symbol().unwrap().as_str();
                     member.clone_with_value(value!(pattern!(instance!(tag))))
                 })
                 .map(|pattern| {
@@ -1274,7 +1687,7 @@ impl PolarVirtualMachine {
                     // if `field` is bound, unification will only succeed for the matching key
                     // if `field` is unbound, unification will succeed for all keys
                     goals.push(Goal::Unify {
-                        left: field.clone_with_value(Value::String(k.clone().0)),
+                        left: field.clone_with_value(Value::String(k.to_string())),
                         right: field.clone(),
                     });
                     // attempt to unify dict value with result
@@ -1287,7 +1700,7 @@ impl PolarVirtualMachine {
                 self.choose(alternatives)
             }
             Value::String(field) => {
-                if let Some(retrieved) = dict.fields.get(&Symbol(field.clone())) {
+                if let Some(retrieved) = dict.fields.get(&Symbol::new(field)) {
                     self.push_goal(Goal::Unify {
                         left: retrieved.clone(),
                         right: value.clone(),
@@ -1327,7 +1740,7 @@ impl PolarVirtualMachine {
                         .collect()
                 }),
             ),
-            Value::String(field) => (Symbol(field.clone()), None, None),
+            Value::String(field) => (Symbol::new(field), None, None),
             v => {
                 return Err(self.type_error(
                     field,
@@ -1503,6 +1916,24 @@ impl PolarVirtualMachine {
                 predicate.to_polar()
             ));
         }
+        let closure = if self.fixpoints {
+            self.recursive_relation(&predicate)
+        } else {
+            None
+        };
+        if let Some((i, parent, field)) = closure {
+            let arity = predicate.args.len();
+            if !recursion_only_follows(&self.kb(), &predicate.name, arity, i, &field) {
+                return RuntimeError::unsupported(
+                    format!(
+                        "recursive rule `{}` has conditions besides following `{}`, which data filters can't express",
+                        predicate.name, field
+                    ),
+                    Term::from(Value::Call(predicate)),
+                );
+            }
+            return self.query_for_closure(predicate, i, parent, field);
+        }
         let goals = match self.kb.read().unwrap().get_generic_rule(&predicate.name) {
             None => vec![Goal::Backtrack],
             Some(generic_rule) => {
@@ -1514,26 +1945,212 @@ impl PolarVirtualMachine {
                 }
 
                 // Pre-filter rules.
-                let args = predicate.args.iter().map(|t| self.deref(t)).collect();
+                let args: TermList = predicate.args.iter().map(|t| self.deref(t)).collect();
                 let pre_filter = generic_rule.get_applicable_rules(&args);
 
                 self.polar_log_mute = true;
 
                 // Filter rules by applicability.
-                vec![
-                    Goal::TraceStackPush,
-                    Goal::FilterRules {
-                        applicable_rules: vec![],
-                        unfiltered_rules: pre_filter,
-                        args: predicate.args,
-                    },
-                    Goal::TraceStackPop,
-                ]
+                let filter_rules = Goal::FilterRules {
+                    applicable_rules: vec![],
+                    unfiltered_rules: pre_filter,
+                    args: predicate.args.clone(),
+                };
+
+                // Calls to tabled rules with constrained arguments aren't tabled.
+                let aliases = if self.kb().is_tabled(&predicate.name) {
+                    self.aliases(&args)
+                } else {
+                    None
+                };
+                match aliases {
+                    Some(aliases) => self.tabled_goals(predicate, args, &aliases, filter_rules),
+                    None => vec![Goal::TraceStackPush, filter_rules, Goal::TraceStackPop],
+                }
             }
         };
         self.append_goals(goals)
     }
 
+    /// If `predicate` is a call `f(.., x.field, ..)` made while evaluating a call
+    /// `f(.., x, ..)` with the same other arguments, where `x` is partially
+    /// evaluated, the position of the argument, `x`, and the field.
+    fn recursive_relation(&self, predicate: &Call) -> Option<(usize, Term, String)> {
+        let args: TermList = predicate.args.iter().map(|arg| self.deref(arg)).collect();
+        if !args
+            .iter()
+            .any(|arg| matches!(arg.value(), Value::Variable(_)))
+        {
+            return None;
+        }
+        self.queries.iter().rev().find_map(|query| {
+            let enclosing: TermList = match query.value() {
+                Value::Call(call)
+                    if call.name == predicate.name && call.args.len() == args.len() =>
+                {
+                    call.args.iter().map(|arg| self.deref(arg)).collect()
+                }
+                _ => return None,
+            };
+            let differ: Vec<usize> = (0..args.len())
+                .filter(|&i| args[i] != enclosing[i])
+                .collect();
+            match differ[..] {
+                [i] => self
+                    .relation_field(&enclosing[i], &args[i])
+                    .map(|field| (i, enclosing[i].clone(), field)),
+                _ => None,
+            }
+        })
+    }
+
+    /// The field `f` if `child` is constrained to be `parent.f`.
+    fn relation_field(&self, parent: &Term, child: &Term) -> Option<String> {
+        let (parent, child) = (
+            parent.value().as_symbol().ok()?,
+            child.value().as_symbol().ok()?,
+        );
+        let constraints = self.binding_manager.get_constraints(child);
+        let (parents, children) = (
+            unified_variables(&constraints, parent),
+            unified_variables(&constraints, child),
+        );
+        for constraint in constraints.constraints() {
+            let (left, right) = match (constraint.operator, &constraint.args[..]) {
+                (Operator::Unify, [left, right]) => (left, right),
+                _ => continue,
+            };
+            for (value, dot) in [(left, right), (right, left)] {
+                if let (
+                    Value::Variable(value),
+                    Ok(Operation {
+                        operator: Operator::Dot,
+                        args,
+                    }),
+                ) = (value.value(), dot.value().as_expression())
+                {
+                    match (args[0].value(), args[1].value()) {
+                        (Value::Variable(object), Value::String(field))
+                            if children.contains(value) && parents.contains(object) =>
+                        {
+                            return Some(field.clone())
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+        None
+    }
+
+    /// `f(.., x.field, ..)` inside `f(.., x, ..)` holds if `f` holds for one of `x.field`,
+    /// `x.field.field`, ..., i.e., if the rest of `f` holds for a `y in x.field+`, the
+    /// transitive closure of the relation. Evaluate that instead of unrolling the
+    /// recursion, and don't recurse again from `y`.
+    fn query_for_closure(
+        &mut self,
+        mut predicate: Call,
+        i: usize,
+        parent: Term,
+        field: String,
+    ) -> Result<()> {
+        let closure = closure_field(&field);
+        let parent_var = parent.value().as_symbol()?;
+        let constraints = self.binding_manager.get_constraints(parent_var);
+        let parents = unified_variables(&constraints, parent_var);
+        let is_closure = constraints.constraints().into_iter().any(|constraint| {
+            match (constraint.operator, &constraint.args[..]) {
+                (Operator::In, [var, dot]) => {
+                    matches!(var.value(), Value::Variable(var) if parents.contains(var))
+                        && matches!(dot.value().as_expression(), Ok(Operation { operator: Operator::Dot, args })
+                            if matches!(args[1].value(), Value::String(field) if field == &closure))
+                }
+                _ => false,
+            }
+        });
+        if is_closure {
+            return self.push_goal(Goal::Backtrack);
+        }
+
+        let ancestor = Term::from(self.kb().gensym("ancestor"));
+        let dot = op!(Dot, parent, Term::from(Value::String(closure)));
+        predicate.args[i] = ancestor.clone();
+        self.append_goals(vec![
+            Goal::AddConstraint {
+                term: Term::from(op!(In, ancestor, Term::from(dot))),
+            },
+            Goal::Query {
+                term: Term::from(Value::Call(predicate)),
+            },
+        ])
+    }
+
+    /// A representative for each set of aliased variables in (dereferenced) `terms`,
+    /// or `None` if any variable has constraints on it.
+    pub(crate) fn aliases(&self, terms: &[Term]) -> Option<Aliases> {
+        let mut vars = HashSet::new();
+        for term in terms {
+            term.variables(&mut vars);
+        }
+        let mut aliases = Aliases::new();
+        for var in vars {
+            let representative = self.binding_manager.aliases(&var)?.into_iter().min()?;
+            aliases.insert(var, representative);
+        }
+        Some(aliases)
+    }
+
+    /// Goals for a call to a tabled rule: evaluate its variant with `filter_rules` in a
+    /// sub-VM (unless the table already has its answers), then choose an answer.
+    fn tabled_goals(
+        &self,
+        predicate: Call,
+        args: TermList,
+        aliases: &Aliases,
+        filter_rules: Goal,
+    ) -> Goals {
+        let key = variant_key(&predicate.name, &args, aliases);
+        let table_answers = Goal::TableAnswers {
+            key: key.clone(),
+            args: predicate.args.clone(),
+        };
+        if self.tables.borrow_mut().consume(&key) {
+            return vec![table_answers];
+        }
+        let evaluator = TableEvaluator::new(
+            self,
+            vec![filter_rules],
+            key,
+            Term::from(Value::Call(predicate)),
+            args,
+            self.tables.clone(),
+        );
+        vec![
+            Goal::Run {
+                runnable: Box::new(evaluator),
+            },
+            table_answers,
+        ]
+    }
+
+    /// Choose one of the answers in the table for `key`, unifying it with `args`.
+    fn table_answers(&mut self, key: &str, args: &[Term]) -> Result<()> {
+        let answers = self.tables.borrow().answers(key);
+        let args = Term::from(Value::List(args.to_vec()));
+        let alternatives = answers
+            .into_iter()
+            .map(|answer| {
+                let kb = &*self.kb.read().unwrap();
+                let answer = Renamer::new(kb).fold_term(Term::from(Value::List(answer)));
+                vec![Goal::Unify {
+                    left: args.clone(),
+                    right: answer,
+                }]
+            })
+            .collect::<Vec<_>>();
+        self.choose(alternatives)
+    }
+
     fn query_for_operation(&mut self, term: &Term) -> Result<QueryEvent> {
         let operation = term.value().as_expression().unwrap();
         let mut args = operation.args.clone();
@@ -1714,6 +2331,19 @@ impl PolarVirtualMachine {
                     }
                 }
 
+                // If a partial was constrained after a choice was made, the alternatives
+                // the cut would discard may succeed for values the constraint excludes,
+                // so the cut can't be expressed as constraints.
+                if self.choices[choice_index..].iter().any(|choice| {
+                    !choice.alternatives.is_empty()
+                        && self.binding_manager.constrained_after(&choice.bsp)
+                }) {
+                    return Err(RuntimeError::Unsupported {
+                        msg: "cannot use cut after constraining a partial; the rules it would skip may match values the constraint excludes".to_owned(),
+                        term: term.clone(),
+                    });
+                }
+
                 self.push_goal(Goal::Cut { choice_index })?;
             }
             Operator::Isa => {
@@ -1750,6 +2380,41 @@ impl PolarVirtualMachine {
                     term: double_negation,
                 })?;
             }
+            Operator::Count | Operator::Collect | Operator::Min | Operator::Max | Operator::Sum => {
+                // Run the goal to exhaustion in a sub-VM and unify the aggregate
+                // of the template's values with the result.
+                if args.len() != 3 {
+                    return wrong_arity();
+                }
+                let result = args.pop().unwrap();
+                let goal = args.pop().unwrap();
+                let template = args.pop().unwrap();
+                let ranges_over = [self.deref(&template), self.deref(&goal)];
+                if self.aliases(&ranges_over).is_none() {
+                    return RuntimeError::unsupported(
+                        format!(
+                            "{} over partially evaluated variables is not supported",
+                            operation.operator.to_polar()
+                        ),
+                        term.clone(),
+                    );
+                }
+                let aggregate = Rc::new(RefCell::new(None));
+                let aggregator = Box::new(Aggregator::new(
+                    self,
+                    vec![Goal::Query { term: goal }],
+                    term.clone(),
+                    operation.operator,
+                    template,
+                    aggregate.clone(),
+                ));
+                self.append_goals(vec![
+                    Goal::Run {
+                        runnable: aggregator,
+                    },
+                    Goal::UnifyAggregate { result, aggregate },
+                ])?;
+            }
         }
         Ok(QueryEvent::None)
     }
@@ -2061,6 +2726,18 @@ impl PolarVirtualMachine {
         let item_is_ground = item.is_ground();
 
         match iterable.value() {
+            // When inverting, constrain a partial to be in a ground list rather than
+            // trying each element: each would be inverted separately, and `not` or
+            // `forall` over a partial would lose track of which one it was.
+            // `build_filter_plan` turns the result back into a conjunction of `!=`s.
+            Value::List(_)
+                if self.inverting
+                    && iterable.is_ground()
+                    && matches!(item.value(), Value::Variable(v)
+                        if self.variable_state(v) == VariableState::Partial) =>
+            {
+                self.add_constraint(term)?;
+            }
             // Unify item with each element of the list, skipping non-matching ground terms.
             Value::List(terms) => self.choose(
                 terms
@@ -2086,7 +2763,7 @@ impl PolarVirtualMachine {
                     .iter()
                     .map(|(k, v)| {
                         iterable.clone_with_value(Value::List(vec![
-                            v.clone_with_value(Value::String(k.0.clone())),
+                            v.clone_with_value(Value::String(k.to_string())),
                             v.clone(),
                         ]))
                     })
@@ -2843,9 +3520,20 @@ impl Runnable for PolarVirtualMachine {
         }
 
         while let Some(goal) = self.goals.pop() {
+            self.budget.spend_goal()?;
             match self.next(goal.clone())? {
                 QueryEvent::None => (),
                 event => {
+                    // Everything but results and debugger events asks the host for something.
+                    if !matches!(
+                        event,
+                        QueryEvent::Done { .. }
+                            | QueryEvent::Result { .. }
+                            | QueryEvent::Debug { .. }
+                            | QueryEvent::Run { .. }
+                    ) {
+                        self.budget.spend_external_call()?;
+                    }
                     self.external_error = None;
                     return Ok(event);
                 }
@@ -2887,7 +3575,8 @@ impl Runnable for PolarVirtualMachine {
 
                 Err(RuntimeError::UnhandledPartial { term, ref var }) => {
                     // use the debugger to get the nicest possible version of this binding
-                    let Binding(original_var_name, simplified) = get_binding_for_var(&var.0, self);
+                    let Binding(original_var_name, simplified) =
+                        get_binding_for_var(&var.to_string(), self);
 
                     // TODO(gj): `t` is a partial constructed in the VM, so we don't have any
                     // source context for it. We make a best effort to track down some relevant
@@ -3600,10 +4289,10 @@ mod tests {
                 operator: Operator::And,
                 args: vec![
                     term!(1),
-                    Term::new_from_test(Value::Variable(Symbol("x".to_string()))),
-                    Term::new_from_test(Value::Variable(Symbol("x".to_string()))),
+                    Term::new_from_test(Value::Variable(Symbol::new("x"))),
+                    Term::new_from_test(Value::Variable(Symbol::new("x"))),
                     Term::new_from_test(Value::List(vec![Term::new_from_test(Value::Variable(
-                        Symbol("y".to_string()),
+                        Symbol::new("y"),
                     ))])),
                 ],
             })),
@@ -3613,14 +4302,14 @@ mod tests {
         let renamed_terms = unwrap_and(&renamed_rule.body);
         assert_eq!(renamed_terms[1].value(), renamed_terms[2].value());
         let x_value = match &renamed_terms[1].value() {
-            Value::Variable(sym) => Some(sym.0.clone()),
+            Value::Variable(sym) => Some(sym.to_string()),
             _ => None,
         };
         assert_eq!(x_value.unwrap(), "_x_1");
 
         let y_value = match &renamed_terms[3].value() {
             Value::List(terms) => match &terms[0].value() {
-                Value::Variable(sym) => Some(sym.0.clone()),
+                Value::Variable(sym) => Some(sym.to_string()),
                 _ => None,
             },
             _ => None,
@@ -3661,7 +4350,7 @@ mod tests {
                 } => {
                     external_isas.push(class_tag.clone());
                     // Return `true` if the specified `class_tag` is `"a"`.
-                    vm.external_question_result(call_id, class_tag.0 == "a")
+                    vm.external_question_result(call_id, class_tag.as_str() == "a")
                         .unwrap()
                 }
                 QueryEvent::ExternalOp { .. }
@@ -3813,14 +4502,20 @@ mod tests {
         let vm = PolarVirtualMachine::default();
         assert!(vm.query_timeout_ms == DEFAULT_TIMEOUT_MS);
 
-        std::env::set_var("POLAR_TIMEOUT_MS", "0");
-        let vm = PolarVirtualMachine::default();
-        std::env::remove_var("POLAR_TIMEOUT_MS");
+        let vm_with_config = |config: PolarConfig| {
+            PolarVirtualMachine::new(
+                Arc::new(RwLock::new(KnowledgeBase::default())),
+                false,
+                vec![],
+                MessageQueue::new(),
+                &config,
+            )
+        };
+
+        let vm = vm_with_config(PolarConfig::new().query_timeout_ms(0));
         assert!(vm.is_query_timeout_disabled());
 
-        std::env::set_var("POLAR_TIMEOUT_MS", "500");
-        let mut vm = PolarVirtualMachine::default();
-        std::env::remove_var("POLAR_TIMEOUT_MS");
+        let mut vm = vm_with_config(PolarConfig::new().query_timeout_ms(500));
         // Turn this off so we don't hit it.
         vm.set_stack_limit(std::usize::MAX);
 
@@ -106,7 +106,7 @@ const MISSING_HAS_PERMISSION_RULE_MSG: &str = indoc! {"
 
 fn common_specializer_misspellings(term: &Term) -> Option<&str> {
     if let Value::Pattern(Pattern::Instance(InstanceLiteral { tag, .. })) = term.value() {
-        let misspelled_type = match tag.0.as_ref() {
+        let misspelled_type = match &*tag.as_str() {
             "integer" => "Integer",
             "int" => "Integer",
             "i32" => "Integer",
@@ -9,8 +9,8 @@ use std::collections::{BTreeMap, HashMap};
 
 use mock_externals::MockExternal;
 use polar_core::{
-    error::*, events::*, messages::*, polar::Polar, query::Query, sym, term, terms::*, traces::*,
-    value, values,
+    config::PolarConfig, error::*, events::*, messages::*, polar::Polar, query::Query, sym, term,
+    terms::*, traces::*, value, values,
 };
 
 fn polar() -> Polar {
@@ -326,7 +326,7 @@ fn vars(p: &Polar, query_str: &str, vars: &[&str]) -> Vec<Vec<Value>> {
         .iter()
         .map(|bindings| {
             vars.iter()
-                .map(|&var| bindings.0.get(&Symbol(var.to_string())).unwrap().clone())
+                .map(|&var| bindings.0.get(&Symbol::new(var)).unwrap().clone())
                 .collect()
         })
         .collect()
@@ -449,6 +449,113 @@ fn test_trace() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_explain_rule_failures() -> TestResult {
+    let p = polar();
+    p.load_str(
+        r#"f(x) if g(x) and x = 1;
+           f(x) if x = 2;
+           g(_);"#,
+    )?;
+    let mut q = p.new_query("f(3)", true)?;
+    while !matches!(q.next_event()?, QueryEvent::Done { .. }) {}
+    let failures = q.rule_failures();
+    assert_eq!(failures.len(), 2);
+
+    assert_eq!(failures[0].rule, "f(x) if g(x) and x = 1;");
+    let goal = failures[0].goal.as_ref().unwrap();
+    assert_eq!(goal.source, "x = 1");
+    assert_eq!(goal.bound.as_deref(), Some("3 = 1"));
+    assert_eq!(failures[0].trace.children.len(), 2);
+
+    assert_eq!(failures[1].rule, "f(x) if x = 2;");
+    assert_eq!(failures[1].goal.as_ref().unwrap().source, "x = 2");
+    Ok(())
+}
+
+#[test]
+fn test_explain_result() -> TestResult {
+    let p = polar();
+    p.load_str("f(x) if g(x) and x = 1; g(1);")?;
+    let mut q = p.new_query("f(1)", true)?;
+    let trace = match q.next_event()? {
+        QueryEvent::Result { trace, .. } => trace.unwrap(),
+        event => panic!("unexpected event {:?}", event),
+    };
+    let proof = q.explain_result(&trace.trace).unwrap();
+    assert_eq!(proof.kind, ExplainKind::Goal);
+    assert_eq!(proof.source, "f(1)");
+    let rule = &proof.children[0];
+    assert_eq!(rule.kind, ExplainKind::Rule);
+    let goals: Vec<_> = rule
+        .children
+        .iter()
+        .map(|goal| goal.bound.clone().unwrap())
+        .collect();
+    assert_eq!(goals, vec!["g(1)", "1 = 1"]);
+    assert_eq!(rule.children[0].children[0].source, "g(1);");
+    Ok(())
+}
+
+#[test]
+fn test_cancellation() -> TestResult {
+    let p = polar();
+    p.load_str("f(1); f(2);")?;
+    let mut q = p.new_query("f(x)", false)?;
+    let token = q.cancellation_token();
+    assert!(matches!(q.next_event()?, QueryEvent::Result { .. }));
+
+    token.cancel();
+    assert!(token.is_cancelled());
+    assert!(matches!(
+        q.next_event().unwrap_err().kind,
+        ErrorKind::Runtime(RuntimeError::Cancelled)
+    ));
+    Ok(())
+}
+
+#[test]
+fn test_query_budgets() -> TestResult {
+    let budgeted = |config: PolarConfig| {
+        let p = Polar::with_config(config.ignore_no_allow_warning(true));
+        p.load_str("f(1); f(2); f(3); loop(x) if loop(x);").unwrap();
+        p
+    };
+
+    let p = budgeted(PolarConfig::new().max_goals(Some(100)));
+    qruntime!(
+        &p,
+        "loop(1)",
+        RuntimeError::BudgetExhausted { budget, limit: 100 },
+        budget == Budget::Goals
+    );
+
+    // Budgets count the VM's own backtracking (e.g., while selecting rules) as well as
+    // failed unifications, so reaching `x = 3` takes four backtracks.
+    let p = budgeted(PolarConfig::new().max_backtracks(Some(3)));
+    qruntime!(
+        &p,
+        "f(x) and x = 3",
+        RuntimeError::BudgetExhausted { budget, limit: 3 },
+        budget == Budget::Backtracks
+    );
+    let p = budgeted(PolarConfig::new().max_backtracks(Some(4)));
+    let mut q = p.new_query("f(x) and x = 3", false)?;
+    assert!(matches!(q.next_event()?, QueryEvent::Result { .. }));
+
+    let p = budgeted(PolarConfig::new().max_external_calls(Some(1)));
+    let mut q = p.new_query("new Foo() = new Bar()", false)?;
+    assert!(matches!(q.next_event()?, QueryEvent::MakeExternal { .. }));
+    assert!(matches!(
+        q.next_event().unwrap_err().kind,
+        ErrorKind::Runtime(RuntimeError::BudgetExhausted {
+            budget: Budget::ExternalCalls,
+            limit: 1
+        })
+    ));
+    Ok(())
+}
+
 #[test]
 fn test_nested_rule() -> TestResult {
     let p = polar();
@@ -607,7 +714,8 @@ fn test_ait_kaci_34() -> TestResult {
 fn test_constants() -> TestResult {
     let p = polar();
     {
-        let mut kb = p.kb.write().unwrap();
+        let kb = p.kb();
+        let mut kb = kb.write().unwrap();
         kb.register_constant(sym!("one"), term!(1))?;
         kb.register_constant(sym!("two"), term!(2))?;
         kb.register_constant(sym!("three"), term!(3))?;
@@ -1869,6 +1977,110 @@ fn test_circular_data() -> TestResult {
     Ok(())
 }
 
+#[test]
+fn test_tabled_rules() -> TestResult {
+    let edges = "edge(1, 2); edge(2, 3); edge(3, 1); edge(3, 4);";
+    let untabled = Polar::with_config(
+        PolarConfig::new()
+            .stack_limit(1_000)
+            .ignore_no_allow_warning(true),
+    );
+    untabled.load_str(&format!(
+        "{} path(x, y) if edge(x, y); path(x, y) if path(x, z) and edge(z, y);",
+        edges
+    ))?;
+    qruntime!(&untabled, "path(4, 1)", RuntimeError::StackOverflow { .. });
+
+    // Left recursion over a cyclic graph terminates with every answer.
+    let p = polar();
+    p.load_str(&format!(
+        "{} tabled type path(x, y);
+         path(x, y) if edge(x, y);
+         path(x, y) if path(x, z) and edge(z, y);",
+        edges
+    ))?;
+    let mut reachable = var(&p, "path(1, y)", "y");
+    reachable.sort_by_key(|v| v.to_polar());
+    assert_eq!(reachable, values![1, 2, 3, 4]);
+    qeval(&p, "path(3, 3)");
+    qnull(&p, "path(4, 1)");
+    assert_eq!(vars(&p, "path(x, y)", &["x", "y"]).len(), 12);
+
+    // Mutual recursion, declared on a rule rather than a rule type.
+    let p = polar();
+    p.load_str(&format!(
+        "{} tabled even(x, y) if x = y;
+         even(x, y) if odd(x, z) and edge(z, y);
+         odd(x, y) if even(x, z) and edge(z, y);",
+        edges
+    ))?;
+    let mut even = var(&p, "even(1, y)", "y");
+    even.sort_by_key(|v| v.to_polar());
+    assert_eq!(even, values![1, 2, 3, 4]);
+
+    let p = polar();
+    let err = p.load_str("memoized type f(x);").unwrap_err();
+    assert!(
+        err.to_string()
+            .contains("Expected 'tabled' but found 'memoized'."),
+        "{}",
+        err
+    );
+    Ok(())
+}
+
+#[test]
+fn test_aggregates() -> TestResult {
+    let p = polar();
+    p.load_str(
+        "f(1); f(2); f(2); f(3);
+         g(x, y) if f(x) and f(y) and x < y;",
+    )?;
+    assert_eq!(var(&p, "count(x, f(x), n)", "n"), values![3]);
+    qvar(&p, "collect(x, f(x), l)", "l", vec![value!([1, 2, 2, 3])]);
+    assert_eq!(var(&p, "min(x, f(x), m)", "m"), values![1]);
+    assert_eq!(var(&p, "max(x, f(x), m)", "m"), values![3]);
+    // Only `count` skips repeated values; the others take every solution.
+    assert_eq!(var(&p, "sum(x, f(x), s)", "s"), values![8]);
+    assert_eq!(var(&p, "sum(x, f(x) and x > 1.5, s)", "s"), values![7]);
+    assert_eq!(var(&p, "count([x, y], g(x, y), n)", "n"), values![3]);
+    qvar(&p, "collect(y, g(1, y), l)", "l", vec![value!([2, 2, 3])]);
+    qeval(&p, "count(x, f(x), 3)");
+    qnull(&p, "count(x, f(x), 4)");
+
+    // Bindings from outside the aggregate are used.
+    assert_eq!(var(&p, "y = 3 and count(x, g(x, y), n)", "n"), values![2]);
+
+    // Empty aggregates.
+    assert_eq!(var(&p, "count(x, f(x) and x > 3, n)", "n"), values![0]);
+    qvar(&p, "collect(x, f(x) and x > 3, l)", "l", vec![value!([])]);
+    assert_eq!(var(&p, "sum(x, f(x) and x > 3, s)", "s"), values![0]);
+    qnull(&p, "min(x, f(x) and x > 3, _)");
+    qnull(&p, "max(x, f(x) and x > 3, _)");
+
+    qruntime!(
+        &p,
+        "sum(x, x in [1, \"a\"], _)",
+        RuntimeError::Unsupported { .. }
+    );
+    qruntime!(
+        &p,
+        "max(x, x in [1, {}], _)",
+        RuntimeError::Unsupported { .. }
+    );
+
+    // The names aren't reserved: rules and variables can still use them.
+    let p = polar();
+    p.load_str(
+        "f(count) if count > 1;
+         sum(a, b, a + b);",
+    )?;
+    qeval(&p, "f(2)");
+    assert_eq!(var(&p, "sum(1, 2, s)", "s"), values![3]);
+    assert_eq!(var(&p, "sum(x, sum(1, 2, x), s)", "s"), values![3]);
+    Ok(())
+}
+
 #[test]
 fn test_data_filtering_dict_specializers() -> TestResult {
     let pol_a = "allow(x, \"read\", _y: { x: x });";
@@ -1926,7 +2138,7 @@ fn test_in_op() -> TestResult {
     assert_eq!(results.len(), 3);
     assert!(results[0].0.is_empty());
     assert_eq!(
-        results[1].0.get(&Symbol("x".to_string())).unwrap().clone(),
+        results[1].0.get(&Symbol::new("x")).unwrap().clone(),
         value!(1)
     );
     assert!(results[2].0.is_empty());
@@ -374,8 +374,10 @@ impl PolarLanguageServer {
                         }
                     }
                 }
-                Line::RuleType(_) => event.policy_stats.rule_types += 1,
-                Line::Rule(_) => {
+                Line::RuleType(_) | Line::TabledRuleType { .. } => {
+                    event.policy_stats.rule_types += 1
+                }
+                Line::Rule(_) | Line::TabledRule { .. } => {
                     event.policy_stats.longhand_rules += 1;
                     event.policy_stats.total_rules += 1;
                 }
@@ -1,4 +1,4 @@
-use polar_core::{polar, sources::Source, terms::Symbol};
+use polar_core::{config::PolarConfig, polar, sources::Source, terms::Symbol};
 use wasm_bindgen::prelude::*;
 
 use crate::errors::{serialization_error, Error};
@@ -16,6 +16,13 @@ impl Polar {
         Self(polar::Polar::new())
     }
 
+    #[wasm_bindgen(js_class = Polar, js_name = withConfig)]
+    pub fn wasm_with_config(config: JsValue) -> JsResult<Polar> {
+        console_error_panic_hook::set_once();
+        let config: PolarConfig = serde_wasm_bindgen::from_value(config)?;
+        Ok(Self(polar::Polar::with_config(config)))
+    }
+
     #[wasm_bindgen(js_class = Polar, js_name = load)]
     pub fn wasm_load(&self, sources: JsValue) -> JsResult<()> {
         let sources: Vec<Source> = serde_wasm_bindgen::from_value(sources)?;
@@ -59,6 +66,19 @@ impl Polar {
         Ok(Query::from(self.0.new_query_from_term(term, false)))
     }
 
+    #[wasm_bindgen(js_class = Polar, js_name = newQueryFromTermWithConfig)]
+    pub fn wasm_new_query_from_term_with_config(
+        &self,
+        term: JsValue,
+        config: JsValue,
+    ) -> JsResult<Query> {
+        let term = serde_wasm_bindgen::from_value(term)?;
+        let config: PolarConfig = serde_wasm_bindgen::from_value(config)?;
+        Ok(Query::from(
+            self.0.new_query_from_term_with_config(term, false, &config),
+        ))
+    }
+
     #[wasm_bindgen(js_class = Polar, js_name = newId)]
     pub fn wasm_get_external_id(&self) -> f64 {
         self.0.get_external_id() as f64
@@ -98,6 +118,26 @@ impl Polar {
             })
     }
 
+    #[wasm_bindgen(js_class = Polar, js_name = buildDataFilterExplained)]
+    pub fn wasm_build_data_filter_explained(
+        &self,
+        types: JsValue,
+        partial_results: JsValue,
+        variable: &str,
+        class_tag: &str,
+    ) -> JsResult<JsValue> {
+        let types = serde_wasm_bindgen::from_value(types)?;
+        let partial_results = serde_wasm_bindgen::from_value(partial_results)?;
+        self.0
+            .build_data_filter_explained(types, partial_results, variable, class_tag)
+            .map_err(Error::from)
+            .map_err(Error::into)
+            .and_then(|explained| {
+                serde_wasm_bindgen::to_value(&explained)
+                    .map_err(|e| serialization_error(e.to_string()))
+            })
+    }
+
     // TODO(@gkaemmer): this is a hack and should not be used for similar cases.
     // Ideally, we'd have a single "configuration" entrypoint for both the Polar
     // and Query types.
@@ -119,7 +119,7 @@ fn new_query_from_str_errors() {
 fn new_query_from_term_succeeds() {
     let polar = polar_wasm_api::Polar::wasm_new();
     let term = Term::from(Value::Call(Call {
-        name: Symbol("x".into()),
+        name: Symbol::new("x"),
         args: vec![],
         kwargs: None,
     }));